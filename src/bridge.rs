@@ -0,0 +1,585 @@
+//! A local HTTP bridge, so that other programs on this machine can share the app's connection to
+//! the radio. `GET /events` is a stream (Server-Sent Events) of JSON events, such as the messages
+//! received, and `POST /send-text`, `/send-position` and `/send-info` send to the radio via the app.
+//! It only listens on localhost, and is enabled by setting `bridge_port` in the config file.
+//! Requests must be addressed to localhost too, so a web page cannot reach it via a DNS name that
+//! it has pointed at this machine (DNS rebinding).
+use crate::bridge::BridgeEvent::{Error, Ready, Request};
+use crate::channel_id::ChannelId;
+use crate::device_subscription::SubscriptionEvent;
+use crate::device_subscription::SubscriptionEvent::{
+    ConnectedEvent, ConnectionError, DevicePacket, DisconnectedEvent, ReconnectingEvent,
+};
+use crate::device_view::DeviceViewMessage;
+use crate::device_view::DeviceViewMessage::{
+    SendInfoMessage, SendPositionMessage, SendTextMessage,
+};
+use crate::received_message::{Names, ReceivedMessage};
+use futures::SinkExt;
+use iced::stream;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::{Sender, UnboundedSender, channel, unbounded_channel};
+use tokio_stream::Stream;
+
+/// The most we will read of a request from a client, headers and body
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// How long a client has to send its request, before we give up on it
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events from the bridge to the app
+#[derive(Debug, Clone)]
+pub enum BridgeEvent {
+    /// The bridge is listening, and the app should send it [SubscriptionEvent]s from the radio
+    Ready(UnboundedSender<SubscriptionEvent>),
+    /// A client asked to send something to the radio
+    Request(DeviceViewMessage),
+    Error(String, String),
+}
+
+/// The JSON events sent to clients on the `/events` stream
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+enum ClientEvent {
+    Connected { device: String },
+    Disconnected { device: String },
+    Reconnecting { device: String, attempt: u32 },
+    Message(ReceivedMessage),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SendTextRequest {
+    channel_id: ChannelId,
+    text: String,
+    #[serde(default)]
+    reply_to_id: Option<u32>,
+}
+
+/// A request to send our position or info to a channel or node
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChannelRequest {
+    channel_id: ChannelId,
+}
+
+/// An HTTP request from a client, with just the parts the bridge uses
+struct HttpRequest {
+    method: String,
+    path: String,
+    host: Option<String>,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Converts [SubscriptionEvent]s from the radio to JSON events for clients, and keeps track of
+/// whether we are connected to a radio, so requests can be refused when we are not
+#[derive(Default)]
+struct Hub {
+    names: Names,
+    connected: Arc<AtomicBool>,
+}
+
+impl Hub {
+    fn client_event(&mut self, event: SubscriptionEvent) -> Option<String> {
+        let client_event = match event {
+            ConnectedEvent(device_id) => {
+                self.connected.store(true, Ordering::SeqCst);
+                ClientEvent::Connected {
+                    device: device_id.to_string(),
+                }
+            }
+            DisconnectedEvent(device_id) | ConnectionError(device_id, _, _) => {
+                self.connected.store(false, Ordering::SeqCst);
+                ClientEvent::Disconnected {
+                    device: device_id.to_string(),
+                }
+            }
+            ReconnectingEvent(device_id, attempt) => {
+                self.connected.store(false, Ordering::SeqCst);
+                ClientEvent::Reconnecting {
+                    device: device_id.to_string(),
+                    attempt,
+                }
+            }
+            // Aliases are kept in the app's config, so clients get the names nodes give themselves
+            DevicePacket(packet) => {
+                ClientEvent::Message(self.names.received(*packet, &HashMap::new())?)
+            }
+            _ => return None,
+        };
+        serde_json::to_string(&client_event)
+            .inspect_err(|e| eprintln!("Could not convert event to JSON: {e}"))
+            .ok()
+    }
+}
+
+/// A stream of [BridgeEvent]s from a bridge listening on `port` on localhost
+pub fn serve(port: &u16) -> impl Stream<Item = BridgeEvent> + use<> {
+    let port = *port;
+    stream::channel(
+        100,
+        move |mut app_sender: futures_channel::mpsc::Sender<BridgeEvent>| async move {
+            let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    let _ = app_sender
+                        .send(Error(
+                            format!("Could not start the bridge on port {port}"),
+                            e.to_string(),
+                        ))
+                        .await;
+                    return;
+                }
+            };
+
+            let (event_sender, mut event_receiver) = unbounded_channel::<SubscriptionEvent>();
+            let _ = app_sender.send(Ready(event_sender)).await;
+
+            let mut hub = Hub::default();
+            let (json_sender, _) = broadcast::channel::<String>(100);
+            let (request_sender, mut request_receiver) = channel::<DeviceViewMessage>(10);
+
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => {
+                            tokio::spawn(handle_client(
+                                stream,
+                                json_sender.subscribe(),
+                                request_sender.clone(),
+                                hub.connected.clone(),
+                            ));
+                        }
+                        Err(e) => eprintln!("Bridge error: {e}"),
+                    },
+                    Some(event) = event_receiver.recv() => {
+                        if let Some(json) = hub.client_event(event) {
+                            // No clients listening is not an error
+                            let _ = json_sender.send(json);
+                        }
+                    }
+                    Some(request) = request_receiver.recv() => {
+                        let _ = app_sender.send(Request(request)).await;
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Read a request from a client and respond to it
+async fn handle_client(
+    stream: TcpStream,
+    events: broadcast::Receiver<String>,
+    requests: Sender<DeviceViewMessage>,
+    connected: Arc<AtomicBool>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_SIZE));
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut reader)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(e)) => return respond(&mut writer, "400 Bad Request", &e).await,
+        Err(_) => return respond(&mut writer, "408 Request Timeout", "Request timed out").await,
+    };
+
+    if !is_localhost(request.host.as_deref()) {
+        return respond(&mut writer, "403 Forbidden", "Host must be localhost").await;
+    }
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/events") => stream_events(&mut writer, events).await,
+        ("POST", _) => {
+            let (status, body) = match device_view_message(&request) {
+                Ok(_) if !connected.load(Ordering::SeqCst) => {
+                    ("503 Service Unavailable", "Not connected to a radio".into())
+                }
+                Ok(message) => match requests.send(message).await {
+                    Ok(()) => ("202 Accepted", "Accepted".into()),
+                    Err(_) => ("503 Service Unavailable", "The app has stopped".into()),
+                },
+                Err(error) => error,
+            };
+            respond(&mut writer, status, &body).await
+        }
+        ("GET", _) => respond(&mut writer, "404 Not Found", "Not found").await,
+        _ => respond(&mut writer, "405 Method Not Allowed", "Method not allowed").await,
+    }
+}
+
+/// Read the request line, headers and body of an HTTP request
+async fn read_request<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+) -> Result<HttpRequest, String> {
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err("Invalid request line".into());
+    };
+    let method = method.to_string();
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut host = None;
+    let mut content_type = None;
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if reader
+            .read_line(&mut line)
+            .await
+            .map_err(|e| e.to_string())?
+            == 0
+        {
+            return Err("Incomplete request headers".into());
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("host") {
+                host = Some(value.to_ascii_lowercase());
+            } else if name.eq_ignore_ascii_case("content-type") {
+                content_type = Some(value.to_ascii_lowercase());
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .parse::<usize>()
+                    .map_err(|_| "Invalid Content-Length")?;
+            }
+        }
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|_| "Incomplete request body")?;
+
+    Ok(HttpRequest {
+        method,
+        path,
+        host,
+        content_type,
+        body,
+    })
+}
+
+/// Return true if the Host header of a request, without any port, names this machine by its
+/// loopback address or as localhost
+fn is_localhost(host: Option<&str>) -> bool {
+    let Some(host) = host else {
+        return false;
+    };
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !host.ends_with(']') && port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+    matches!(name, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+/// Convert a POST request to the [DeviceViewMessage] the app uses to send it, or return the
+/// status and message to respond with if it is not valid.
+/// Requiring a JSON content type stops web pages posting to the bridge, as browsers will not
+/// send one to another origin without asking first (and the bridge does not answer that)
+fn device_view_message(request: &HttpRequest) -> Result<DeviceViewMessage, (&'static str, String)> {
+    if !matches!(
+        request.path.as_str(),
+        "/send-text" | "/send-position" | "/send-info"
+    ) {
+        return Err(("404 Not Found", "Not found".into()));
+    }
+    if !request
+        .content_type
+        .as_ref()
+        .is_some_and(|content_type| content_type.starts_with("application/json"))
+    {
+        return Err((
+            "415 Unsupported Media Type",
+            "Content-Type must be application/json".into(),
+        ));
+    }
+
+    match request.path.as_str() {
+        "/send-text" => {
+            let send_text: SendTextRequest = parse_json(&request.body)?;
+            if send_text.text.is_empty() {
+                return Err(("400 Bad Request", "No text to send".into()));
+            }
+            Ok(SendTextMessage(
+                send_text.text,
+                send_text.channel_id,
+                send_text.reply_to_id,
+            ))
+        }
+        "/send-position" => Ok(SendPositionMessage(
+            parse_json::<ChannelRequest>(&request.body)?.channel_id,
+        )),
+        _ => Ok(SendInfoMessage(
+            parse_json::<ChannelRequest>(&request.body)?.channel_id,
+        )),
+    }
+}
+
+fn parse_json<T: DeserializeOwned>(body: &[u8]) -> Result<T, (&'static str, String)> {
+    serde_json::from_slice(body).map_err(|e| ("400 Bad Request", e.to_string()))
+}
+
+/// Send JSON events to a client as Server-Sent Events, until it goes away
+async fn stream_events<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    mut events: broadcast::Receiver<String>,
+) {
+    let header = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+        Cache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+    if writer.write_all(header.as_bytes()).await.is_err() {
+        return;
+    }
+
+    loop {
+        match events.recv().await {
+            Ok(json) => {
+                if writer
+                    .write_all(format!("data: {json}\n\n").as_bytes())
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            // A slow client misses some events, rather than holding up the others
+            Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+async fn respond<W: AsyncWrite + Unpin>(writer: &mut W, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let _ = writer.write_all(response.as_bytes()).await;
+    let _ = writer.shutdown().await;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bridge::BridgeEvent::{Ready, Request};
+    use crate::bridge::{BridgeEvent, is_localhost, serve};
+    use crate::channel_id::ChannelId;
+    use crate::device_id::DeviceId;
+    use crate::device_subscription::SubscriptionEvent;
+    use crate::device_subscription::SubscriptionEvent::{
+        ConnectedEvent, DevicePacket, ReconnectingEvent,
+    };
+    use crate::device_view::DeviceViewMessage::SendTextMessage;
+    use meshtastic::protobufs::from_radio::PayloadVariant;
+    use meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded;
+    use meshtastic::protobufs::{Data, FromRadio, MeshPacket, PortNum};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpStream;
+    use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+    use tokio_stream::StreamExt;
+
+    /// Start a bridge on a free port, polling it in a task as the app would, returning the port,
+    /// the sender to pass it radio events, and a receiver of the events it sends to the app
+    async fn start_bridge() -> (
+        u16,
+        UnboundedSender<SubscriptionEvent>,
+        UnboundedReceiver<BridgeEvent>,
+    ) {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Could not find a free port")
+            .port();
+        let (app_sender, mut app_receiver) = unbounded_channel();
+        tokio::spawn(async move {
+            let mut events = Box::pin(serve(&port));
+            while let Some(event) = events.next().await {
+                let _ = app_sender.send(event);
+            }
+        });
+        let Some(Ready(event_sender)) = next(&mut app_receiver).await else {
+            panic!("Bridge did not start");
+        };
+        (port, event_sender, app_receiver)
+    }
+
+    async fn next(app_receiver: &mut UnboundedReceiver<BridgeEvent>) -> Option<BridgeEvent> {
+        tokio::time::timeout(Duration::from_secs(5), app_receiver.recv())
+            .await
+            .expect("Timed out waiting for the bridge")
+    }
+
+    /// Post a request to the bridge, returning the status line of the response
+    async fn post(port: u16, path: &str, content_type: &str, body: &str) -> String {
+        post_to_host(port, "localhost", path, content_type, body).await
+    }
+
+    /// Post a request to the bridge, giving `host` as the Host it is addressed to
+    async fn post_to_host(
+        port: u16,
+        host: &str,
+        path: &str,
+        content_type: &str,
+        body: &str,
+    ) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port))
+            .await
+            .expect("Could not connect to bridge");
+        let request = format!(
+            "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: {content_type}\r\n\
+            Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    /// Read the next event sent to a client of the `/events` stream
+    async fn read_event(events: &mut BufReader<TcpStream>) -> String {
+        let mut line = String::new();
+        while !line.starts_with("data: ") {
+            line.clear();
+            tokio::time::timeout(Duration::from_secs(5), events.read_line(&mut line))
+                .await
+                .expect("Timed out waiting for an event")
+                .expect("Could not read event");
+        }
+        line.trim_end().to_string()
+    }
+
+    #[tokio::test]
+    async fn send_text_and_receive_events() {
+        let (port, event_sender, mut app_receiver) = start_bridge().await;
+        let send_text = r#"{"channelId":{"Channel":0},"text":"Hi"}"#;
+
+        assert_eq!(
+            post(port, "/send-text", "application/json", send_text).await,
+            "HTTP/1.1 503 Service Unavailable"
+        );
+
+        // Start listening to events
+        let mut events = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        events
+            .write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut events = BufReader::new(events);
+        let mut status = String::new();
+        events.read_line(&mut status).await.unwrap();
+        assert_eq!(status.trim_end(), "HTTP/1.1 200 OK");
+
+        event_sender
+            .send(ConnectedEvent(DeviceId::Simulated))
+            .unwrap();
+        assert_eq!(
+            read_event(&mut events).await,
+            r#"data: {"event":"connected","device":"Simulated Radio"}"#
+        );
+
+        assert_eq!(
+            post(port, "/send-text", "application/json", send_text).await,
+            "HTTP/1.1 202 Accepted"
+        );
+        assert!(matches!(
+            next(&mut app_receiver).await,
+            Some(Request(SendTextMessage(text, ChannelId::Channel(0), None))) if text == "Hi"
+        ));
+
+        event_sender
+            .send(DevicePacket(Box::new(FromRadio {
+                id: 1,
+                payload_variant: Some(PayloadVariant::Packet(MeshPacket {
+                    from: 1001,
+                    to: u32::MAX,
+                    payload_variant: Some(Decoded(Data {
+                        portnum: PortNum::TextMessageApp as i32,
+                        payload: b"Hello".to_vec(),
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                })),
+            })))
+            .unwrap();
+        let event = read_event(&mut events).await;
+        assert!(event.contains(r#""event":"message""#));
+        assert!(event.contains(r#""text":"Hello""#));
+
+        // Nothing can be sent while the app reconnects to the radio
+        event_sender
+            .send(ReconnectingEvent(DeviceId::Simulated, 1))
+            .unwrap();
+        assert_eq!(
+            read_event(&mut events).await,
+            r#"data: {"event":"reconnecting","device":"Simulated Radio","attempt":1}"#
+        );
+        assert_eq!(
+            post(port, "/send-text", "application/json", send_text).await,
+            "HTTP/1.1 503 Service Unavailable"
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_requests() {
+        let (port, event_sender, _app_receiver) = start_bridge().await;
+        event_sender
+            .send(ConnectedEvent(DeviceId::Simulated))
+            .unwrap();
+        let channel = r#"{"channelId":{"Node":1001}}"#;
+
+        assert_eq!(
+            post(port, "/send-info", "text/plain", channel).await,
+            "HTTP/1.1 415 Unsupported Media Type"
+        );
+        assert_eq!(
+            post(port, "/send-info", "application/json", "{}").await,
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            post(port, "/send-pigeon", "application/json", channel).await,
+            "HTTP/1.1 404 Not Found"
+        );
+        assert_eq!(
+            post_to_host(
+                port,
+                "evil.example:8080",
+                "/send-info",
+                "application/json",
+                channel
+            )
+            .await,
+            "HTTP/1.1 403 Forbidden"
+        );
+    }
+
+    #[test]
+    fn localhost_names() {
+        assert!(is_localhost(Some("localhost")));
+        assert!(is_localhost(Some("localhost:4404")));
+        assert!(is_localhost(Some("127.0.0.1:4404")));
+        assert!(is_localhost(Some("[::1]")));
+        assert!(is_localhost(Some("[::1]:4404")));
+        assert!(!is_localhost(Some("evil.example")));
+        assert!(!is_localhost(Some("localhost.evil.example:4404")));
+        assert!(!is_localhost(None));
+    }
+}
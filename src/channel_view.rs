@@ -1,42 +1,49 @@
 use crate::Message::DeviceViewEvent;
 use crate::channel_id::ChannelId;
 use crate::channel_view::ChannelViewMessage::{
-    CancelPrepareReply, ClearMessage, EmojiPickerMsg, MessageInput, MessageSeen, PickChannel,
-    PrepareReply, ReplyWithEmoji, SendMessage,
+    CancelPrepareReply, ClearHighlight, ClearMessage, ComposerAction, EmojiPickerMsg, MessageSeen,
+    PickChannel, PrepareReply, ReplyWithEmoji, ScrollToEnd, Scrolled, SendMessage, ShowEntry,
 };
+use crate::channel_view_entry::DeliveryStatus;
 use crate::channel_view_entry::Payload::{
     AlertMessage, EmojiReply, NewTextMessage, PositionMessage, TextMessageReply, UserMessage,
+    WaypointMessage,
 };
+use crate::composer::SendKey;
 use crate::config::Config;
 use crate::device_view::DeviceViewMessage::{
-    ChannelMsg, ForwardMessage, SendInfoMessage, SendPositionMessage, ShowChannel,
-    StopForwardingMessage,
+    ChannelMsg, ForwardMessage, NewWaypoint, SendInfoMessage, SendPositionMessage, ShowChannel,
+    StopEditingWaypoint, StopForwardingMessage,
 };
 use crate::device_view::{DeviceView, DeviceViewMessage};
 use crate::styles::{
-    DAY_SEPARATOR_STYLE, button_chip_style, picker_header_style, reply_to_style, scrollbar_style,
-    text_input_style, tooltip_style,
+    DAY_SEPARATOR_STYLE, button_chip_style, highlight_style, picker_header_style, reply_to_style,
+    scrollbar_style, text_editor_style, tooltip_style,
 };
 use crate::{Message, channel_view_entry::ChannelViewEntry, icons};
+use crate::{composer, message_parts};
 use chrono::prelude::DateTime;
 use chrono::{Datelike, Local};
 use iced::font::Style::Italic;
 use iced::font::Weight;
 use iced::padding::right;
+use iced::widget::operation::RelativeOffset;
 use iced::widget::scrollable::Scrollbar;
-use iced::widget::text_input::{Icon, Side};
+use iced::widget::text_editor::{Action, Edit};
 use iced::widget::{
-    Button, Column, Container, Row, Space, button, center, container, mouse_area, opaque,
-    scrollable, stack, text, text_input,
+    Button, Column, Container, Id, Row, Space, button, center, container, mouse_area, opaque,
+    operation, scrollable, stack, text, text_editor,
 };
-use iced::{Center, Color, Element, Fill, Font, Padding, Pixels, Task};
+use iced::{Bottom, Center, Color, Element, Fill, Font, Padding, Task};
 use meshtastic::protobufs::NodeInfo;
 use ringmap::RingMap;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub enum ChannelViewMessage {
-    MessageInput(String),
+    ComposerAction(text_editor::Action),
     ClearMessage,
     SendMessage(Option<u32>), // optional message id if we are replying to that message
     PrepareReply(u32),        // entry_id
@@ -45,6 +52,10 @@ pub enum ChannelViewMessage {
     PickChannel(Option<ChannelId>),
     ReplyWithEmoji(u32, String, ChannelId), // Send an emoji reply
     EmojiPickerMsg(Box<crate::emoji_picker::PickerMessage<ChannelViewMessage>>),
+    ClearHighlight(u32), // entry_id
+    ShowEntry(u32),      // entry_id
+    Scrolled(bool),      // true if scrolled to the end of the entries
+    ScrollToEnd,
 }
 
 /// [ChannelView] implements view and update methods for Iced for a set of
@@ -52,11 +63,33 @@ pub enum ChannelViewMessage {
 #[derive(Debug, Default)]
 pub struct ChannelView {
     channel_id: ChannelId,
-    message: String,                         // text message typed in so far
+    composer: text_editor::Content, // text message typed in so far
     entries: RingMap<u32, ChannelViewEntry>, // entries received so far, keyed by message_id, ordered by rx_time
     my_node_num: u32,
     preparing_reply: Option<u32>,
     emoji_picker: crate::emoji_picker::EmojiPicker,
+    partial_messages: HashMap<u32, PartialMessage>, // messages being received in parts, by sender
+    highlighted: Option<u32>, // entry jumped to, that is outlined for a moment
+    scrolled_up: bool,        // the user has scrolled up from the newest entries
+    new_below: usize,         // entries received since the user scrolled up
+}
+
+/// A message split into numbered parts, being joined together as the parts are received
+#[derive(Debug)]
+struct PartialMessage {
+    entry_id: u32, // entry for the first part, that the later parts are added to
+    parts: usize,  // parts received so far
+    text: String,  // text of those parts, without their prefixes
+}
+
+/// The composer grows with the message typed, up to this height, and then scrolls
+const MAX_COMPOSER_HEIGHT: f32 = 150.0;
+/// How long an entry that has been jumped to stays outlined
+const HIGHLIGHT_TIME: Duration = Duration::from_secs(3);
+
+/// The id of the scrollable list of entries, to be able to scroll it to an entry
+fn entries_id() -> Id {
+    Id::new("channel_entries")
 }
 
 async fn empty() {}
@@ -82,6 +115,21 @@ impl ChannelView {
         }
     }
 
+    /// Set the [DeliveryStatus] of a message we sent, returning true if the message was found
+    pub fn set_status(&mut self, message_id: u32, status: DeliveryStatus) -> bool {
+        if let Some(entry) = self.entries.get_mut(&message_id) {
+            entry.set_status(status);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Return the entry for a message, if it is in the channel
+    pub fn entry(&self, message_id: u32) -> Option<&ChannelViewEntry> {
+        self.entries.get(&message_id)
+    }
+
     /// Add an emoji reply to a message.
     fn add_emoji_to(&mut self, request_id: u32, emoji_string: String, from: u32) {
         if let Some(entry) = self.entries.get_mut(&request_id) {
@@ -96,7 +144,11 @@ impl ChannelView {
             | NewTextMessage(_)
             | PositionMessage(_, _)
             | UserMessage(_)
+            | WaypointMessage(_)
             | TextMessageReply(_, _) => {
+                if self.scrolled_up && new_message.from() != self.my_node_num {
+                    self.new_below += 1;
+                }
                 self.entries.insert_sorted_by(
                     new_message.message_id(),
                     new_message,
@@ -109,6 +161,127 @@ impl ChannelView {
         };
     }
 
+    /// Add a new [ChannelViewEntry] message, joining it onto an earlier message from the same
+    /// sender if it is the next numbered part of that message, e.g. "2/3 ..." after "1/3 ...".
+    /// The first part of a reply is sent as a reply, and the other parts as text messages
+    pub fn new_message_part(&mut self, new_message: ChannelViewEntry) {
+        let from = new_message.from();
+        if let NewTextMessage(text) | TextMessageReply(_, text) = new_message.payload()
+            && let Some((number, count, part_text)) = message_parts::part(text)
+        {
+            if number == 1 {
+                self.partial_messages.insert(
+                    from,
+                    PartialMessage {
+                        entry_id: new_message.message_id(),
+                        parts: 1,
+                        text: part_text.to_string(),
+                    },
+                );
+            } else if let Some(partial) = self.partial_messages.get_mut(&from)
+                && partial.parts + 1 == number
+                && let Some(entry) = self.entries.get_mut(&partial.entry_id)
+            {
+                partial.parts = number;
+                partial.text.push_str(part_text);
+                entry.set_text(partial.text.clone());
+                entry.seen = false;
+                if number == count {
+                    self.partial_messages.remove(&from);
+                }
+                return;
+            }
+        }
+        self.new_message(new_message);
+    }
+
+    /// Restore entries loaded from the stored history, keeping any entry already in the view.
+    /// Messages that were still on their way when stored were never confirmed, so can be resent
+    pub fn restore(&mut self, entries: Vec<ChannelViewEntry>) {
+        for mut entry in entries {
+            if matches!(
+                entry.status(),
+                Some(DeliveryStatus::Queued | DeliveryStatus::Sent)
+            ) {
+                entry.set_status(DeliveryStatus::TimedOut);
+            }
+            if !self.entries.contains_key(&entry.message_id()) {
+                self.entries.insert_sorted_by(
+                    entry.message_id(),
+                    entry,
+                    ChannelViewEntry::sort_by_rx_time,
+                );
+            }
+        }
+    }
+
+    /// Restore a message typed but not sent before, keeping any text already typed
+    pub fn restore_draft(&mut self, draft: &str) {
+        if self.composer.text().is_empty() {
+            self.composer = text_editor::Content::with_text(draft);
+        }
+    }
+
+    /// Return the message typed in the channel but not sent yet, to be stored in the history
+    pub fn draft(&self) -> String {
+        self.composer.text()
+    }
+
+    /// Return true if a message has been typed in the channel but not sent yet
+    pub fn has_draft(&self) -> bool {
+        !self.composer.text().trim().is_empty()
+    }
+
+    /// Return the entries in the channel, in order
+    pub fn entries(&self) -> impl Iterator<Item = &ChannelViewEntry> {
+        self.entries.values()
+    }
+
+    /// Scroll to an entry and outline it for a moment, so the user can see it
+    pub fn show_entry(&mut self, entry_id: u32) -> Task<Message> {
+        let Some(index) = self.entries.get_index_of(&entry_id) else {
+            return Task::none();
+        };
+        self.highlighted = Some(entry_id);
+        self.snap_to_index(index).chain(Task::perform(
+            async { tokio::time::sleep(HIGHLIGHT_TIME).await },
+            move |_| DeviceViewEvent(ChannelMsg(ClearHighlight(entry_id))),
+        ))
+    }
+
+    /// Scroll to the first entry the user has not seen yet, or to the newest entry if they have
+    /// seen them all, when the channel is opened
+    pub fn show_first_unseen(&mut self) -> Task<Message> {
+        self.new_below = 0;
+        match self.entries.values().position(|entry| !entry.seen) {
+            Some(index) => self.snap_to_index(index),
+            None => operation::snap_to_end(entries_id()),
+        }
+    }
+
+    /// Scroll to the entry at `index`, by its position in the list of entries, as there is no way
+    /// to scroll to a widget. Entries of very different heights make it a little approximate
+    fn snap_to_index(&self, index: usize) -> Task<Message> {
+        let last = self.entries.len().saturating_sub(1).max(1);
+        let offset = RelativeOffset {
+            x: 0.0,
+            y: index as f32 / last as f32,
+        };
+        operation::snap_to(entries_id(), offset)
+    }
+
+    /// Return a copy of all the entries in the channel, in order, to be stored in the history
+    pub fn history(&self) -> Vec<ChannelViewEntry> {
+        self.entries.values().cloned().collect()
+    }
+
+    /// Return true if the entry with `message_id` exists and has not been seen yet
+    pub fn is_unseen(&self, message_id: u32) -> bool {
+        self.entries
+            .get(&message_id)
+            .is_some_and(|entry| !entry.seen)
+    }
+
     /// Return the number of unread messages in the channel
     pub fn unread_count(&self) -> usize {
         self.entries
@@ -124,20 +297,23 @@ impl ChannelView {
     /// Update the [ChannelView] state based on a [ChannelViewMessage]
     pub fn update(&mut self, channel_view_message: ChannelViewMessage) -> Task<Message> {
         match channel_view_message {
-            MessageInput(s) => {
-                if s.len() < 200 {
-                    self.message = s;
+            ComposerAction(action) => {
+                match action {
+                    Action::Edit(Edit::Paste(pasted)) => self.composer.perform(Action::Edit(
+                        Edit::Paste(Arc::new(composer::clean_pasted(&pasted))),
+                    )),
+                    action => self.composer.perform(action),
                 }
                 Task::none()
             }
             ClearMessage => {
-                self.message = String::new();
+                self.composer = text_editor::Content::new();
                 Task::none()
             }
             SendMessage(reply_to_id) => {
-                if !self.message.is_empty() {
-                    let msg = self.message.clone();
-                    self.message = String::new();
+                let msg = self.composer.text().trim_end().to_string();
+                if !msg.is_empty() {
+                    self.composer = text_editor::Content::new();
                     let channel_id = self.channel_id.clone();
                     self.preparing_reply = None;
                     Task::perform(empty(), move |_| {
@@ -175,6 +351,24 @@ impl ChannelView {
                     message_id, emoji, channel_id,
                 ))
             }),
+            ShowEntry(entry_id) => self.show_entry(entry_id),
+            Scrolled(at_end) => {
+                self.scrolled_up = !at_end;
+                if at_end {
+                    self.new_below = 0;
+                }
+                Task::none()
+            }
+            ScrollToEnd => {
+                self.new_below = 0;
+                operation::snap_to_end(entries_id())
+            }
+            ClearHighlight(entry_id) => {
+                if self.highlighted == Some(entry_id) {
+                    self.highlighted = None;
+                }
+                Task::none()
+            }
             EmojiPickerMsg(picker_msg) => {
                 if let Some(msg) = self.emoji_picker.update(*picker_msg) {
                     // Forward the wrapped message
@@ -196,10 +390,17 @@ impl ChannelView {
         device_view: &'a DeviceView,
         config: &'a Config,
     ) -> Element<'a, Message> {
-        let channel_view_content = self.channel_view(nodes, enable_position, enable_my_info);
+        let channel_view_content =
+            self.channel_view(nodes, enable_position, enable_my_info, device_view, config);
 
         if device_view.forwarding_message.is_some() {
             self.channel_picker(channel_view_content, device_view, config)
+        } else if let Some(editor) = &device_view.editing_waypoint {
+            Self::modal(
+                channel_view_content,
+                editor.view(&self.channel_id),
+                DeviceViewEvent(StopEditingWaypoint),
+            )
         } else {
             channel_view_content
         }
@@ -210,6 +411,8 @@ impl ChannelView {
         nodes: &'a HashMap<u32, NodeInfo>,
         enable_position: bool,
         enable_my_info: bool,
+        device_view: &'a DeviceView,
+        config: &'a Config,
     ) -> Element<'a, Message> {
         let mut channel_view_content = Column::new().padding(right(10));
 
@@ -229,25 +432,50 @@ impl ChannelView {
                     previous_day = message_day;
                 }
 
-                channel_view_content = channel_view_content.push(entry.view(
+                let mine = entry.from() == self.my_node_num;
+                let distance = if mine {
+                    None
+                } else {
+                    entry.distance_text(device_view.my_position(), config.distance_unit)
+                };
+                let entry_view = entry.view(
                     &self.entries,
                     nodes,
                     &self.channel_id,
-                    entry.from() == self.my_node_num,
+                    mine,
                     &self.emoji_picker,
-                ));
+                    distance,
+                );
+                channel_view_content = if self.highlighted == Some(entry.message_id()) {
+                    channel_view_content
+                        .push(container(entry_view).padding(2).style(highlight_style))
+                } else {
+                    channel_view_content.push(entry_view)
+                };
             }
 
             // Wrap the list of messages in a scrollable container, with a scrollbar
-            scrollable(channel_view_content)
+            let entries_scroll = scrollable(channel_view_content)
+                .id(entries_id())
                 .direction({
                     let scrollbar = Scrollbar::new().width(10.0);
                     scrollable::Direction::Vertical(scrollbar)
                 })
+                .on_scroll(|viewport| {
+                    let at_end = viewport.content_bounds().height <= viewport.bounds().height
+                        || viewport.relative_offset().y >= 0.99;
+                    DeviceViewEvent(ChannelMsg(Scrolled(at_end)))
+                })
                 .style(scrollbar_style)
                 .width(Fill)
-                .height(Fill)
-                .into()
+                .height(Fill);
+
+            // If messages have arrived while scrolled up, float a button over them to jump down
+            if self.new_below > 0 {
+                stack![entries_scroll, self.new_below_button()].into()
+            } else {
+                entries_scroll.into()
+            }
         };
 
         // A row of action buttons at the bottom of the channel view - this could be made
@@ -259,6 +487,11 @@ impl ChannelView {
             ));
         }
 
+        let mut waypoint_button = button(text("Waypoint 📍")).style(button_chip_style);
+        if enable_position {
+            waypoint_button = waypoint_button.on_press(DeviceViewEvent(NewWaypoint));
+        }
+
         let mut send_info_button = button(text("Send Info ⓘ")).style(button_chip_style);
         if enable_my_info {
             send_info_button = send_info_button
@@ -269,6 +502,8 @@ impl ChannelView {
             .padding([2, 0])
             .push(send_position_button)
             .push(Space::new().width(6))
+            .push(waypoint_button)
+            .push(Space::new().width(6))
             .push(send_info_button);
 
         // Place the scrollable in a column, with a row of buttons at the bottom
@@ -283,7 +518,7 @@ impl ChannelView {
         }
 
         // Add the input box at the bottom of the channel view
-        column.push(self.input_box()).into()
+        column.push(self.input_box(config.send_key)).into()
     }
 
     fn empty_view<'a>() -> Element<'a, Message> {
@@ -433,14 +668,37 @@ impl ChannelView {
             .into()
     }
 
-    fn input_box(&self) -> Element<'static, Message> {
+    /// A button at the bottom of the entries, saying how many have arrived since the user
+    /// scrolled up, that scrolls down to them
+    fn new_below_button(&self) -> Element<'static, Message> {
+        let label = match self.new_below {
+            1 => "1 new message ↓".to_string(),
+            count => format!("{count} new messages ↓"),
+        };
+        container(
+            button(text(label))
+                .style(button_chip_style)
+                .on_press(DeviceViewEvent(ChannelMsg(ScrollToEnd))),
+        )
+        .width(Fill)
+        .height(Fill)
+        .align_x(Center)
+        .align_y(Bottom)
+        .padding(8)
+        .into()
+    }
+
+    /// The multi-line composer a message is typed in, that grows with the message up to a limit,
+    /// with buttons to clear and send it
+    fn input_box(&self, send_key: SendKey) -> Element<'_, Message> {
+        let message = self.composer.text();
         let mut send_button = button(icons::send().size(18))
             .style(button_chip_style)
             .padding(Padding::from([6, 6]));
         let mut clear_button = button(text("⨂").size(18))
             .style(button_chip_style)
             .padding(Padding::from([6, 6]));
-        if !self.message.is_empty() {
+        if !message.trim_end().is_empty() {
             send_button = send_button.on_press(DeviceViewEvent(ChannelMsg(SendMessage(
                 self.preparing_reply,
             ))));
@@ -449,23 +707,26 @@ impl ChannelView {
 
         Row::new()
             .align_y(Center)
+            .push(text('\u{2709}').font(Font::with_name("icons")).size(18))
+            .push(Space::new().width(4.0))
             .push(
-                text_input("Type your message here", &self.message)
-                    .style(text_input_style)
-                    .on_input(|s| DeviceViewEvent(ChannelMsg(MessageInput(s))))
-                    .on_submit(DeviceViewEvent(ChannelMsg(SendMessage(
-                        self.preparing_reply,
-                    ))))
+                text_editor(&self.composer)
+                    .placeholder("Type your message here")
+                    .style(text_editor_style)
+                    .on_action(|action| DeviceViewEvent(ChannelMsg(ComposerAction(action))))
+                    .key_binding(move |key_press| {
+                        composer::key_binding(
+                            key_press,
+                            send_key,
+                            DeviceViewEvent(ChannelMsg(SendMessage(self.preparing_reply))),
+                        )
+                    })
                     .padding([6, 6])
-                    .icon(Icon {
-                        font: Font::with_name("icons"),
-                        code_point: '\u{2709}',
-                        size: Some(Pixels(18.0)),
-                        spacing: 4.0,
-                        side: Side::Left,
-                    }),
+                    .max_height(MAX_COMPOSER_HEIGHT),
             )
             .push(Space::new().width(4.0))
+            .push(text(message_parts::length_text(&message)).size(12))
+            .push(Space::new().width(4.0))
             .push(clear_button)
             .push(Space::new().width(4.0))
             .push(send_button)
@@ -475,10 +736,17 @@ impl ChannelView {
 
 #[cfg(test)]
 mod test {
-    use crate::channel_view::ChannelViewMessage::PrepareReply;
+    use crate::channel_view::ChannelViewMessage::{
+        ClearHighlight, ComposerAction, PrepareReply, ScrollToEnd, Scrolled, SendMessage, ShowEntry,
+    };
     use crate::channel_view::{ChannelId, ChannelView};
-    use crate::channel_view_entry::ChannelViewEntry;
-    use crate::channel_view_entry::Payload::NewTextMessage;
+    use crate::channel_view_entry::Payload::{NewTextMessage, TextMessageReply};
+    use crate::channel_view_entry::{ChannelViewEntry, DeliveryStatus};
+    use crate::message_parts;
+    use chrono::Utc;
+    use iced::widget::text_editor::{Action, Edit};
+    use meshtastic::protobufs::MeshPacket;
+    use std::sync::Arc;
     use std::time::Duration;
 
     #[tokio::test]
@@ -529,6 +797,44 @@ mod test {
         assert_eq!(channel_view.unread_count(), 1);
     }
 
+    #[test]
+    fn test_restore_history() {
+        let mut channel_view = ChannelView::new(ChannelId::Channel(0), 0);
+        let mut live = ChannelViewEntry::new(NewTextMessage("Live".to_string()), 1, 2);
+        live.seen = true;
+        channel_view.new_message(live);
+
+        let stored_old = ChannelViewEntry::new(NewTextMessage("Old".to_string()), 1, 1);
+        let stored_live = ChannelViewEntry::new(NewTextMessage("Stale".to_string()), 1, 2);
+        channel_view.restore(vec![stored_old, stored_live]);
+
+        assert_eq!(channel_view.history().len(), 2);
+        // The entry already in the view is not replaced by the stored one
+        assert_eq!(
+            channel_view.entries.get(&2).unwrap().payload().to_string(),
+            "Live"
+        );
+        assert!(channel_view.is_unseen(1));
+        assert!(!channel_view.is_unseen(2));
+    }
+
+    #[test]
+    fn test_restore_unconfirmed_message() {
+        let mut channel_view = ChannelView::new(ChannelId::Channel(0), 0);
+        let mut queued = ChannelViewEntry::new(NewTextMessage("Queued".to_string()), 0, 1);
+        queued.set_status(DeliveryStatus::Queued);
+        let mut acked = ChannelViewEntry::new(NewTextMessage("Acked".to_string()), 0, 2);
+        acked.set_status(DeliveryStatus::Acked);
+        channel_view.restore(vec![queued, acked]);
+
+        let restored = channel_view.entry(1).unwrap();
+        assert_eq!(restored.status(), Some(&DeliveryStatus::TimedOut));
+        assert_eq!(restored.resendable(), Some(("Queued".to_string(), None)));
+        let restored = channel_view.entry(2).unwrap();
+        assert_eq!(restored.status(), Some(&DeliveryStatus::Acked));
+        assert!(restored.resendable().is_none());
+    }
+
     #[test]
     fn test_replying_valid_entry() {
         let mut channel_view = ChannelView::new(ChannelId::Channel(0), 0);
@@ -550,4 +856,140 @@ mod test {
 
         assert!(channel_view.preparing_reply.is_none());
     }
+
+    #[test]
+    fn queued_messages_ordered_by_rx_time() {
+        let mut channel_view = ChannelView::new(ChannelId::Channel(0), 0);
+        let now = Utc::now().timestamp() as u32;
+        let packet = |id, rx_time| MeshPacket {
+            id,
+            from: 1,
+            rx_time,
+            ..Default::default()
+        };
+
+        // A live message, then one the radio queued an hour ago, then one from a radio that does
+        // not know the time
+        let live = ChannelViewEntry::received(NewTextMessage("Live".into()), &packet(1, now));
+        let queued =
+            ChannelViewEntry::received(NewTextMessage("Queued".into()), &packet(2, now - 3600));
+        let no_time = ChannelViewEntry::received(NewTextMessage("No time".into()), &packet(3, 100));
+        assert_eq!(live.late_by(), None);
+        assert!(queued.late_by().is_some_and(|late_by| late_by >= 3600));
+        assert_eq!(no_time.late_by(), None);
+
+        channel_view.new_message(live);
+        channel_view.new_message(queued);
+        channel_view.new_message(no_time);
+        let ids: Vec<u32> = channel_view.entries.keys().copied().collect();
+        assert_eq!(ids, vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn message_parts_joined() {
+        let mut channel_view = ChannelView::new(ChannelId::Channel(0), 0);
+        let part =
+            |id, from, text: &str| ChannelViewEntry::new(NewTextMessage(text.into()), from, id);
+
+        channel_view.new_message_part(part(1, 7, "1/3 The quick brown "));
+        channel_view.new_message_part(part(2, 8, "Interrupting"));
+        channel_view.new_message_part(part(3, 7, "2/3 fox jumps over "));
+        channel_view.new_message_part(part(4, 7, "3/3 the lazy dog"));
+        // A part out of order is shown on its own
+        channel_view.new_message_part(part(5, 7, "3/3 again"));
+
+        let texts: Vec<String> = channel_view
+            .entries
+            .values()
+            .map(|entry| entry.payload().to_string())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "The quick brown fox jumps over the lazy dog",
+                "Interrupting",
+                "3/3 again"
+            ]
+        );
+    }
+
+    #[test]
+    fn reply_parts_joined() {
+        let mut channel_view = ChannelView::new(ChannelId::Channel(0), 0);
+        channel_view.new_message(ChannelViewEntry::new(NewTextMessage("Hello".into()), 8, 1));
+
+        let text = "The quick brown fox jumps over the lazy dog";
+        let parts = message_parts::split(text, 20);
+        channel_view.new_message_part(ChannelViewEntry::new(
+            TextMessageReply(1, parts[0].clone()),
+            7,
+            2,
+        ));
+        for (id, part) in (3..).zip(&parts[1..]) {
+            channel_view.new_message_part(ChannelViewEntry::new(
+                NewTextMessage(part.clone()),
+                7,
+                id,
+            ));
+        }
+
+        assert_eq!(channel_view.entries.len(), 2);
+        let reply = channel_view.entry(2).expect("Reply not found");
+        assert!(matches!(reply.payload(), TextMessageReply(1, joined) if joined == text));
+    }
+
+    #[test]
+    fn pasted_text_cleaned_and_sent() {
+        let mut channel_view = ChannelView::new(ChannelId::Channel(0), 0);
+        let _ = channel_view.update(ComposerAction(Action::Edit(Edit::Paste(Arc::new(
+            "Line 1\r\nLine 2\t!".into(),
+        )))));
+        assert_eq!(channel_view.composer.text(), "Line 1\nLine 2 !");
+
+        let _ = channel_view.update(SendMessage(None));
+        assert!(channel_view.composer.text().is_empty());
+    }
+
+    #[test]
+    fn entry_shown_and_highlight_cleared() {
+        let mut channel_view = ChannelView::new(ChannelId::Channel(0), 0);
+        channel_view.new_message(ChannelViewEntry::new(NewTextMessage("One".into()), 1, 1));
+        channel_view.new_message(ChannelViewEntry::new(NewTextMessage("Two".into()), 1, 2));
+
+        let _ = channel_view.show_entry(3);
+        assert_eq!(channel_view.highlighted, None);
+
+        let _ = channel_view.show_entry(1);
+        assert_eq!(channel_view.highlighted, Some(1));
+        let _ = channel_view.update(ClearHighlight(2));
+        assert_eq!(channel_view.highlighted, Some(1));
+        let _ = channel_view.update(ClearHighlight(1));
+        assert_eq!(channel_view.highlighted, None);
+    }
+
+    #[test]
+    fn new_messages_counted_while_scrolled_up() {
+        let mut channel_view = ChannelView::new(ChannelId::Channel(0), 0);
+        let message = |id| ChannelViewEntry::new(NewTextMessage("Hi".into()), 1, id);
+        channel_view.new_message(message(1));
+        assert_eq!(channel_view.new_below, 0);
+
+        let _ = channel_view.update(Scrolled(false));
+        channel_view.new_message(message(2));
+        channel_view.new_message(message(3));
+        // Messages we send are not counted
+        channel_view.new_message(ChannelViewEntry::new(NewTextMessage("Me".into()), 0, 4));
+        assert_eq!(channel_view.new_below, 2);
+
+        let _ = channel_view.update(ScrollToEnd);
+        assert_eq!(channel_view.new_below, 0);
+
+        channel_view.new_message(message(5));
+        let _ = channel_view.update(Scrolled(true));
+        assert_eq!(channel_view.new_below, 0);
+        assert!(!channel_view.scrolled_up);
+
+        let _ = channel_view.update(ShowEntry(2));
+        assert_eq!(channel_view.highlighted, Some(2));
+    }
 }
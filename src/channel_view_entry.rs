@@ -2,26 +2,37 @@ use crate::Message;
 use crate::Message::{CopyToClipBoard, DeviceViewEvent, ShowLocation};
 use crate::channel_id::ChannelId;
 use crate::channel_view::ChannelViewMessage;
-use crate::channel_view::ChannelViewMessage::{MessageSeen, ReplyWithEmoji};
+use crate::channel_view::ChannelViewMessage::{MessageSeen, ReplyWithEmoji, ShowEntry};
+use crate::channel_view_entry::DeliveryStatus::{Acked, Failed, Queued, Relayed, Sent, TimedOut};
 use crate::channel_view_entry::Payload::{
     AlertMessage, EmojiReply, NewTextMessage, PositionMessage, TextMessageReply, UserMessage,
+    WaypointMessage,
+};
+use crate::device_view::DeviceViewMessage::{
+    ChannelMsg, DeleteWaypoint, EditWaypoint, ResendMessage, ShowChannel, StartForwardingMessage,
 };
-use crate::device_view::DeviceViewMessage::{ChannelMsg, ShowChannel, StartForwardingMessage};
 use crate::device_view::short_name;
+use crate::distance::{DistanceUnit, distance_and_bearing_text};
+use crate::node_detail::{duration, hops_text};
 use crate::styles::{
-    COLOR_DICTIONARY, COLOR_GREEN, MY_MESSAGE_BUBBLE_STYLE, OTHERS_MESSAGE_BUBBLE_STYLE,
+    COLOR_DICTIONARY, COLOR_GREEN, COLOR_RED, MY_MESSAGE_BUBBLE_STYLE, OTHERS_MESSAGE_BUBBLE_STYLE,
     TIME_TEXT_COLOR, TIME_TEXT_SIZE, TIME_TEXT_WIDTH, alert_message_style, button_chip_style,
-    menu_button_style, message_text_style,
+    menu_button_style, message_text_style, tooltip_style,
 };
+use crate::waypoint::{expiry_text, waypoint_text};
 use chrono::{DateTime, Local, Utc};
 use iced::Length::Fixed;
 use iced::font::Weight;
-use iced::widget::{Column, Container, Row, Space, Text, button, sensor, text, tooltip};
+use iced::mouse::Interaction;
+use iced::widget::{
+    Column, Container, Row, Space, Text, button, mouse_area, sensor, text, tooltip,
+};
 use iced::{Bottom, Color, Element, Fill, Font, Left, Padding, Renderer, Right, Theme, Top};
-use iced_aw::menu::Menu;
+use iced_aw::menu::{Item, Menu};
 use iced_aw::{MenuBar, menu_bar, menu_items};
-use meshtastic::protobufs::{NodeInfo, User};
+use meshtastic::protobufs::{MeshPacket, NodeInfo, Position, User, Waypoint};
 use ringmap::RingMap;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::default::Default;
@@ -30,7 +41,7 @@ use std::fmt::Formatter;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Payload {
     AlertMessage(String),
     NewTextMessage(String),
@@ -38,6 +49,7 @@ pub enum Payload {
     EmojiReply(u32, String),
     PositionMessage(i32, i32),
     UserMessage(User),
+    WaypointMessage(Waypoint),
 }
 
 impl fmt::Display for Payload {
@@ -49,6 +61,84 @@ impl fmt::Display for Payload {
             EmojiReply(_, _) => f.write_str(""), // Not possible
             PositionMessage(lat, long) => f.write_str(&ChannelViewEntry::location_text(lat, long)),
             UserMessage(user) => f.write_str(&ChannelViewEntry::user_text(user)),
+            WaypointMessage(waypoint) => f.write_str(&waypoint_text(waypoint)),
+        }
+    }
+}
+
+/// Messages that reach us this many seconds after the radio received them are shown as late
+const LATE_SECONDS: i64 = 120;
+
+/// An `rx_time` before this (2020-01-01) is from a radio that did not know the time
+const EARLIEST_RX_TIME: u32 = 1_577_836_800;
+
+/// How a message we received reached our radio
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RadioInfo {
+    pub snr: f32,          // dB
+    pub rssi: i32,         // dBm, 0 if not known, e.g. via MQTT
+    pub hops: Option<u32>, // hops taken, if the sender's firmware reports it
+    pub via_mqtt: bool,    // came through an MQTT gateway
+    pub rx_time: u32,      // seconds since the epoch, 0 if not known
+}
+
+impl RadioInfo {
+    pub fn new(mesh_packet: &MeshPacket) -> Self {
+        let hops = (mesh_packet.hop_start > 0 && mesh_packet.hop_start >= mesh_packet.hop_limit)
+            .then(|| mesh_packet.hop_start - mesh_packet.hop_limit);
+        RadioInfo {
+            snr: mesh_packet.rx_snr,
+            rssi: mesh_packet.rx_rssi,
+            hops,
+            via_mqtt: mesh_packet.via_mqtt,
+            rx_time: mesh_packet.rx_time,
+        }
+    }
+
+    /// Return a description of how the message was received, one line per item
+    fn description(&self) -> String {
+        let mut lines = vec![];
+        if self.rssi != 0 {
+            lines.push(format!("SNR {:.1} dB, RSSI {} dBm", self.snr, self.rssi));
+        }
+        if let Some(hops) = self.hops {
+            lines.push(hops_text(hops));
+        }
+        if self.via_mqtt {
+            lines.push("Via MQTT".into());
+        }
+        if let Some(time) = DateTime::<Utc>::from_timestamp_secs(self.rx_time as i64)
+            && self.rx_time != 0
+        {
+            lines.push(format!(
+                "Received {}",
+                time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// The progress of a message sent by us, on its way to being delivered
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DeliveryStatus {
+    Queued,         // Waiting to be sent to the radio
+    Sent,           // Sent to the radio
+    Relayed,        // Heard being relayed by another node
+    Acked,          // Acknowledged by the destination
+    Failed(String), // Failed, with the reason given by the radio
+    TimedOut,       // Not acknowledged in time
+}
+
+impl fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Queued => f.write_str("Queued"),
+            Sent => f.write_str("Sent to radio"),
+            Relayed => f.write_str("Relayed"),
+            Acked => f.write_str("Delivered"),
+            Failed(reason) => write!(f, "Failed: {reason}"),
+            TimedOut => f.write_str("Timed out"),
         }
     }
 }
@@ -62,7 +152,7 @@ impl Default for Payload {
 /// An entry in the Channel View that represents some type of message sent to either this user on
 /// this device or to a channel this device can read. Can be any of [Payload] types.
 #[allow(dead_code)] // Remove when the 'seen' field is used
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ChannelViewEntry {
     /// NodeId of the node that sent this message
     from: u32,
@@ -74,11 +164,19 @@ pub struct ChannelViewEntry {
     payload: Payload,
     /// Has the user of the app seen this message?
     pub seen: bool,
-    /// Has the entry been acknowledged as received by a receiver?
-    acked: bool,
+    /// How far a message sent by us has got on its way to being delivered
+    status: Option<DeliveryStatus>,
     /// Map of emojis and for each emoji there is the string for it and a number of node ids
     /// who sent that emoji
+    #[serde(default = "HashMap::new")]
     emoji_reply: HashMap<String, Vec<u32>>,
+    /// How a message we received reached our radio
+    #[serde(default)]
+    radio_info: Option<RadioInfo>,
+    /// How many seconds after it was received by the radio, the message reached us, if that was
+    /// long enough for it to be shown as late, e.g. when the radio sends its queue as we connect
+    #[serde(default)]
+    late_by: Option<i64>,
 }
 
 impl ChannelViewEntry {
@@ -94,6 +192,31 @@ impl ChannelViewEntry {
         }
     }
 
+    /// Create a new [ChannelViewEntry] for a message received in a [MeshPacket], keeping how it
+    /// reached our radio
+    /// The time is when the radio received it, if the radio knows the time, so that messages the
+    /// radio had queued are in the right order
+    pub fn received(payload: Payload, mesh_packet: &MeshPacket) -> Self {
+        let now = Self::now();
+        let rx_daytime = Self::rx_daytime(mesh_packet.rx_time, now);
+        let late_by = (now - rx_daytime).num_seconds();
+        ChannelViewEntry {
+            rx_daytime,
+            late_by: (late_by >= LATE_SECONDS).then_some(late_by),
+            radio_info: Some(RadioInfo::new(mesh_packet)),
+            ..Self::new(payload, mesh_packet.from, mesh_packet.id)
+        }
+    }
+
+    /// Return the time a packet was received by the radio, from its `rx_time`, or `now` if the
+    /// radio did not know the time, or it is not believable
+    fn rx_daytime(rx_time: u32, now: DateTime<Local>) -> DateTime<Local> {
+        match DateTime::<Utc>::from_timestamp_secs(rx_time as i64) {
+            Some(time) if rx_time >= EARLIEST_RX_TIME && time <= now => time.with_timezone(&Local),
+            _ => now,
+        }
+    }
+
     /// Get the time now as a [DateTime<Local>]
     fn now() -> DateTime<Local> {
         let rx_time = SystemTime::now()
@@ -115,14 +238,62 @@ impl ChannelViewEntry {
         &self.payload
     }
 
+    /// Return the text of a text message or alert, if this entry is one
+    pub fn text(&self) -> Option<&str> {
+        match &self.payload {
+            AlertMessage(text) | NewTextMessage(text) | TextMessageReply(_, text) => Some(text),
+            _ => None,
+        }
+    }
+
     /// Return the message_id
     pub fn message_id(&self) -> u32 {
         self.message_id
     }
 
-    /// Mark the Entry as acknowledgeMd
+    /// Mark the Entry as acknowledged
     pub fn ack(&mut self) {
-        self.acked = true;
+        self.status = Some(Acked);
+    }
+
+    /// Set the [DeliveryStatus] of a message sent by us
+    pub fn set_status(&mut self, status: DeliveryStatus) {
+        self.status = Some(status);
+    }
+
+    /// Return how a message we received reached our radio, if known
+    pub fn radio_info(&self) -> Option<&RadioInfo> {
+        self.radio_info.as_ref()
+    }
+
+    /// Return how many seconds late the message reached us, if it was late
+    pub fn late_by(&self) -> Option<i64> {
+        self.late_by
+    }
+
+    /// Return the [DeliveryStatus] of a message sent by us, if it is being tracked
+    pub fn status(&self) -> Option<&DeliveryStatus> {
+        self.status.as_ref()
+    }
+
+    /// Return the text and reply to message id needed to send this message again, if it is a
+    /// text message that failed to be delivered
+    pub fn resendable(&self) -> Option<(String, Option<u32>)> {
+        if !matches!(self.status, Some(Failed(_) | TimedOut)) {
+            return None;
+        }
+        match &self.payload {
+            NewTextMessage(text) => Some((text.clone(), None)),
+            TextMessageReply(reply_to_id, text) => Some((text.clone(), Some(*reply_to_id))),
+            _ => None,
+        }
+    }
+
+    /// Replace the text of a text message, e.g. when more parts of it have been received
+    pub fn set_text(&mut self, new_text: String) {
+        if let NewTextMessage(text) | TextMessageReply(_, text) = &mut self.payload {
+            *text = new_text;
+        }
     }
 
     /// Add an emoji reply to this entry
@@ -133,11 +304,6 @@ impl ChannelViewEntry {
             .or_insert(vec![from]);
     }
 
-    /// Return true if the radio has acknowledged this message
-    pub fn acked(&self) -> bool {
-        self.acked
-    }
-
     /// Return the emoji reply to this message, if any.
     pub fn emojis(&self) -> &HashMap<String, Vec<u32>> {
         &self.emoji_reply
@@ -148,9 +314,10 @@ impl ChannelViewEntry {
         self.rx_daytime
     }
 
-    /// Order two messages - using the rx_daytime field.
+    /// Order a message already in a channel (`left`) and one being inserted (`right`) - using the
+    /// rx_daytime field. Messages with the same time are kept in the order they were inserted
     pub fn sort_by_rx_time(_: &u32, left: &Self, _: &u32, right: &Self) -> Ordering {
-        left.rx_daytime.cmp(&right.rx_daytime)
+        left.rx_daytime.cmp(&right.rx_daytime).then(Ordering::Less)
     }
 
     /// Return the text to use when replying to this message
@@ -218,6 +385,7 @@ impl ChannelViewEntry {
         channel_id: &'a ChannelId,
         mine: bool,
         emoji_picker: &'a crate::emoji_picker::EmojiPicker,
+        distance: Option<String>,
     ) -> Element<'a, Message> {
         let name = short_name(nodes, self.from);
 
@@ -227,6 +395,7 @@ impl ChannelViewEntry {
             TextMessageReply(_, text_msg) => text_msg.clone(),
             PositionMessage(lat, long) => Self::location_text(lat, long),
             UserMessage(user) => Self::user_text(user),
+            WaypointMessage(waypoint) => waypoint_text(waypoint),
             EmojiReply(_, _) => String::default(), // Should never happen
         };
 
@@ -242,6 +411,9 @@ impl ChannelViewEntry {
             );
         }
 
+        // My messages have a menu at the end, to be able to resend them if they fail
+        let my_menu_bar = mine.then(|| self.my_menu_bar(message_text.clone(), channel_id));
+
         let content: Element<'static, Message> = match self.payload() {
             AlertMessage(_) => text(message_text)
                 .style(alert_message_style)
@@ -251,31 +423,52 @@ impl ChannelViewEntry {
                 text(message_text).style(message_text_style).size(18).into()
             }
             TextMessageReply(reply_to_id, _) => {
+                // Clicking the quote jumps to the message replied to
                 if let Some(reply_quote) = Self::reply_quote(entries, reply_to_id) {
-                    let quote_row = Row::new().push(text(reply_quote).color(COLOR_GREEN));
+                    let quote = mouse_area(text(reply_quote).color(COLOR_GREEN))
+                        .on_press(DeviceViewEvent(ChannelMsg(ShowEntry(*reply_to_id))))
+                        .interaction(Interaction::Pointer);
+                    let quote_row = Row::new().push(quote);
                     message_content_column = message_content_column.push(quote_row);
                 };
                 text(message_text).style(message_text_style).size(18).into()
             }
-            PositionMessage(lat, long) => button(text(message_text))
-                .padding([1, 5])
-                .style(button_chip_style)
-                .on_press(ShowLocation(*lat, *long))
-                .into(),
+            PositionMessage(lat, long) => {
+                let position_button = button(text(message_text))
+                    .padding([1, 5])
+                    .style(button_chip_style)
+                    .on_press(ShowLocation(*lat, *long));
+                match distance {
+                    Some(distance) => Row::new()
+                        .spacing(6)
+                        .align_y(Bottom)
+                        .push(position_button)
+                        .push(text(distance).color(TIME_TEXT_COLOR).size(14))
+                        .into(),
+                    None => position_button.into(),
+                }
+            }
+            WaypointMessage(waypoint) => Self::waypoint_content(waypoint, channel_id, mine),
             EmojiReply(_, _) => text(message_text).into(),
         };
 
-        // Create the row with message text and time and maybe an ACK tick mark
+        // Create the row with message text and time and maybe a delivery status mark
         let mut text_and_time_row = Row::new()
             .push(content)
             .push(Space::new().width(10.0))
-            .push(Self::time_to_text(self.time()))
+            .push(self.time_and_radio_info())
             .align_y(Bottom);
 
-        if self.acked() {
-            text_and_time_row = text_and_time_row.push(text("✓").size(14).color(COLOR_GREEN))
+        if let Some(status) = self.status() {
+            text_and_time_row = text_and_time_row.push(Self::status_mark(status));
         };
 
+        if let Some(my_menu_bar) = my_menu_bar {
+            text_and_time_row = text_and_time_row
+                .push(Space::new().width(4.0))
+                .push(my_menu_bar);
+        }
+
         // Add the message text and time row
         message_content_column = message_content_column.push(text_and_time_row);
 
@@ -329,6 +522,99 @@ impl ChannelViewEntry {
             .into()
     }
 
+    /// Return an element with the time of the message, with a tooltip describing how it reached
+    /// our radio, and a mark if it came via MQTT
+    fn time_and_radio_info(&self) -> Element<'static, Message> {
+        let time = Self::time_to_text(self.time());
+        let Some(radio_info) = &self.radio_info else {
+            return time.into();
+        };
+
+        let mut row = Row::new().align_y(Bottom).push(time);
+        if radio_info.via_mqtt {
+            row = row.push(text("☁").size(TIME_TEXT_SIZE).color(TIME_TEXT_COLOR));
+        }
+        let mut description = radio_info.description();
+        if let Some(late_by) = self.late_by {
+            row = row.push(text("⌛").size(TIME_TEXT_SIZE).color(TIME_TEXT_COLOR));
+            if !description.is_empty() {
+                description.push('\n');
+            }
+            description.push_str(&format!("Reached us {} late", duration(late_by)));
+        }
+        if description.is_empty() {
+            return row.into();
+        }
+        tooltip(row, text(description), tooltip::Position::Bottom)
+            .style(tooltip_style)
+            .into()
+    }
+
+    /// Return an element with a mark for the [DeliveryStatus], and a tooltip describing it
+    fn status_mark(status: &DeliveryStatus) -> Element<'static, Message> {
+        let (mark, color) = match status {
+            Queued => ("🕓", TIME_TEXT_COLOR),
+            Sent => ("✓", TIME_TEXT_COLOR),
+            Relayed => ("✓✓", TIME_TEXT_COLOR),
+            Acked => ("✓✓", COLOR_GREEN),
+            Failed(_) | TimedOut => ("⚠", COLOR_RED),
+        };
+        tooltip(
+            text(mark).size(14).color(color),
+            text(status.to_string()),
+            tooltip::Position::Bottom,
+        )
+        .style(tooltip_style)
+        .into()
+    }
+
+    /// The content of a waypoint message: its name, to show its location, description and expiry,
+    /// with buttons to edit or delete it unless it is locked by another node
+    fn waypoint_content(
+        waypoint: &Waypoint,
+        channel_id: &ChannelId,
+        mine: bool,
+    ) -> Element<'static, Message> {
+        let mut name_button = button(text(waypoint_text(waypoint)))
+            .padding([1, 5])
+            .style(button_chip_style);
+        if let (Some(lat), Some(long)) = (waypoint.latitude_i, waypoint.longitude_i) {
+            name_button = name_button.on_press(ShowLocation(lat, long));
+        }
+
+        let mut column = Column::new().spacing(2).push(name_button);
+        if !waypoint.description.is_empty() {
+            column = column.push(
+                text(waypoint.description.clone())
+                    .style(message_text_style)
+                    .size(16),
+            );
+        }
+        let now = Utc::now().timestamp();
+        if let Some(expiry) = expiry_text(waypoint, now) {
+            column = column.push(text(expiry).color(TIME_TEXT_COLOR).size(TIME_TEXT_SIZE));
+        }
+
+        if (waypoint.locked_to == 0 || mine) && !crate::waypoint::expired(waypoint, now) {
+            let edit = |label: &'static str, message| {
+                button(text(label).size(14))
+                    .padding([0, 6])
+                    .style(button_chip_style)
+                    .on_press(DeviceViewEvent(message))
+            };
+            column = column.push(
+                Row::new()
+                    .spacing(4)
+                    .push(edit("edit", EditWaypoint(waypoint.clone())))
+                    .push(edit(
+                        "delete",
+                        DeleteWaypoint(channel_id.clone(), waypoint.clone()),
+                    )),
+            );
+        }
+        column.into()
+    }
+
     fn user_text(user: &User) -> String {
         format!(
             "ⓘ from '{}' ('{}'), id = '{}', with hardware '{}'",
@@ -339,6 +625,24 @@ impl ChannelViewEntry {
         )
     }
 
+    /// Return how far, and in which direction, the location in this message is from `my_position`
+    pub fn distance_text(
+        &self,
+        my_position: Option<&Position>,
+        unit: DistanceUnit,
+    ) -> Option<String> {
+        let (latitude_i, longitude_i) = match &self.payload {
+            PositionMessage(lat, long) => (*lat, *long),
+            _ => return None,
+        };
+        let position = Position {
+            latitude_i: Some(latitude_i),
+            longitude_i: Some(longitude_i),
+            ..Default::default()
+        };
+        distance_and_bearing_text(my_position?, &position, unit)
+    }
+
     fn location_text(lat: &i32, long: &i32) -> String {
         let latitude = 0.0000001 * *lat as f64;
         let longitude = 0.0000001 * *long as f64;
@@ -432,7 +736,7 @@ impl ChannelViewEntry {
             menu_tpl_2(menu_items!(
             (picker_element)))),
             (menu_button("copy".into(), CopyToClipBoard(message.to_string()))),
-            (menu_button("forward".into(), DeviceViewEvent(StartForwardingMessage(self.clone())))),
+            (menu_button("forward".into(), DeviceViewEvent(StartForwardingMessage(Box::new(self.clone()))))),
             (menu_button("reply".into(), DeviceViewEvent(ChannelMsg(ChannelViewMessage::PrepareReply(self.message_id))))),
             (menu_button(dm, DeviceViewEvent(ShowChannel(Some(ChannelId::Node(self.from()))))))
         );
@@ -448,6 +752,37 @@ impl ChannelViewEntry {
     }
 }
 
+impl ChannelViewEntry {
+    /// The menu for messages sent by us
+    fn my_menu_bar<'a>(
+        &'a self,
+        message: String,
+        channel_id: &'a ChannelId,
+    ) -> MenuBar<'a, Message, Theme, Renderer> {
+        #[rustfmt::skip]
+        let mut menu_items = menu_items!(
+            (menu_button("copy".into(), CopyToClipBoard(message))),
+            (menu_button("forward".into(), DeviceViewEvent(StartForwardingMessage(Box::new(self.clone()))))),
+            (menu_button("reply".into(), DeviceViewEvent(ChannelMsg(ChannelViewMessage::PrepareReply(self.message_id)))))
+        );
+
+        if self.resendable().is_some() {
+            menu_items.push(Item::new(menu_button(
+                "resend".into(),
+                DeviceViewEvent(ResendMessage(channel_id.clone(), self.message_id)),
+            )));
+        }
+
+        let menu_tpl_1 = |items| Menu::new(items).spacing(3);
+        menu_bar!((menu_root_button("▼"), {
+            menu_tpl_1(menu_items).width(140)
+        }))
+        .close_on_background_click(true)
+        .close_on_item_click(true)
+        .style(menu_button_style)
+    }
+}
+
 fn menu_button(
     label: String,
     message: Message,
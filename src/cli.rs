@@ -0,0 +1,575 @@
+//! A headless command line mode, to send and receive messages without opening a window, e.g.
+//! `meshchat send --channel 0 "Hello"` from a cron job, or `meshchat listen --json | jq`
+use crate::channel_id::ChannelId;
+use crate::channel_view_entry::DeliveryStatus;
+use crate::channel_view_entry::DeliveryStatus::{Failed, TimedOut};
+use crate::config::{Config, read_config};
+use crate::device_id::DeviceId;
+use crate::device_subscription::SubscriberMessage::{Connect, Disconnect, SendText};
+use crate::device_subscription::SubscriptionEvent::{
+    ConnectedEvent, ConnectionError, DevicePacket, DisconnectedEvent, MessageSent, Ready,
+    ReconnectingEvent,
+};
+use crate::device_subscription::{SubscriberMessage, SubscriptionEvent, subscribe};
+use crate::message_parts::{self, MAX_MESSAGE_BYTES};
+use crate::outbox::{Delivery, Outbox, OutboxItem, routing_error};
+use crate::received_message::Names;
+use meshtastic::protobufs::from_radio::PayloadVariant;
+use meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded;
+use meshtastic::protobufs::{FromRadio, PortNum, routing};
+use meshtastic::utils::generate_rand_id;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::Sender;
+use tokio_stream::{Stream, StreamExt};
+
+pub const USAGE: &str = "Usage:
+  meshchat
+      Start the app
+  meshchat send [--device <device>] [--channel <index> | --node <node>] [<text>...]
+      Send a text message, read from stdin if not given, and wait until it is delivered
+  meshchat listen [--device <device>] [--json]
+      Print the text messages received, until interrupted
+  meshchat help
+      Print this help
+
+<device> is written as in the config file: a BLE MAC address, serial:<port>, tcp:<host:port> or
+simulated. It defaults to the last device the app connected to.
+<index> is a channel index, 0 (the primary channel) when no destination is given.
+<node> is a node number, a node id such as !a1b2c3d4, or an alias given to a node in the app.";
+
+/// How often to check for messages that have not been acknowledged in time
+const OUTBOX_CHECK_PERIOD: Duration = Duration::from_secs(10);
+
+/// How long to wait for the radio to disconnect, before giving up on it
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A command to run without the UI
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Send {
+        device: Option<DeviceId>,
+        destination: Destination,
+        text: Option<String>, // None to read the text from stdin
+    },
+    Listen {
+        device: Option<DeviceId>,
+        json: bool,
+    },
+    Help,
+}
+
+/// Where to send a message, as given on the command line
+#[derive(Debug, PartialEq)]
+pub enum Destination {
+    Channel(i32), // Channel index
+    Node(String), // Node number, node id or alias
+}
+
+impl Destination {
+    /// Return the [ChannelId] to send to, looking up node aliases in the config
+    fn resolve(&self, config: &Config) -> Result<ChannelId, String> {
+        match self {
+            Destination::Channel(index) => Ok(ChannelId::Channel(*index)),
+            Destination::Node(node) => {
+                let node_num = if let Some(hex) = node.strip_prefix('!') {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Ok(node_num) = node.parse::<u32>() {
+                    Some(node_num)
+                } else {
+                    config
+                        .aliases
+                        .iter()
+                        .find(|(_, alias)| alias.eq_ignore_ascii_case(node))
+                        .map(|(node_num, _)| *node_num)
+                };
+                node_num
+                    .map(ChannelId::Node)
+                    .ok_or(format!("Unknown node '{node}'"))
+            }
+        }
+    }
+}
+
+/// Parse the command line arguments (without the program name). Return None if there is no
+/// command, in which case the app should be started as normal
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Option<Result<Command, String>> {
+    let mut args = args.into_iter();
+    match args.next()?.as_str() {
+        "send" => Some(parse_send(args)),
+        "listen" => Some(parse_listen(args)),
+        "help" | "--help" | "-h" => Some(Ok(Command::Help)),
+        _ => None,
+    }
+}
+
+fn parse_send<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut device = None;
+    let mut destination = Destination::Channel(0);
+    let mut words = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--device" => device = Some(DeviceId::from_str(&option_value(&mut args, &arg)?)?),
+            "--channel" => {
+                let index = option_value(&mut args, &arg)?;
+                destination = Destination::Channel(
+                    index
+                        .parse()
+                        .ok()
+                        .filter(|index| (0..8).contains(index))
+                        .ok_or(format!("Invalid channel index '{index}'"))?,
+                );
+            }
+            "--node" => destination = Destination::Node(option_value(&mut args, &arg)?),
+            // Everything after "--" is text, even if it looks like an option
+            "--" => words.extend(args.by_ref()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{arg}'")),
+            _ => words.push(arg),
+        }
+    }
+
+    Ok(Command::Send {
+        device,
+        destination,
+        text: (!words.is_empty()).then(|| words.join(" ")),
+    })
+}
+
+fn parse_listen<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut device = None;
+    let mut json = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--device" => device = Some(DeviceId::from_str(&option_value(&mut args, &arg)?)?),
+            "--json" => json = true,
+            _ => return Err(format!("Unknown option '{arg}'")),
+        }
+    }
+    Ok(Command::Listen { device, json })
+}
+
+/// Return the value following an option, or an error if it is missing
+fn option_value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String, String> {
+    args.next().ok_or(format!("Missing value for '{option}'"))
+}
+
+/// Run a command parsed from the command line, returning the exit code for the process
+pub fn run(command: Result<Command, String>) -> i32 {
+    let command = match command {
+        Ok(Command::Help) => {
+            println!("{USAGE}");
+            return 0;
+        }
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return 2;
+        }
+    };
+
+    let result = tokio::runtime::Runtime::new()
+        .map_err(|e| format!("Could not start the runtime: {e}"))
+        .and_then(|runtime| runtime.block_on(run_command(command)));
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+async fn run_command(command: Command) -> Result<(), String> {
+    let config = read_config()
+        .await
+        .map_err(|e| format!("Error reading config file: {e}"))?;
+    let device_or_default = |device: Option<DeviceId>| {
+        device.or(config.device.clone()).ok_or(
+            "No device given with --device, and the app has not connected to one".to_string(),
+        )
+    };
+
+    match command {
+        Command::Send {
+            device,
+            destination,
+            text,
+        } => {
+            let device = device_or_default(device)?;
+            let channel_id = destination.resolve(&config)?;
+            let text = match text {
+                Some(text) => text,
+                None => std::io::read_to_string(std::io::stdin())
+                    .map_err(|e| format!("Could not read the text to send: {e}"))?,
+            };
+            let text = text.trim_end().to_string();
+            if text.is_empty() {
+                return Err("No text to send".into());
+            }
+            let status = send(
+                Box::pin(subscribe()),
+                device,
+                channel_id,
+                text,
+                config.message_retries,
+            )
+            .await?;
+            eprintln!("{status}");
+            Ok(())
+        }
+        Command::Listen { device, json } => {
+            let device = device_or_default(device)?;
+            listen(Box::pin(subscribe()), device, &config, json).await
+        }
+        Command::Help => Ok(()),
+    }
+}
+
+/// Wait for the subscription to be ready then connect to `device`, returning the sender used to
+/// send messages to the subscription
+async fn connect<S: Stream<Item = SubscriptionEvent> + Unpin>(
+    events: &mut S,
+    device: DeviceId,
+) -> Result<Sender<SubscriberMessage>, String> {
+    let Some(Ready(sender)) = events.next().await else {
+        return Err("Could not start the connection to the radio".into());
+    };
+    sender
+        .send(Connect(device))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    loop {
+        match events.next().await {
+            Some(ConnectedEvent(device_id)) => {
+                eprintln!("Connected to {device_id}");
+                return Ok(sender);
+            }
+            Some(event) => connection_event(&event)?,
+            None => return Err("The connection to the radio ended".into()),
+        }
+    }
+}
+
+/// Report a change in the connection to the radio, returning an error if it has been lost
+fn connection_event(event: &SubscriptionEvent) -> Result<(), String> {
+    match event {
+        ReconnectingEvent(device_id, attempt) => {
+            eprintln!("Could not reach {device_id}, reconnecting (attempt {attempt})");
+            Ok(())
+        }
+        ConnectionError(_, summary, detail) => Err(format!("{summary}: {detail}")),
+        DisconnectedEvent(device_id) => Err(format!("Disconnected from {device_id}")),
+        _ => Ok(()),
+    }
+}
+
+/// Ask the subscription to disconnect from the radio, and wait a while for it to do so
+async fn disconnect<S: Stream<Item = SubscriptionEvent> + Unpin>(
+    events: &mut S,
+    sender: &Sender<SubscriberMessage>,
+) {
+    let _ = sender.send(Disconnect).await;
+    let _ = tokio::time::timeout(DISCONNECT_TIMEOUT, async {
+        while let Some(event) = events.next().await {
+            if matches!(event, DisconnectedEvent(_) | ConnectionError(..)) {
+                break;
+            }
+        }
+    })
+    .await;
+}
+
+async fn send_item(sender: &Sender<SubscriberMessage>, item: OutboxItem) -> Result<(), String> {
+    sender
+        .send(SendText(
+            item.text,
+            item.channel_id,
+            item.reply_to_id,
+            item.packet_id,
+        ))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Return the request id, sender and error of a routing response, if the packet is one
+fn routing_response(packet: &FromRadio) -> Option<(u32, u32, routing::Error)> {
+    if let Some(PayloadVariant::Packet(mesh_packet)) = &packet.payload_variant
+        && let Some(Decoded(data)) = &mesh_packet.payload_variant
+        && data.portnum == PortNum::RoutingApp as i32
+    {
+        Some((
+            data.request_id,
+            mesh_packet.from,
+            routing_error(&data.payload),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Connect to `device` and send `text` to `channel_id`, using an [Outbox] to retry it as the app
+/// does. A message too long for one packet is sent as numbered parts, in order.
+/// Return the final [DeliveryStatus] once every part is delivered, or an error if one could not be
+async fn send<S: Stream<Item = SubscriptionEvent> + Unpin>(
+    mut events: S,
+    device: DeviceId,
+    channel_id: ChannelId,
+    text: String,
+    message_retries: Option<u32>,
+) -> Result<DeliveryStatus, String> {
+    let sender = connect(&mut events, device).await?;
+    let mut outbox = Outbox::default();
+    outbox.set_max_retries(message_retries);
+    let items: Vec<OutboxItem> = message_parts::split(&text, MAX_MESSAGE_BYTES)
+        .into_iter()
+        .map(|part| {
+            let packet_id = generate_rand_id();
+            outbox.add(channel_id.clone(), packet_id, packet_id, part, None)
+        })
+        .collect();
+    for item in items {
+        send_item(&sender, item).await?;
+    }
+
+    let mut timeout_check = tokio::time::interval(OUTBOX_CHECK_PERIOD);
+    let result = 'delivering: loop {
+        let deliveries: Vec<Delivery> = tokio::select! {
+            event = events.next() => match event {
+                Some(MessageSent(packet_id)) => outbox.sent(packet_id).into_iter().collect(),
+                Some(DevicePacket(packet)) => match routing_response(&packet) {
+                    Some((request_id, from, error)) => {
+                        outbox.routing(request_id, from, error).into_iter().collect()
+                    }
+                    None => vec![],
+                },
+                // Send again what could not be sent while the link to the radio was down
+                Some(ConnectedEvent(_)) => {
+                    for item in outbox.queued() {
+                        send_item(&sender, item).await?;
+                    }
+                    vec![]
+                }
+                Some(event) => match connection_event(&event) {
+                    Ok(()) => vec![],
+                    Err(e) => break Err(e),
+                },
+                None => break Err("The connection to the radio ended".to_string()),
+            },
+            _ = timeout_check.tick() => outbox.check_timeouts(Instant::now()),
+        };
+
+        for delivery in deliveries {
+            match delivery {
+                // A part that has failed after all its retries means the message can't be read
+                Delivery::Update(_, _, status @ (Failed(_) | TimedOut)) => {
+                    break 'delivering Err(status.to_string());
+                }
+                // Once the last part leaves the outbox, the whole message has been delivered
+                Delivery::Update(_, _, status) if outbox.is_empty() => {
+                    break 'delivering Ok(status);
+                }
+                Delivery::Update(_, _, status) => eprintln!("{status}"),
+                Delivery::Retry(item) => {
+                    eprintln!("Retrying");
+                    send_item(&sender, item).await?;
+                }
+            }
+        }
+    };
+
+    disconnect(&mut events, &sender).await;
+    result
+}
+
+/// Connect to `device` and print the text messages received, as text or JSON lines, until
+/// interrupted or the connection is lost
+async fn listen<S: Stream<Item = SubscriptionEvent> + Unpin>(
+    mut events: S,
+    device: DeviceId,
+    config: &Config,
+    json: bool,
+) -> Result<(), String> {
+    let sender = connect(&mut events, device).await?;
+    let mut names = Names::default();
+
+    let result = loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+            event = events.next() => match event {
+                Some(DevicePacket(packet)) => {
+                    if let Some(message) = names.received(*packet, &config.aliases) {
+                        if json {
+                            match serde_json::to_string(&message) {
+                                Ok(line) => println!("{line}"),
+                                Err(e) => eprintln!("Could not convert message to JSON: {e}"),
+                            }
+                        } else {
+                            println!("{message}");
+                        }
+                    }
+                }
+                Some(event) => {
+                    if let Err(e) = connection_event(&event) {
+                        break Err(e);
+                    }
+                }
+                None => break Err("The connection to the radio ended".to_string()),
+            }
+        }
+    };
+
+    disconnect(&mut events, &sender).await;
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::channel_id::ChannelId;
+    use crate::channel_view_entry::DeliveryStatus;
+    use crate::cli::{Command, Destination, parse_args, send};
+    use crate::config::Config;
+    use crate::device_id::DeviceId;
+    use crate::device_subscription::subscribe_with;
+    use crate::message_parts::{MAX_MESSAGE_BYTES, split};
+    use crate::simulator::SimulatedRadio;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn no_command() {
+        assert!(parse_args(args("")).is_none());
+        assert!(parse_args(args("-psn_0_12345")).is_none());
+    }
+
+    #[test]
+    fn parse_send() {
+        assert_eq!(
+            parse_args(args("send --channel 2 Hello there")),
+            Some(Ok(Command::Send {
+                device: None,
+                destination: Destination::Channel(2),
+                text: Some("Hello there".into()),
+            }))
+        );
+        assert_eq!(
+            parse_args(args(
+                "send --device simulated --node Alice -- --not-an-option"
+            )),
+            Some(Ok(Command::Send {
+                device: Some(DeviceId::Simulated),
+                destination: Destination::Node("Alice".into()),
+                text: Some("--not-an-option".into()),
+            }))
+        );
+        assert_eq!(
+            parse_args(args("send")),
+            Some(Ok(Command::Send {
+                device: None,
+                destination: Destination::Channel(0),
+                text: None,
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(
+            parse_args(args("send --channel 8 Hi")),
+            Some(Err(_))
+        ));
+        assert!(matches!(parse_args(args("send --node")), Some(Err(_))));
+        assert!(matches!(
+            parse_args(args("send --device nonsense Hi")),
+            Some(Err(_))
+        ));
+        assert!(matches!(parse_args(args("listen --verbose")), Some(Err(_))));
+    }
+
+    #[test]
+    fn parse_listen() {
+        assert_eq!(
+            parse_args(args("listen --json --device tcp:localhost:4403")),
+            Some(Ok(Command::Listen {
+                device: Some(DeviceId::Tcp("localhost:4403".into())),
+                json: true,
+            }))
+        );
+    }
+
+    #[test]
+    fn resolve_destination() {
+        let mut config = Config::default();
+        config.aliases.insert(1234, "Base Camp".into());
+        let resolve = |node: &str| Destination::Node(node.into()).resolve(&config);
+
+        assert_eq!(resolve("1234"), Ok(ChannelId::Node(1234)));
+        assert_eq!(resolve("!000004d2"), Ok(ChannelId::Node(1234)));
+        assert_eq!(resolve("base camp"), Ok(ChannelId::Node(1234)));
+        assert!(resolve("Nobody").is_err());
+        assert_eq!(
+            Destination::Channel(1).resolve(&config),
+            Ok(ChannelId::Channel(1))
+        );
+    }
+
+    #[tokio::test]
+    async fn send_to_simulated_radio() {
+        let radio = SimulatedRadio::new(1000, "ME", "Me")
+            .node(1001, "ALIC", "Alice")
+            .node(1003, "CARL", "Carol")
+            .unreachable(1003);
+
+        let status = send(
+            Box::pin(subscribe_with(radio.clone())),
+            DeviceId::Simulated,
+            ChannelId::Node(1001),
+            "Hi Alice".into(),
+            Some(0),
+        )
+        .await;
+        assert_eq!(status, Ok(DeliveryStatus::Acked));
+
+        let status = send(
+            Box::pin(subscribe_with(radio)),
+            DeviceId::Simulated,
+            ChannelId::Node(1003),
+            "Hi Carol".into(),
+            Some(0),
+        )
+        .await;
+        assert_eq!(status, Err("Failed: max retransmit".into()));
+    }
+
+    #[tokio::test]
+    async fn send_long_message_to_simulated_radio() {
+        let radio = SimulatedRadio::new(1000, "ME", "Me")
+            .node(1001, "ALIC", "Alice")
+            .node(1003, "CARL", "Carol")
+            .unreachable(1003);
+        let text = "All work and no play makes Jack a dull boy. ".repeat(12);
+        assert_eq!(split(&text, MAX_MESSAGE_BYTES).len(), 3);
+
+        let status = send(
+            Box::pin(subscribe_with(radio.clone())),
+            DeviceId::Simulated,
+            ChannelId::Node(1001),
+            text.clone(),
+            Some(0),
+        )
+        .await;
+        assert_eq!(status, Ok(DeliveryStatus::Acked));
+
+        let status = send(
+            Box::pin(subscribe_with(radio)),
+            DeviceId::Simulated,
+            ChannelId::Node(1003),
+            text,
+            Some(0),
+        )
+        .await;
+        assert_eq!(status, Err("Failed: max retransmit".into()));
+    }
+}
@@ -0,0 +1,94 @@
+//! The keys used to send a message from the multi-line composer, and cleaning up of text pasted
+//! into it
+use iced::keyboard::Key;
+use iced::keyboard::key::Named;
+use iced::widget::text_editor::{Binding, KeyPress, Status};
+use serde::{Deserialize, Serialize};
+
+/// The key that sends the message being composed, set by `send_key` in the config file.
+/// Shift+Enter always starts a new line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SendKey {
+    /// Enter sends the message
+    #[default]
+    Enter,
+    /// Ctrl+Enter (Cmd+Enter on macOS) sends the message, and Enter starts a new line
+    CtrlEnter,
+}
+
+/// Return what a key press in the composer does, producing `send` when it is the `send_key`,
+/// and the default editing binding otherwise
+pub fn key_binding<Message>(
+    key_press: KeyPress,
+    send_key: SendKey,
+    send: Message,
+) -> Option<Binding<Message>> {
+    if key_press.key == Key::Named(Named::Enter)
+        && matches!(key_press.status, Status::Focused { .. })
+    {
+        let modifiers = key_press.modifiers;
+        let sends = match send_key {
+            SendKey::Enter => !modifiers.shift() && !modifiers.command(),
+            SendKey::CtrlEnter => modifiers.command(),
+        };
+        if sends {
+            return Some(Binding::Custom(send));
+        }
+    }
+    Binding::from_key_press(key_press)
+}
+
+/// Clean up text pasted into the composer: line endings become "\n", tabs become spaces and other
+/// control and invisible formatting characters, that other apps may not show, are removed
+pub fn clean_pasted(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\t', " ")
+        .chars()
+        .filter(|c| *c == '\n' || !(c.is_control() || *c == '\u{feff}' || *c == '\u{200b}'))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::composer::{SendKey, clean_pasted, key_binding};
+    use iced::keyboard::key::{Code, Named, Physical};
+    use iced::keyboard::{Key, Modifiers};
+    use iced::widget::text_editor::{Binding, KeyPress, Status};
+
+    fn enter(modifiers: Modifiers) -> KeyPress {
+        KeyPress {
+            key: Key::Named(Named::Enter),
+            modified_key: Key::Named(Named::Enter),
+            physical_key: Physical::Code(Code::Enter),
+            modifiers,
+            text: Some("\r".into()),
+            status: Status::Focused { is_hovered: false },
+        }
+    }
+
+    #[test]
+    fn enter_sends() {
+        let send = |modifiers| key_binding(enter(modifiers), SendKey::Enter, "send");
+        assert_eq!(send(Modifiers::empty()), Some(Binding::Custom("send")));
+        assert_eq!(send(Modifiers::SHIFT), Some(Binding::Enter));
+    }
+
+    #[test]
+    fn ctrl_enter_sends() {
+        let send = |modifiers| key_binding(enter(modifiers), SendKey::CtrlEnter, "send");
+        assert_eq!(send(Modifiers::empty()), Some(Binding::Enter));
+        assert_eq!(send(Modifiers::SHIFT), Some(Binding::Enter));
+        assert_eq!(send(Modifiers::COMMAND), Some(Binding::Custom("send")));
+    }
+
+    #[test]
+    fn pasted_text_cleaned() {
+        assert_eq!(
+            clean_pasted("Line 1\r\nLine 2\r\tTabbed\u{7}\u{feff}"),
+            "Line 1\nLine 2\n Tabbed"
+        );
+        assert_eq!(clean_pasted("Hi 😀"), "Hi 😀");
+    }
+}
@@ -1,8 +1,11 @@
 use crate::Message;
 use crate::channel_id::ChannelId;
-use btleplug::api::BDAddr;
+use crate::composer::SendKey;
+use crate::device_id::DeviceId;
+use crate::distance::DistanceUnit;
 use directories::ProjectDirs;
 use iced::Task;
+use meshtastic::protobufs::config::device_config::Role;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::io;
@@ -13,13 +16,47 @@ use tokio::io::AsyncWriteExt;
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
-    pub device_mac_address: Option<BDAddr>,
+    #[serde(alias = "device_mac_address")]
+    pub device: Option<DeviceId>,
     pub channel_id: Option<ChannelId>,
     pub fav_nodes: HashSet<u32>,
     #[serde(default = "HashMap::new")]
     pub aliases: HashMap<u32, String>, // node name aliases
     #[serde(default = "HashMap::new")]
-    pub device_aliases: HashMap<BDAddr, String>, // node name aliases
+    pub device_aliases: HashMap<DeviceId, String>, // device name aliases
+    #[serde(default = "Vec::new")]
+    pub tcp_devices: Vec<DeviceId>, // manually added network attached radios
+    pub message_retries: Option<u32>, // times to resend a message that fails, None for default
+    #[serde(default)]
+    pub simulated_radio: bool, // list the simulated radio, to demo the app without a radio
+    pub bridge_port: Option<u16>, // localhost port for other programs to use the radio, if enabled
+    #[serde(default)]
+    pub distance_unit: DistanceUnit, // units to show distances to other nodes in
+    #[serde(default = "HashSet::new")]
+    pub hidden_roles: HashSet<String>, // roles of nodes not to list, e.g. "ROUTER"
+    pub online_minutes: Option<u32>, // nodes heard this recently are online, None for default
+    pub offline_minutes: Option<u32>, // nodes not heard for this long are offline, None for default
+    #[serde(default)]
+    pub join_message_parts: bool, // join the numbered parts of long received messages into one
+    #[serde(default)]
+    pub send_key: SendKey, // key that sends the message being typed, "enter" or "ctrl-enter"
+}
+
+impl Config {
+    /// Return true if nodes with this role are hidden from the list of nodes
+    pub fn role_hidden(&self, role: i32) -> bool {
+        Role::try_from(role).is_ok_and(|role| self.hidden_roles.contains(role.as_str_name()))
+    }
+
+    /// Hide nodes with this role from the list of nodes, or show them if they are hidden
+    pub fn toggle_role_hidden(&mut self, role: i32) {
+        if let Ok(role) = Role::try_from(role) {
+            let name = role.as_str_name().to_string();
+            if !self.hidden_roles.remove(&name) {
+                self.hidden_roles.insert(name);
+            }
+        }
+    }
 }
 
 // Private methods for async reading and writing of config files
@@ -103,17 +140,37 @@ pub fn load_config() -> Task<Message> {
     }
 }
 
+/// Use `read_config` to read the config from disk outside the UI, e.g. in the command line mode.
+/// A missing config file gives the default config
+pub async fn read_config() -> io::Result<Config> {
+    match ProjectDirs::from("net", "Mackenzie Serres", "meshchat") {
+        Some(proj_dirs) => {
+            let config_path = proj_dirs.config_dir().join("config.toml");
+            if config_path.exists() {
+                load(config_path).await
+            } else {
+                Ok(Config::default())
+            }
+        }
+        None => Ok(Config::default()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::config::{Config, load, save};
+    use crate::device_id::DeviceId;
+    use crate::distance::DistanceUnit;
     use btleplug::api::BDAddr;
+    use meshtastic::protobufs::config::device_config::Role;
 
     fn assert_default(config: Config) {
-        assert!(config.device_mac_address.is_none());
+        assert!(config.device.is_none());
         assert!(config.channel_id.is_none());
         assert!(config.fav_nodes.is_empty());
         assert!(config.aliases.is_empty());
         assert!(config.device_aliases.is_empty());
+        assert!(config.tcp_devices.is_empty());
     }
 
     #[test]
@@ -152,7 +209,55 @@ mod tests {
     #[tokio::test]
     async fn mac_address_saved() {
         let config = Config {
-            device_mac_address: Some(BDAddr::from([0, 1, 2, 3, 4, 6])),
+            device: Some(DeviceId::Ble(BDAddr::from([0, 1, 2, 3, 4, 6]))),
+            ..Default::default()
+        };
+
+        let tempfile = tempfile::Builder::new()
+            .prefix("meshchat")
+            .tempdir()
+            .expect("Could not create a temp file for test");
+        save(tempfile.path().join("config.toml"), config.clone())
+            .await
+            .expect("Could not save config file");
+
+        let returned = load(tempfile.path().join("config.toml"))
+            .await
+            .expect("Could not load config file");
+        assert_eq!(
+            returned.device,
+            Some(DeviceId::Ble(BDAddr::from([0, 1, 2, 3, 4, 6])))
+        );
+    }
+
+    #[tokio::test]
+    async fn serial_port_saved() {
+        let config = Config {
+            device: Some(DeviceId::Serial("/dev/ttyACM0".into())),
+            ..Default::default()
+        };
+
+        let tempfile = tempfile::Builder::new()
+            .prefix("meshchat")
+            .tempdir()
+            .expect("Could not create a temp file for test");
+        save(tempfile.path().join("config.toml"), config.clone())
+            .await
+            .expect("Could not save config file");
+
+        let returned = load(tempfile.path().join("config.toml"))
+            .await
+            .expect("Could not load config file");
+        assert_eq!(
+            returned.device,
+            Some(DeviceId::Serial("/dev/ttyACM0".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn tcp_devices_saved() {
+        let config = Config {
+            tcp_devices: vec![DeviceId::tcp("localhost")],
             ..Default::default()
         };
 
@@ -168,8 +273,44 @@ mod tests {
             .await
             .expect("Could not load config file");
         assert_eq!(
-            returned.device_mac_address,
-            Some(BDAddr::from([0, 1, 2, 3, 4, 6]))
+            returned.tcp_devices,
+            vec![DeviceId::Tcp("localhost:4403".into())]
+        );
+    }
+
+    #[test]
+    fn old_mac_address_config_loads() {
+        let config: Config =
+            toml::from_str("device_mac_address = \"00:01:02:03:04:06\"\nfav_nodes = []\n")
+                .expect("Could not parse old config format");
+        assert_eq!(
+            config.device,
+            Some(DeviceId::Ble(BDAddr::from([0, 1, 2, 3, 4, 6])))
         );
     }
+
+    #[test]
+    fn distance_unit() {
+        let config: Config = toml::from_str("fav_nodes = []").expect("Could not parse config");
+        assert_eq!(config.distance_unit, DistanceUnit::Kilometres);
+
+        let config: Config = toml::from_str("fav_nodes = []\ndistance_unit = \"miles\"")
+            .expect("Could not parse config");
+        assert_eq!(config.distance_unit, DistanceUnit::Miles);
+    }
+
+    #[test]
+    fn hidden_roles() {
+        let mut config = Config::default();
+        assert!(!config.role_hidden(Role::Router as i32));
+
+        config.toggle_role_hidden(Role::Router as i32);
+        assert!(config.role_hidden(Role::Router as i32));
+        assert!(!config.role_hidden(Role::Client as i32));
+        let saved = toml::to_string(&config).expect("Could not save config");
+        assert!(saved.contains("hidden_roles = [\"ROUTER\"]"));
+
+        config.toggle_role_hidden(Role::Router as i32);
+        assert!(!config.role_hidden(Role::Router as i32));
+    }
 }
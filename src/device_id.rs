@@ -0,0 +1,172 @@
+use crate::device_id::DeviceId::{Ble, Serial, Simulated, Tcp};
+use btleplug::api::BDAddr;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::hash::Hash;
+use std::str::FromStr;
+
+const SERIAL_PREFIX: &str = "serial:";
+const TCP_PREFIX: &str = "tcp:";
+const SIMULATED: &str = "simulated";
+
+/// The TCP port meshtastic radios and `meshtasticd` listen on by default
+pub const DEFAULT_TCP_PORT: u16 = 4403;
+
+/// Identifies a radio and the transport used to reach it
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum DeviceId {
+    Ble(BDAddr),    // Bluetooth LE MAC address
+    Serial(String), // Serial port name, e.g. "/dev/ttyACM0" or "COM3"
+    Tcp(String),    // Network address as "host:port", e.g. "192.168.1.20:4403"
+    Simulated,      // The simulated radio, for development and demos
+}
+
+impl Display for DeviceId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ble(mac_address) => write!(f, "{}", mac_address),
+            Serial(port_name) => f.write_str(port_name),
+            Tcp(address) => f.write_str(address),
+            Simulated => f.write_str("Simulated Radio"),
+        }
+    }
+}
+
+impl DeviceId {
+    /// Create a [DeviceId] for a network attached radio from an address entered by the user,
+    /// adding the default meshtastic port if none was given.
+    /// IPv6 addresses with a port are written as "[addr]:port", and a bare IPv6 address with
+    /// several colons and no brackets is taken to have no port.
+    pub fn tcp(address: &str) -> Self {
+        let address = address.trim();
+        if address.matches(':').count() > 1 && !address.starts_with('[') {
+            return Tcp(format!("[{address}]:{DEFAULT_TCP_PORT}"));
+        }
+        let has_port = address
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+        if has_port {
+            Tcp(address.to_string())
+        } else {
+            Tcp(format!("{address}:{DEFAULT_TCP_PORT}"))
+        }
+    }
+
+    /// Return the string used to store this [DeviceId] in the config file.
+    /// BLE devices are stored as a plain MAC address, as they were before other transports existed
+    fn to_config_string(&self) -> String {
+        match self {
+            Ble(mac_address) => mac_address.to_string(),
+            Serial(port_name) => format!("{SERIAL_PREFIX}{port_name}"),
+            Tcp(address) => format!("{TCP_PREFIX}{address}"),
+            Simulated => SIMULATED.to_string(),
+        }
+    }
+}
+
+impl FromStr for DeviceId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(port_name) = s.strip_prefix(SERIAL_PREFIX) {
+            Ok(Serial(port_name.to_string()))
+        } else if let Some(address) = s.strip_prefix(TCP_PREFIX) {
+            Ok(Tcp(address.to_string()))
+        } else if s == SIMULATED {
+            Ok(Simulated)
+        } else {
+            BDAddr::from_str(s)
+                .map(Ble)
+                .map_err(|e| format!("Invalid device id '{s}': {e}"))
+        }
+    }
+}
+
+impl Serialize for DeviceId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_config_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for DeviceId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        DeviceId::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::device_id::DeviceId;
+    use btleplug::api::BDAddr;
+    use std::str::FromStr;
+
+    #[test]
+    fn ble_round_trip() {
+        let device_id = DeviceId::Ble(BDAddr::from([0, 1, 2, 3, 4, 6]));
+        let config_string = device_id.to_config_string();
+        assert_eq!(config_string, "00:01:02:03:04:06");
+        assert_eq!(DeviceId::from_str(&config_string), Ok(device_id));
+    }
+
+    #[test]
+    fn serial_round_trip() {
+        let device_id = DeviceId::Serial("/dev/ttyACM0".into());
+        let config_string = device_id.to_config_string();
+        assert_eq!(config_string, "serial:/dev/ttyACM0");
+        assert_eq!(DeviceId::from_str(&config_string), Ok(device_id));
+    }
+
+    #[test]
+    fn tcp_round_trip() {
+        let device_id = DeviceId::Tcp("localhost:4403".into());
+        let config_string = device_id.to_config_string();
+        assert_eq!(config_string, "tcp:localhost:4403");
+        assert_eq!(DeviceId::from_str(&config_string), Ok(device_id));
+    }
+
+    #[test]
+    fn simulated_round_trip() {
+        let config_string = DeviceId::Simulated.to_config_string();
+        assert_eq!(config_string, "simulated");
+        assert_eq!(DeviceId::from_str(&config_string), Ok(DeviceId::Simulated));
+    }
+
+    #[test]
+    fn tcp_default_port() {
+        assert_eq!(
+            DeviceId::tcp("meshtastic.local"),
+            DeviceId::Tcp("meshtastic.local:4403".into())
+        );
+    }
+
+    #[test]
+    fn tcp_explicit_port() {
+        assert_eq!(
+            DeviceId::tcp(" 192.168.1.20:4000 "),
+            DeviceId::Tcp("192.168.1.20:4000".into())
+        );
+    }
+
+    #[test]
+    fn tcp_ipv6() {
+        assert_eq!(
+            DeviceId::tcp("fe80::1"),
+            DeviceId::Tcp("[fe80::1]:4403".into())
+        );
+        assert_eq!(
+            DeviceId::tcp("[fe80::1]"),
+            DeviceId::Tcp("[fe80::1]:4403".into())
+        );
+        assert_eq!(
+            DeviceId::tcp("[fe80::1]:4000"),
+            DeviceId::Tcp("[fe80::1]:4000".into())
+        );
+    }
+
+    #[test]
+    fn invalid_device_id() {
+        assert!(DeviceId::from_str("not a device").is_err());
+    }
+}
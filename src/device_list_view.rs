@@ -1,16 +1,20 @@
 use crate::Message::{
-    AddDeviceAlias, DeviceListViewEvent, DeviceViewEvent, Navigation, RemoveDeviceAlias,
+    AddDeviceAlias, AddTcpDevice, DeviceListViewEvent, DeviceViewEvent, Navigation,
+    RemoveDeviceAlias, RemoveTcpDevice,
 };
 use crate::config::Config;
+use crate::device_id::DeviceId;
 use crate::device_list_view::DeviceListEvent::{
-    BLERadioFound, BLERadioLost, Error, StartEditingAlias,
+    BLERadioFound, BLERadioLost, Error, SerialRadioFound, SerialRadioLost, StartEditingAlias,
+    TcpAddressInput,
 };
 use crate::device_view::ConnectionState;
-use crate::device_view::ConnectionState::{Connected, Connecting, Disconnected, Disconnecting};
+use crate::device_view::ConnectionState::{
+    Connected, Connecting, Disconnected, Disconnecting, Reconnecting,
+};
 use crate::device_view::DeviceViewMessage::{ConnectRequest, DisconnectRequest};
 use crate::styles::{button_chip_style, menu_button_style, text_input_style, tooltip_style};
 use crate::{Message, View};
-use btleplug::api::BDAddr;
 use futures_channel::mpsc::Sender;
 use iced::futures::{SinkExt, Stream};
 use iced::widget::scrollable::Scrollbar;
@@ -19,8 +23,9 @@ use iced::widget::{
 };
 use iced::{Bottom, stream};
 use iced::{Center, Element, Fill, Renderer, Task, Theme, alignment};
+use iced_aw::menu::Item;
 use iced_aw::{Menu, MenuBar, menu_bar, menu_items};
-use meshtastic::utils::stream::{BleDevice, available_ble_devices};
+use meshtastic::utils::stream::{BleDevice, available_ble_devices, available_serial_ports};
 use std::collections::HashMap;
 use std::time::Duration;
 
@@ -28,16 +33,20 @@ use std::time::Duration;
 pub enum DeviceListEvent {
     BLERadioFound(BleDevice),
     BLERadioLost(BleDevice),
+    SerialRadioFound(String), // Serial port name
+    SerialRadioLost(String),  // Serial port name
     Error(String),
-    StartEditingAlias(BDAddr),
-    AliasInput(String), // From text_input
+    StartEditingAlias(DeviceId),
+    AliasInput(String),      // From text_input
+    TcpAddressInput(String), // From text_input
 }
 
 #[derive(Default)]
 pub struct DeviceListView {
-    device_list: HashMap<BDAddr, String>,
+    device_list: HashMap<DeviceId, String>,
     alias: String,
-    editing_alias: Option<BDAddr>,
+    editing_alias: Option<DeviceId>,
+    tcp_address: String,
 }
 
 async fn empty() {}
@@ -47,7 +56,7 @@ impl DeviceListView {
         match device_list_event {
             BLERadioFound(device) => {
                 if let std::collections::hash_map::Entry::Vacant(e) =
-                    self.device_list.entry(device.mac_address)
+                    self.device_list.entry(DeviceId::Ble(device.mac_address))
                 {
                     e.insert(
                         device
@@ -58,7 +67,15 @@ impl DeviceListView {
                 }
             }
             BLERadioLost(device) => {
-                let _ = self.device_list.remove(&device.mac_address);
+                let _ = self.device_list.remove(&DeviceId::Ble(device.mac_address));
+            }
+            SerialRadioFound(port_name) => {
+                let _ = self
+                    .device_list
+                    .insert(DeviceId::Serial(port_name.clone()), port_name);
+            }
+            SerialRadioLost(port_name) => {
+                let _ = self.device_list.remove(&DeviceId::Serial(port_name));
             }
             Error(e) => {
                 return Task::perform(empty(), move |_| {
@@ -67,14 +84,32 @@ impl DeviceListView {
             }
             StartEditingAlias(device) => self.start_editing_alias(device),
             DeviceListEvent::AliasInput(alias) => self.alias = alias,
+            TcpAddressInput(address) => self.tcp_address = address,
         };
 
         Task::none()
     }
 
+    /// Add a manually configured network attached radio to the list of devices
+    pub fn add_tcp_device(&mut self, device_id: DeviceId) {
+        self.tcp_address = String::new();
+        self.add_device(device_id);
+    }
+
+    /// Add a device that is not discovered, such as a network attached or simulated radio
+    pub fn add_device(&mut self, device_id: DeviceId) {
+        let name = device_id.to_string();
+        let _ = self.device_list.insert(device_id, name);
+    }
+
+    /// Remove a manually configured network attached radio from the list of devices
+    pub fn remove_tcp_device(&mut self, device_id: &DeviceId) {
+        let _ = self.device_list.remove(device_id);
+    }
+
     /// Called when the user selects to alias a device name
-    fn start_editing_alias(&mut self, mac_address: BDAddr) {
-        self.editing_alias = Some(mac_address);
+    fn start_editing_alias(&mut self, device_id: DeviceId) {
+        self.editing_alias = Some(device_id);
         self.alias = String::new();
     }
 
@@ -87,15 +122,15 @@ impl DeviceListView {
     /// Return the device name or any alias to it that might exist in the config
     pub fn device_name_or_alias<'a>(
         &'a self,
-        mac_address: &'a BDAddr,
+        device_id: &'a DeviceId,
         config: &'a Config,
     ) -> String {
-        if let Some(alias) = config.device_aliases.get(mac_address) {
+        if let Some(alias) = config.device_aliases.get(device_id) {
             alias.to_string()
         } else {
             self.device_list
-                .get(mac_address)
-                .unwrap_or(&mac_address.to_string())
+                .get(device_id)
+                .unwrap_or(&device_id.to_string())
                 .to_string()
         }
     }
@@ -122,26 +157,34 @@ impl DeviceListView {
                 )))
                 .style(button_chip_style),
             ),
-            Connected(mac_address) => Row::new().push(
-                button(text(self.device_name_or_alias(mac_address, config)))
+            Connected(device_id) => Row::new().push(
+                button(text(self.device_name_or_alias(device_id, config)))
                     .style(button_chip_style)
                     .on_press(Navigation(View::Device(None))),
             ),
-            Disconnecting(mac_address) => Row::new().push(
+            Reconnecting(device_id, attempt) => Row::new().push(Space::new().width(Fill)).push(
+                button(text(format!(
+                    "Reconnecting to {} (attempt {attempt})",
+                    self.device_name_or_alias(device_id, config)
+                )))
+                .style(button_chip_style)
+                .on_press(Navigation(View::Device(None))),
+            ),
+            Disconnecting(device_id) => Row::new().push(
                 text(format!(
                     "Disconnecting from {}",
-                    self.device_name_or_alias(mac_address, config)
+                    self.device_name_or_alias(device_id, config)
                 ))
                 .width(Fill)
                 .align_x(alignment::Horizontal::Right),
             ),
         });
 
-        // Add a disconnect button on the right if we are connected
-        if let Connected(mac_address) = state {
+        // Add a disconnect button on the right if we are connected, or trying to reconnect
+        if let Connected(device_id) | Reconnecting(device_id, _) = state {
             header = header.push(Space::new().width(Fill)).push(
                 button("Disconnect")
-                    .on_press(DeviceViewEvent(DisconnectRequest(*mac_address, false)))
+                    .on_press(DeviceViewEvent(DisconnectRequest(device_id.clone(), false)))
                     .style(button_chip_style),
             )
         }
@@ -154,17 +197,56 @@ impl DeviceListView {
         config: &'a Config,
         connection_state: &'a ConnectionState,
     ) -> Element<'a, Message> {
-        if self.device_list.is_empty() {
-            return empty_view();
+        let device_list: Element<'a, Message> = if self.device_list.is_empty() {
+            empty_view()
+        } else {
+            self.device_list(config, connection_state)
+        };
+
+        Column::new()
+            .push(self.add_tcp_device_box())
+            .push(device_list)
+            .into()
+    }
+
+    /// Create a row where the user can enter the address of a network attached radio to add
+    fn add_tcp_device_box(&self) -> Element<'_, Message> {
+        let mut add_button = button(text("Add")).style(button_chip_style).padding([6, 8]);
+        if !self.tcp_address.trim().is_empty() {
+            add_button = add_button.on_press(AddTcpDevice(self.tcp_address.clone()));
         }
 
+        Row::new()
+            .push(
+                text_input(
+                    "Add a network radio by address (host or host:port)",
+                    &self.tcp_address,
+                )
+                .style(text_input_style)
+                .padding([6, 6])
+                .on_input(|s| DeviceListViewEvent(TcpAddressInput(s)))
+                .on_submit(AddTcpDevice(self.tcp_address.clone())),
+            )
+            .push(Space::new().width(4.0))
+            .push(add_button)
+            .padding([0, 4])
+            .align_y(Center)
+            .into()
+    }
+
+    /// Create the list of devices found or added, with buttons to connect to them
+    fn device_list<'a>(
+        &'a self,
+        config: &'a Config,
+        connection_state: &'a ConnectionState,
+    ) -> Element<'a, Message> {
         let mut main_col = Column::new();
 
-        for (mac_address, device_name) in &self.device_list {
+        for (device_id, device_name) in &self.device_list {
             let mut device_row = Row::new().align_y(Center).padding(2);
 
             let name_element: Element<'a, Message> =
-                if let Some(alias) = config.device_aliases.get(mac_address) {
+                if let Some(alias) = config.device_aliases.get(device_id) {
                     tooltip(
                         text(alias).width(250),
                         text(format!("Original device name: {}", device_name)),
@@ -172,13 +254,13 @@ impl DeviceListView {
                     )
                     .style(tooltip_style)
                     .into()
-                } else if let Some(editing_mac) = &self.editing_alias
-                    && editing_mac == mac_address
+                } else if let Some(editing_id) = &self.editing_alias
+                    && editing_id == device_id
                 {
                     text_input("Enter alias for this device", &self.alias)
                         .width(250)
                         .on_input(|s| DeviceListViewEvent(DeviceListEvent::AliasInput(s)))
-                        .on_submit(AddDeviceAlias(*editing_mac, self.alias.clone()))
+                        .on_submit(AddDeviceAlias(editing_id.clone(), self.alias.clone()))
                         .style(text_input_style)
                         .into()
                 } else {
@@ -189,17 +271,17 @@ impl DeviceListView {
             device_row = device_row.push(Space::new().width(6));
 
             device_row = device_row.push(Self::menu_bar(
-                mac_address,
-                config.device_aliases.contains_key(mac_address),
+                device_id,
+                config.device_aliases.contains_key(device_id),
             ));
 
             device_row = device_row.push(Space::new().width(6));
             match &connection_state {
-                Connected(connected_mac_address) => {
+                Connected(connected_device_id) => {
                     device_row = device_row.push(
                         button("Disconnect")
                             .on_press(DeviceViewEvent(DisconnectRequest(
-                                *connected_mac_address,
+                                connected_device_id.clone(),
                                 false,
                             )))
                             .style(button_chip_style),
@@ -208,17 +290,29 @@ impl DeviceListView {
                 Disconnected(_id, _error) => {
                     device_row = device_row.push(
                         button("Connect")
-                            .on_press(DeviceViewEvent(ConnectRequest(*mac_address, None)))
+                            .on_press(DeviceViewEvent(ConnectRequest(device_id.clone(), None)))
                             .style(button_chip_style),
                     );
                 }
-                Connecting(connecting_mac_address) => {
-                    if connecting_mac_address == mac_address {
+                Reconnecting(reconnecting_device_id, _) => {
+                    if reconnecting_device_id == device_id {
+                        device_row = device_row.push(
+                            button("Disconnect")
+                                .on_press(DeviceViewEvent(DisconnectRequest(
+                                    device_id.clone(),
+                                    false,
+                                )))
+                                .style(button_chip_style),
+                        );
+                    }
+                }
+                Connecting(connecting_device_id) => {
+                    if connecting_device_id == device_id {
                         device_row = device_row.push(button("Connecting").style(button_chip_style));
                     }
                 }
-                Disconnecting(disconnecting_mac_address) => {
-                    if disconnecting_mac_address == mac_address {
+                Disconnecting(disconnecting_device_id) => {
+                    if disconnecting_device_id == device_id {
                         device_row =
                             device_row.push(button("Disconnecting").style(button_chip_style));
                     }
@@ -244,27 +338,35 @@ impl DeviceListView {
     }
 
     fn menu_bar<'a>(
-        mac_address: &BDAddr,
+        device_id: &DeviceId,
         alias_exists: bool,
     ) -> MenuBar<'a, Message, Theme, Renderer> {
         let menu_tpl_1 = |items| Menu::new(items).spacing(3);
 
-        let menu_items = if alias_exists {
+        let mut menu_items = if alias_exists {
             menu_items!(
                 (menu_button(
                     "Unalias this device".into(),
-                    RemoveDeviceAlias(*mac_address)
+                    RemoveDeviceAlias(device_id.clone())
                 ))
             )
         } else {
             menu_items!(
                 (menu_button(
                     "Alias this device".into(),
-                    DeviceListViewEvent(StartEditingAlias(*mac_address))
+                    DeviceListViewEvent(StartEditingAlias(device_id.clone()))
                 ))
             )
         };
 
+        // Manually added network radios can also be removed from the list
+        if let DeviceId::Tcp(_) = device_id {
+            menu_items.push(Item::new(menu_button(
+                "Remove this device".into(),
+                RemoveTcpDevice(device_id.clone()),
+            )));
+        }
+
         // Create the menu bar with the root button and list of options
         menu_bar!((menu_root_button("▼"), {
             menu_tpl_1(menu_items).width(180)
@@ -351,3 +453,58 @@ pub fn ble_discovery() -> impl Stream<Item = DeviceListEvent> {
         },
     )
 }
+
+/// A stream of [DeviceListEvent] announcing the discovery or loss of radios attached via a
+/// serial port, such as USB connected radios
+pub fn serial_discovery() -> impl Stream<Item = DeviceListEvent> {
+    stream::channel(
+        100,
+        move |mut gui_sender: Sender<DeviceListEvent>| async move {
+            let mut port_names: Vec<String> = vec![];
+            let mut error_reported = false;
+
+            // loop polling for serial ports
+            loop {
+                match available_serial_ports() {
+                    Ok(port_names_now) => {
+                        // detect lost ports
+                        for port_name in &port_names {
+                            if !port_names_now.contains(port_name) {
+                                // inform GUI of a device lost
+                                gui_sender
+                                    .send(SerialRadioLost(port_name.clone()))
+                                    .await
+                                    .unwrap_or_else(|e| eprintln!("Discovery gui send error: {e}"));
+                            }
+                        }
+
+                        // detect new ports found
+                        for port_name in &port_names_now {
+                            if !port_names.contains(port_name) {
+                                // inform GUI of a new device found
+                                gui_sender
+                                    .send(SerialRadioFound(port_name.clone()))
+                                    .await
+                                    .unwrap_or_else(|e| eprintln!("Discovery gui send error: {e}"));
+                            }
+                        }
+
+                        port_names = port_names_now;
+                        error_reported = false;
+                    }
+                    // Only report the error once, until the ports can be listed again
+                    Err(e) if !error_reported => {
+                        error_reported = true;
+                        gui_sender
+                            .send(Error(e.to_string()))
+                            .await
+                            .unwrap_or_else(|e| eprintln!("Discovery gui send error: {e}"));
+                    }
+                    Err(_) => {}
+                }
+
+                tokio::time::sleep(Duration::from_secs(4)).await;
+            }
+        },
+    )
+}
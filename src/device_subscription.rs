@@ -1,28 +1,36 @@
 use crate::channel_id::ChannelId;
-use crate::device_subscription::DeviceState::{Connected, Disconnected};
+use crate::device_id::DeviceId;
+use crate::device_subscription::DeviceState::{Connected, Disconnected, Reconnecting};
 use crate::device_subscription::SubscriberMessage::{
-    Connect, Disconnect, RadioPacket, SendEmojiReply, SendInfo, SendPosition, SendText,
+    Connect, Disconnect, RadioLost, RadioPacket, SendEmojiReply, SendInfo, SendPosition, SendText,
+    SendTraceroute, SendWaypoint,
 };
 use crate::device_subscription::SubscriptionEvent::{
     ConnectedEvent, ConnectionError, DeviceMeshPacket, DevicePacket, DisconnectedEvent,
+    MessageSent, ReconnectingEvent,
 };
-use btleplug::api::BDAddr;
+use crate::simulator::SimulatedRadio;
 use futures::SinkExt;
 use iced::stream;
-use meshtastic::api::{ConnectedStreamApi, StreamApi};
+use meshtastic::api::{ConnectedStreamApi, StreamApi, StreamHandle};
 use meshtastic::errors::Error;
-use meshtastic::packet::{PacketReceiver, PacketRouter};
+use meshtastic::packet::{PacketDestination, PacketReceiver, PacketRouter};
 use meshtastic::protobufs::config::device_config::Role;
 use meshtastic::protobufs::from_radio::PayloadVariant::{
     Channel, ClientNotification, MyInfo, NodeInfo, Packet,
 };
-use meshtastic::protobufs::{FromRadio, MeshPacket, PortNum, Position, User};
-use meshtastic::types::NodeId;
+use meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded;
+use meshtastic::protobufs::{
+    Data, FromRadio, MeshPacket, PortNum, Position, RouteDiscovery, User, Waypoint, to_radio,
+};
+use meshtastic::types::{MeshChannel, NodeId};
 use meshtastic::utils::stream::BleId;
 use meshtastic::{Message, utils};
 use std::pin::Pin;
 use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::mpsc::{Sender, channel};
+use tokio::time::Instant;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use tokio_stream::{Stream, StreamExt};
 
@@ -30,27 +38,58 @@ use tokio_stream::{Stream, StreamExt};
 pub enum SubscriptionEvent {
     /// A message from the subscription to indicate it is ready to receive messages
     Ready(Sender<SubscriberMessage>),
-    ConnectedEvent(BDAddr),
-    DisconnectedEvent(BDAddr),
+    ConnectedEvent(DeviceId),
+    DisconnectedEvent(DeviceId),
+    /// The link to the radio was lost, or could not be made, and we are retrying (attempt number)
+    ReconnectingEvent(DeviceId, u32),
     DevicePacket(Box<FromRadio>),
     DeviceMeshPacket(Box<MeshPacket>),
-    ConnectionError(BDAddr, String, String),
+    /// A text message (with this packet id) has been sent to the radio
+    MessageSent(u32),
+    ConnectionError(DeviceId, String, String),
 }
 
 /// A message type sent from the UI to the subscriber
 pub enum SubscriberMessage {
-    Connect(BDAddr),
+    Connect(DeviceId),
     Disconnect,
-    SendText(String, ChannelId, Option<u32>), // Optional reply to message id
+    SendText(String, ChannelId, Option<u32>, u32), // Optional reply to message id, packet id
     SendEmojiReply(String, ChannelId, u32),
     SendPosition(ChannelId, Position),
-    SendInfo(ChannelId),
+    SendInfo(ChannelId, bool), // bool is to ask for their info in response
+    SendTraceroute(u32),       // node number to trace the route to
+    SendWaypoint(ChannelId, Waypoint),
     RadioPacket(Box<FromRadio>),
+    RadioLost, // Sent internally when the stream of packets from the radio ends
 }
 
 enum DeviceState {
     Disconnected,
-    Connected(BDAddr, PacketReceiver),
+    Connected(DeviceId, PacketReceiver),
+    Reconnecting(DeviceId, u32, Instant), // attempt number, and when to make it
+}
+
+/// The delay before the first attempt to reconnect, doubled on each following attempt
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// The longest we will wait between two attempts to reconnect
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+/// How many times we will try to reconnect before giving up and reporting an error
+const MAX_RECONNECT_ATTEMPTS: u32 = 10;
+
+/// Return how long to wait before reconnect `attempt` (starting at 1), backing off exponentially
+fn reconnect_delay(attempt: u32) -> Duration {
+    INITIAL_RECONNECT_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_RECONNECT_DELAY)
+}
+
+/// Return the [DeviceState] for waiting to make reconnect `attempt` to a device
+fn reconnecting(device_id: DeviceId, attempt: u32) -> DeviceState {
+    Reconnecting(
+        device_id,
+        attempt,
+        Instant::now() + reconnect_delay(attempt),
+    )
 }
 
 struct MyRouter {
@@ -130,9 +169,15 @@ impl PacketRouter<(), Error> for MyRouter {
     }
 }
 
-/// A stream of [DeviceViewMessage] announcing the discovery or loss of devices via BLE
-///
+/// A stream of [SubscriptionEvent] from the radio we connect to, with the default
+/// [SimulatedRadio] used when connecting to [DeviceId::Simulated]
 pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
+    subscribe_with(SimulatedRadio::default())
+}
+
+/// A stream of [SubscriptionEvent] from the radio we connect to, using `simulated_radio`
+/// when connecting to [DeviceId::Simulated]
+pub fn subscribe_with(simulated_radio: SimulatedRadio) -> impl Stream<Item = SubscriptionEvent> {
     stream::channel(
         100,
         move |mut gui_sender: futures_channel::mpsc::Sender<SubscriptionEvent>| async move {
@@ -140,6 +185,8 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
             let mut stream_api: Option<ConnectedStreamApi> = None;
             let mut my_router = MyRouter::new(gui_sender.clone());
             let (subscriber_sender, mut subscriber_receiver) = channel::<SubscriberMessage>(100);
+            // Messages from the UI while reconnecting, to send once we are connected again
+            let mut queued: Vec<SubscriberMessage> = vec![];
 
             // Send the event sender back to the GUI, so it can send messages
             let _ = gui_sender
@@ -159,35 +206,62 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                     Disconnected => {
                         // Wait for a message from the UI to request that we connect to a device
                         // No need to wait for any messages from a radio, as we are not connected to one
-                        if let Some(Connect(mac_address)) = subscriber_receiver.next().await {
-                            match do_connect(&mac_address).await {
-                                Ok((packet_receiver, stream)) => {
-                                    device_state = Connected(mac_address, packet_receiver);
-                                    stream_api = Some(stream);
-
-                                    gui_sender
-                                        .send(ConnectedEvent(mac_address))
-                                        .await
-                                        .unwrap_or_else(|e| eprintln!("Send error: {e}"));
-                                }
-                                Err(e) => {
-                                    gui_sender
-                                        .send(ConnectionError(
-                                            mac_address,
-                                            format!("Failed to connect to {}", mac_address),
-                                            e.to_string(),
-                                        ))
-                                        .await
-                                        .unwrap_or_else(|e| eprintln!("Send error: {e}"));
+                        if let Some(Connect(device_id)) = subscriber_receiver.next().await {
+                            let (state, api, event) =
+                                try_connect(device_id, 0, &simulated_radio).await;
+                            device_state = state;
+                            stream_api = api;
+                            gui_sender
+                                .send(event)
+                                .await
+                                .unwrap_or_else(|e| eprintln!("Send error: {e}"));
+                        }
+                    }
+                    Reconnecting(device_id, attempt, retry_at) => {
+                        // Wait until it is time to retry, unless the UI asks us to stop trying.
+                        // Messages from the UI meanwhile are queued, and do not delay the retry
+                        match tokio::time::timeout_at(retry_at, subscriber_receiver.next()).await {
+                            Ok(Some(Disconnect)) => {
+                                queued.clear();
+                                device_state = Disconnected;
+                                gui_sender
+                                    .send(DisconnectedEvent(device_id))
+                                    .await
+                                    .unwrap_or_else(|e| eprintln!("Send error: {e}"));
+                            }
+                            Ok(Some(Connect(_))) | Ok(None) => {
+                                eprintln!("Cannot connect while reconnecting");
+                                device_state = Reconnecting(device_id, attempt, retry_at);
+                            }
+                            Ok(Some(message)) => {
+                                queued.push(message);
+                                device_state = Reconnecting(device_id, attempt, retry_at);
+                            }
+                            Err(_) => {
+                                let (state, api, event) =
+                                    try_connect(device_id, attempt, &simulated_radio).await;
+                                if matches!(state, Disconnected) {
+                                    queued.clear();
                                 }
+                                device_state = state;
+                                stream_api = api;
+                                gui_sender
+                                    .send(event)
+                                    .await
+                                    .unwrap_or_else(|e| eprintln!("Send error: {e}"));
                             }
                         }
                     }
-                    Connected(mac_address, packet_receiver) => {
+                    Connected(device_id, packet_receiver) => {
                         let radio_stream = UnboundedReceiverStream::from(packet_receiver)
-                            .map(|fr| RadioPacket(Box::new(fr)));
+                            .map(|fr| RadioPacket(Box::new(fr)))
+                            .chain(tokio_stream::once(RadioLost));
 
-                        let mut merged_stream = radio_stream.merge(&mut subscriber_receiver);
+                        // Send any messages queued while reconnecting first
+                        let ui_stream = tokio_stream::iter(std::mem::take(&mut queued))
+                            .chain(&mut subscriber_receiver);
+                        let mut merged_stream = radio_stream.merge(ui_stream);
+                        let mut link_lost = false;
 
                         while let Some(message) = StreamExt::next(&mut merged_stream).await {
                             let result = match message {
@@ -196,17 +270,28 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                     Ok(())
                                 }
                                 Disconnect => break,
-                                SendText(text, channel_id, reply_to_id) => {
+                                RadioLost => {
+                                    link_lost = true;
+                                    break;
+                                }
+                                SendText(text, channel_id, reply_to_id, packet_id) => {
                                     let mut api = stream_api.take().unwrap();
                                     let r = send_text_message(
                                         &mut api,
-                                        &mut my_router,
+                                        &my_router,
                                         channel_id,
                                         reply_to_id,
                                         text,
+                                        packet_id,
                                     )
                                     .await;
                                     let _none = stream_api.replace(api);
+                                    if r.is_ok() {
+                                        gui_sender
+                                            .send(MessageSent(packet_id))
+                                            .await
+                                            .unwrap_or_else(|e| eprintln!("Send error: {e}"));
+                                    }
                                     r
                                 }
                                 SendPosition(channel_id, position) => {
@@ -221,9 +306,34 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                     let _none = stream_api.replace(api);
                                     r
                                 }
-                                SendInfo(channel_id) => {
+                                SendInfo(channel_id, want_response) => {
                                     let mut api = stream_api.take().unwrap();
-                                    let r = send_info(&mut api, &mut my_router, channel_id).await;
+                                    let r = send_info(
+                                        &mut api,
+                                        &mut my_router,
+                                        channel_id,
+                                        want_response,
+                                    )
+                                    .await;
+                                    let _none = stream_api.replace(api);
+                                    r
+                                }
+                                SendWaypoint(channel_id, waypoint) => {
+                                    let mut api = stream_api.take().unwrap();
+                                    let r = send_waypoint(
+                                        &mut api,
+                                        &mut my_router,
+                                        channel_id,
+                                        waypoint,
+                                    )
+                                    .await;
+                                    let _none = stream_api.replace(api);
+                                    r
+                                }
+                                SendTraceroute(node_num) => {
+                                    let mut api = stream_api.take().unwrap();
+                                    let r =
+                                        send_traceroute(&mut api, &mut my_router, node_num).await;
                                     let _none = stream_api.replace(api);
                                     r
                                 }
@@ -243,24 +353,26 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
                                 }
                             };
 
+                            // Failing to send to the radio means the link to it has gone
                             if let Err(e) = result {
-                                gui_sender
-                                    .send(ConnectionError(
-                                        mac_address,
-                                        "Send error".to_string(),
-                                        e.to_string(),
-                                    ))
-                                    .await
-                                    .unwrap_or_else(|e| eprintln!("Send error: {e}"));
+                                eprintln!("Send error: {e}");
+                                link_lost = true;
+                                break;
                             }
                         }
 
                         // Disconnect
                         let api = stream_api.take().unwrap();
-                        device_state = Disconnected;
                         let _ = do_disconnect(api).await;
+                        let event = if link_lost {
+                            device_state = reconnecting(device_id.clone(), 1);
+                            ReconnectingEvent(device_id, 1)
+                        } else {
+                            device_state = Disconnected;
+                            DisconnectedEvent(device_id)
+                        };
                         gui_sender
-                            .send(DisconnectedEvent(mac_address))
+                            .send(event)
                             .await
                             .unwrap_or_else(|e| eprintln!("Send error: {e}"));
                     }
@@ -270,29 +382,40 @@ pub fn subscribe() -> impl Stream<Item = SubscriptionEvent> {
     )
 }
 
-/// Send a Text Message to the other node or the channel, which is possibly a reply
+/// Send a Text Message to the other node or the channel, which is possibly a reply.
+/// The packet id is chosen by the UI's outbox, so it can track the delivery of the message, and
+/// the message is not echoed back as the UI has already added it to the channel
 async fn send_text_message(
     stream_api: &mut ConnectedStreamApi,
-    my_router: &mut MyRouter,
+    my_router: &MyRouter,
     channel_id: ChannelId,
     reply_to_id: Option<u32>,
     text: String,
+    packet_id: u32,
 ) -> Result<(), Error> {
     let (packet_destination, mesh_channel) = channel_id.to_destination();
+    let to = match packet_destination {
+        PacketDestination::Node(node_id) => node_id.id(),
+        _ => u32::MAX, // Broadcast
+    };
+
+    let mesh_packet = MeshPacket {
+        payload_variant: Some(Decoded(Data {
+            portnum: PortNum::TextMessageApp as i32,
+            payload: text.into_bytes(),
+            reply_id: reply_to_id.unwrap_or(0),
+            ..Default::default()
+        })),
+        from: my_router.source_node_id().id(),
+        to,
+        id: packet_id,
+        want_ack: true,
+        channel: mesh_channel.channel(),
+        ..Default::default()
+    };
 
     stream_api
-        .send_mesh_packet(
-            my_router,
-            text.into_bytes().into(),
-            PortNum::TextMessageApp,
-            packet_destination,
-            mesh_channel,
-            true, // want_ack
-            false,
-            true, // echo_response - via PacketRouter
-            reply_to_id,
-            None, // Used for emoji reply! https://github.com/andrewdavidmackenzie/meshchat/issues/91
-        )
+        .send_to_radio_packet(Some(to_radio::PayloadVariant::Packet(mesh_packet)))
         .await
 }
 
@@ -347,11 +470,38 @@ async fn send_position(
         .await
 }
 
-/// Send a [User] info "ping" message to the channel or other node
+/// Send a [Waypoint] to the channel or other node, to create, update or delete it
+async fn send_waypoint(
+    stream_api: &mut ConnectedStreamApi,
+    my_router: &mut MyRouter,
+    channel_id: ChannelId,
+    waypoint: Waypoint,
+) -> Result<(), Error> {
+    let (packet_destination, mesh_channel) = channel_id.to_destination();
+
+    stream_api
+        .send_mesh_packet(
+            my_router,
+            waypoint.encode_to_vec().into(),
+            PortNum::WaypointApp,
+            packet_destination,
+            mesh_channel,
+            true, // want_ack
+            false,
+            true, // echo_response - via PacketRouter
+            None,
+            None,
+        )
+        .await
+}
+
+/// Send a [User] info "ping" message to the channel or other node, asking for theirs in response
+/// if `want_response` is true
 async fn send_info(
     stream_api: &mut ConnectedStreamApi,
     my_router: &mut MyRouter,
     channel_id: ChannelId,
+    want_response: bool,
 ) -> Result<(), Error> {
     let (packet_destination, mesh_channel) = channel_id.to_destination();
 
@@ -363,7 +513,7 @@ async fn send_info(
             packet_destination,
             mesh_channel,
             true, // want_ack
-            false,
+            want_response,
             true, // echo_response - via PacketRouter
             None,
             None,
@@ -371,16 +521,103 @@ async fn send_info(
         .await
 }
 
-/// Connect to a specific [BleDevice] and return a [PacketReceiver] that receives messages from the
-/// radio and a [ConnectedStreamApi] that can be used to send messages to the radio.
-async fn do_connect(mac_address: &BDAddr) -> Result<(PacketReceiver, ConnectedStreamApi), Error> {
-    let ble_stream = utils::stream::build_ble_stream::<BleId>(
-        BleId::from_mac_address(&mac_address.to_string()).unwrap(),
-        Duration::from_secs(4),
-    )
-    .await?;
+/// Send a traceroute request to a node, that it and the nodes on the way there and back will
+/// add themselves to
+async fn send_traceroute(
+    stream_api: &mut ConnectedStreamApi,
+    my_router: &mut MyRouter,
+    node_num: u32,
+) -> Result<(), Error> {
+    stream_api
+        .send_mesh_packet(
+            my_router,
+            RouteDiscovery::default().encode_to_vec().into(),
+            PortNum::TracerouteApp,
+            PacketDestination::Node(NodeId::from(node_num)),
+            MeshChannel::default(),
+            false, // want_ack
+            true,  // want_response - the route
+            false,
+            None,
+            None,
+        )
+        .await
+}
+
+/// Try to connect to a device, where `attempt` is the number of reconnect attempts made so far,
+/// or 0 for the first connection to it.
+/// Return the new [DeviceState], the [ConnectedStreamApi] if connected, and the event to send
+/// to the GUI. If reconnecting after the link was lost fails, it will be retried until
+/// [MAX_RECONNECT_ATTEMPTS] have been made, but failing to connect at first is reported at once.
+async fn try_connect(
+    device_id: DeviceId,
+    attempt: u32,
+    simulated_radio: &SimulatedRadio,
+) -> (DeviceState, Option<ConnectedStreamApi>, SubscriptionEvent) {
+    match do_connect(&device_id, simulated_radio).await {
+        Ok((packet_receiver, stream_api)) => (
+            Connected(device_id.clone(), packet_receiver),
+            Some(stream_api),
+            ConnectedEvent(device_id),
+        ),
+        Err(e) if attempt > 0 && attempt < MAX_RECONNECT_ATTEMPTS => {
+            eprintln!("Failed to connect to {device_id}: {e}");
+            (
+                reconnecting(device_id.clone(), attempt + 1),
+                None,
+                ReconnectingEvent(device_id, attempt + 1),
+            )
+        }
+        Err(e) => (
+            Disconnected,
+            None,
+            ConnectionError(
+                device_id.clone(),
+                format!("Failed to connect to {}", device_id),
+                e.to_string(),
+            ),
+        ),
+    }
+}
+
+/// Connect to a specific device, using the transport its [DeviceId] specifies, and return a
+/// [PacketReceiver] that receives messages from the radio and a [ConnectedStreamApi] that can be
+/// used to send messages to the radio.
+async fn do_connect(
+    device_id: &DeviceId,
+    simulated_radio: &SimulatedRadio,
+) -> Result<(PacketReceiver, ConnectedStreamApi), Error> {
+    match device_id {
+        DeviceId::Ble(mac_address) => {
+            let ble_stream = utils::stream::build_ble_stream::<BleId>(
+                BleId::from_mac_address(&mac_address.to_string()).unwrap(),
+                Duration::from_secs(4),
+            )
+            .await?;
+            connect_stream(ble_stream).await
+        }
+        DeviceId::Serial(port_name) => {
+            let serial_stream =
+                utils::stream::build_serial_stream(port_name.clone(), None, None, None)?;
+            connect_stream(serial_stream).await
+        }
+        DeviceId::Tcp(address) => {
+            let tcp_stream = utils::stream::build_tcp_stream(address.clone()).await?;
+            connect_stream(tcp_stream).await
+        }
+        DeviceId::Simulated => connect_stream(simulated_radio.connect()?).await,
+    }
+}
+
+/// Connect to a radio over an already opened stream, whatever the transport, and configure it
+async fn connect_stream<S>(
+    stream_handle: StreamHandle<S>,
+) -> Result<(PacketReceiver, ConnectedStreamApi), Error>
+where
+    S: AsyncReadExt + AsyncWriteExt + Send + 'static,
+{
     let stream_api = StreamApi::new();
-    let (packet_receiver, stream_api) = stream_api.connect(ble_stream).await;
+    let (packet_receiver, stream_api) = stream_api.connect(stream_handle).await;
     let config_id = utils::generate_rand_id();
     let stream_api = stream_api.configure(config_id).await?;
     Ok((packet_receiver, stream_api))
@@ -390,3 +627,299 @@ async fn do_connect(mac_address: &BDAddr) -> Result<(PacketReceiver, ConnectedSt
 async fn do_disconnect(stream_api: ConnectedStreamApi) -> Result<StreamApi, Error> {
     stream_api.disconnect().await
 }
+
+#[cfg(all(test, unix))]
+mod test {
+    use crate::channel_id::ChannelId;
+    use crate::device_id::DeviceId;
+    use crate::device_subscription::SubscriberMessage::{Connect, Disconnect, SendText};
+    use crate::device_subscription::SubscriptionEvent::{
+        ConnectedEvent, ConnectionError, DisconnectedEvent, MessageSent, Ready, ReconnectingEvent,
+    };
+    use crate::device_subscription::{
+        SubscriptionEvent, connect_stream, do_connect, do_disconnect, reconnect_delay, subscribe,
+    };
+    use crate::simulator::SimulatedRadio;
+    use meshtastic::Message;
+    use meshtastic::api::StreamHandle;
+    use meshtastic::protobufs::from_radio::PayloadVariant::MyInfo;
+    use meshtastic::protobufs::to_radio::PayloadVariant::{Packet, WantConfigId};
+    use meshtastic::protobufs::{FromRadio, MyNodeInfo, ToRadio};
+    use std::time::Duration;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_serial::SerialStream;
+    use tokio_stream::{Stream, StreamExt};
+
+    /// Frame a packet the way it is sent over a stream, with magic bytes and length header
+    fn frame(payload: Vec<u8>) -> Vec<u8> {
+        let mut framed = vec![0x94, 0xc3, (payload.len() >> 8) as u8, payload.len() as u8];
+        framed.extend(payload);
+        framed
+    }
+
+    /// Read one framed [ToRadio] packet sent by the app
+    async fn read_to_radio<S: AsyncRead + Unpin>(radio: &mut S) -> ToRadio {
+        let mut header = [0u8; 4];
+        radio
+            .read_exact(&mut header)
+            .await
+            .expect("Fake radio could not read header");
+        assert_eq!(header[..2], [0x94, 0xc3], "Bad frame magic bytes");
+        let mut payload = vec![0u8; ((header[2] as usize) << 8) + header[3] as usize];
+        radio
+            .read_exact(&mut payload)
+            .await
+            .expect("Fake radio could not read payload");
+        ToRadio::decode(payload.as_slice()).expect("Fake radio could not decode packet")
+    }
+
+    /// A fake radio on one end of a stream (the master side of a pty pair, or a TCP connection),
+    /// that waits for the app to request its config and responds with its [MyNodeInfo].
+    /// The stream is returned so that it is kept open, as closing a pty discards any data not yet
+    /// read by the app
+    async fn fake_radio<S: AsyncRead + AsyncWrite + Unpin>(mut radio: S, my_node_num: u32) -> S {
+        let to_radio = read_to_radio(&mut radio).await;
+        assert!(matches!(to_radio.payload_variant, Some(WantConfigId(_))));
+
+        let my_info = FromRadio {
+            payload_variant: Some(MyInfo(MyNodeInfo {
+                my_node_num,
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        radio
+            .write_all(&frame(my_info.encode_to_vec()))
+            .await
+            .expect("Fake radio could not write");
+
+        radio
+    }
+
+    /// Wait for the next event from the subscription that is not a packet from the radio
+    async fn next_event<S: Stream<Item = SubscriptionEvent> + Unpin>(
+        events: &mut S,
+    ) -> SubscriptionEvent {
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(10), events.next())
+                .await
+                .expect("Timed out waiting for subscription event")
+                .expect("Subscription ended");
+            if !matches!(
+                event,
+                SubscriptionEvent::DevicePacket(_) | SubscriptionEvent::DeviceMeshPacket(_)
+            ) {
+                return event;
+            }
+        }
+    }
+
+    #[test]
+    fn reconnect_backoff() {
+        assert_eq!(reconnect_delay(1), Duration::from_secs(1));
+        assert_eq!(reconnect_delay(2), Duration::from_secs(2));
+        assert_eq!(reconnect_delay(4), Duration::from_secs(8));
+        assert_eq!(reconnect_delay(7), Duration::from_secs(60));
+        assert_eq!(reconnect_delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn reconnect_when_radio_drops() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind a local TCP port");
+        let address = listener
+            .local_addr()
+            .expect("Listener has no local address");
+        let fake_radio = tokio::spawn(async move {
+            // The first connection to the radio is dropped as soon as it has configured
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            drop(fake_radio(stream, 44).await);
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            fake_radio(stream, 44).await
+        });
+
+        let mut events = Box::pin(subscribe());
+        let Ready(sender) = next_event(&mut events).await else {
+            panic!("Subscription did not start with Ready");
+        };
+        let device_id = DeviceId::Tcp(address.to_string());
+        let _ = sender.send(Connect(device_id.clone())).await;
+
+        assert!(matches!(next_event(&mut events).await, ConnectedEvent(id) if id == device_id));
+        assert!(matches!(next_event(&mut events).await,
+            ReconnectingEvent(id, 1) if id == device_id));
+        assert!(matches!(next_event(&mut events).await, ConnectedEvent(id) if id == device_id));
+        // Let the radio be configured again before disconnecting from it
+        let radio = fake_radio.await.expect("Fake radio failed");
+
+        let _ = sender.send(Disconnect).await;
+        assert!(matches!(next_event(&mut events).await, DisconnectedEvent(id) if id == device_id));
+        drop(radio);
+    }
+
+    #[tokio::test]
+    async fn first_connect_failure_reported() {
+        // A port nothing is listening on
+        let address = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind a local TCP port")
+            .local_addr()
+            .expect("Listener has no local address");
+
+        let mut events = Box::pin(subscribe());
+        let Ready(sender) = next_event(&mut events).await else {
+            panic!("Subscription did not start with Ready");
+        };
+        let device_id = DeviceId::Tcp(address.to_string());
+        let _ = sender.send(Connect(device_id.clone())).await;
+        assert!(matches!(next_event(&mut events).await,
+            ConnectionError(id, _, _) if id == device_id));
+    }
+
+    #[tokio::test]
+    async fn messages_queued_while_reconnecting() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind a local TCP port");
+        let address = listener
+            .local_addr()
+            .expect("Listener has no local address");
+        let fake_radio = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            drop(fake_radio(stream, 44).await);
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            let mut radio = fake_radio(stream, 44).await;
+            let to_radio = read_to_radio(&mut radio).await;
+            (radio, to_radio)
+        });
+
+        let mut events = Box::pin(subscribe());
+        let Ready(sender) = next_event(&mut events).await else {
+            panic!("Subscription did not start with Ready");
+        };
+        let device_id = DeviceId::Tcp(address.to_string());
+        let _ = sender.send(Connect(device_id.clone())).await;
+        assert!(matches!(next_event(&mut events).await, ConnectedEvent(id) if id == device_id));
+        assert!(matches!(next_event(&mut events).await,
+            ReconnectingEvent(id, 1) if id == device_id));
+
+        // A message sent while reconnecting is sent once reconnected, and a busy UI sending
+        // more messages does not hold off reconnecting
+        let _ = sender
+            .send(SendText("Hello".into(), ChannelId::Channel(0), None, 1234))
+            .await;
+        let busy_sender = sender.clone();
+        let busy_device_id = device_id.clone();
+        let busy_ui = tokio::spawn(async move {
+            loop {
+                let _ = busy_sender.send(Connect(busy_device_id.clone())).await;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        });
+        assert!(matches!(next_event(&mut events).await, ConnectedEvent(id) if id == device_id));
+        busy_ui.abort();
+        assert!(matches!(next_event(&mut events).await, MessageSent(1234)));
+
+        let (radio, to_radio) = fake_radio.await.expect("Fake radio failed");
+        match to_radio.payload_variant {
+            Some(Packet(packet)) => assert_eq!(packet.id, 1234),
+            other => panic!("Unexpected packet sent to radio: {:?}", other),
+        }
+
+        let _ = sender.send(Disconnect).await;
+        assert!(matches!(next_event(&mut events).await, DisconnectedEvent(id) if id == device_id));
+        drop(radio);
+    }
+
+    #[tokio::test]
+    async fn connect_to_serial_radio() {
+        let (radio, port) = SerialStream::pair().expect("Could not create a pty pair");
+        let fake_radio = tokio::spawn(fake_radio(radio, 42));
+
+        // A pty does not support setting the DTR and RTS lines, so open the stream directly
+        let (mut packet_receiver, stream_api) = connect_stream(StreamHandle::from_stream(port))
+            .await
+            .expect("Could not connect to fake serial radio");
+
+        let packet = tokio::time::timeout(Duration::from_secs(5), packet_receiver.recv())
+            .await
+            .expect("Timed out waiting for packet from fake radio")
+            .expect("Packet channel closed");
+        match packet.payload_variant {
+            Some(MyInfo(my_info)) => assert_eq!(my_info.my_node_num, 42),
+            other => panic!("Unexpected packet from fake radio: {:?}", other),
+        }
+
+        let _ = do_disconnect(stream_api).await;
+        drop(fake_radio.await.expect("Fake radio failed"));
+    }
+
+    #[tokio::test]
+    async fn connect_to_tcp_radio() {
+        // A local stand-in for a network attached radio
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind a local TCP port");
+        let address = listener
+            .local_addr()
+            .expect("Listener has no local address");
+        let fake_radio = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("Could not accept");
+            fake_radio(stream, 43).await
+        });
+
+        let (mut packet_receiver, stream_api) = do_connect(
+            &DeviceId::Tcp(address.to_string()),
+            &SimulatedRadio::default(),
+        )
+        .await
+        .expect("Could not connect to fake TCP radio");
+
+        let packet = tokio::time::timeout(Duration::from_secs(5), packet_receiver.recv())
+            .await
+            .expect("Timed out waiting for packet from fake radio")
+            .expect("Packet channel closed");
+        match packet.payload_variant {
+            Some(MyInfo(my_info)) => assert_eq!(my_info.my_node_num, 43),
+            other => panic!("Unexpected packet from fake radio: {:?}", other),
+        }
+
+        let _ = do_disconnect(stream_api).await;
+        drop(fake_radio.await.expect("Fake radio failed"));
+    }
+
+    #[tokio::test]
+    async fn connect_to_missing_tcp_radio() {
+        // Find a free port, then close it so there is nothing listening there
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Could not bind a local TCP port");
+        let address = listener
+            .local_addr()
+            .expect("Listener has no local address");
+        drop(listener);
+
+        assert!(
+            do_connect(
+                &DeviceId::Tcp(address.to_string()),
+                &SimulatedRadio::default()
+            )
+            .await
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_to_missing_serial_port() {
+        assert!(
+            do_connect(
+                &DeviceId::Serial("/dev/meshchat-no-such-port".into()),
+                &SimulatedRadio::default()
+            )
+            .await
+            .is_err()
+        );
+    }
+}
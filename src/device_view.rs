@@ -1,23 +1,33 @@
 use crate::battery::{Battery, BatteryState};
+use crate::channel_view::ChannelViewMessage::MessageSeen;
 use crate::channel_view::{ChannelView, ChannelViewMessage};
 use crate::channel_view_entry::ChannelViewEntry;
+use crate::channel_view_entry::DeliveryStatus::Queued;
 use crate::channel_view_entry::Payload::{
     AlertMessage, EmojiReply, NewTextMessage, PositionMessage, TextMessageReply, UserMessage,
+    WaypointMessage,
 };
 use crate::config::Config;
 use crate::device_subscription::SubscriberMessage::{
-    Connect, Disconnect, SendEmojiReply, SendInfo, SendPosition, SendText,
+    Connect, Disconnect, SendEmojiReply, SendInfo, SendPosition, SendText, SendTraceroute,
+    SendWaypoint,
 };
 use crate::device_subscription::SubscriptionEvent::{
-    ConnectedEvent, ConnectionError, DeviceMeshPacket, DevicePacket, DisconnectedEvent, Ready,
+    ConnectedEvent, ConnectionError, DeviceMeshPacket, DevicePacket, DisconnectedEvent,
+    MessageSent, Ready, ReconnectingEvent,
 };
 use crate::device_subscription::{SubscriberMessage, SubscriptionEvent};
-use crate::device_view::ConnectionState::{Connected, Connecting, Disconnected, Disconnecting};
+use crate::device_view::ConnectionState::{
+    Connected, Connecting, Disconnected, Disconnecting, Reconnecting,
+};
 use crate::device_view::DeviceViewMessage::{
-    AliasInput, ChannelMsg, ClearFilter, ConnectRequest, DisconnectRequest, ForwardMessage,
-    SearchInput, SendEmojiReplyMessage, SendInfoMessage, SendPositionMessage, SendTextMessage,
-    ShowChannel, StartEditingAlias, StartForwardingMessage, StopForwardingMessage,
-    SubscriptionMessage,
+    AliasInput, ChannelMsg, CheckOutbox, ClearFilter, ConnectRequest, DeleteWaypoint,
+    DisconnectRequest, EditWaypoint, ForwardMessage, HistoryLoaded, MessageSearchMsg, NewWaypoint,
+    NodeListMsg, RefreshPresence, RequestNodeInfo, ResendMessage, SearchInput,
+    SendEmojiReplyMessage, SendInfoMessage, SendPositionMessage, SendTextMessage,
+    SendWaypointMessage, ShowChannel, ShowMessage, ShowNodeDetail, ShowNodes, ShowSearch,
+    StartEditingAlias, StartForwardingMessage, StopEditingWaypoint, StopForwardingMessage,
+    SubscriptionMessage, Traceroute, WaypointEditorMsg,
 };
 
 use crate::ConfigChangeMessage::DeviceAndChannel;
@@ -27,16 +37,30 @@ use crate::Message::{
 use crate::View::DeviceList;
 use crate::channel_id::ChannelId;
 use crate::channel_id::ChannelId::Node;
+use crate::device_id::DeviceId;
 use crate::device_list_view::DeviceListView;
+use crate::distance::{distance, distance_and_bearing_text};
+use crate::history::{ChannelHistory, load_history, save_history};
+use crate::map::NodeMap;
+use crate::message_parts::{self, MAX_MESSAGE_BYTES};
+use crate::node_detail::{node_detail, role_name};
+use crate::node_list::{NodeList, NodeListMessage, NodeSummary};
+use crate::outbox::{Delivery, Outbox, OutboxItem, routing_error};
+use crate::presence::{Presence, heard_text};
+use crate::search::{ChannelChoice, MessageSearch, MessageSearchMessage, SenderChoice};
 use crate::styles::{
-    DAY_SEPARATOR_STYLE, button_chip_style, channel_row_style, count_style, fav_button_style,
-    scrollbar_style, text_input_style, tooltip_style,
+    DAY_SEPARATOR_STYLE, TIME_TEXT_COLOR, badge_style, button_chip_style, channel_row_style,
+    count_style, fav_button_style, scrollbar_style, text_input_style, tooltip_style,
 };
+use crate::telemetry::NodeTelemetry;
+use crate::topology::Topology;
+use crate::traceroute;
+use crate::waypoint::{WaypointEditor, WaypointEditorMessage, waypoint_text};
 use crate::{Message, View, icons};
-use btleplug::api::BDAddr;
+use chrono::Utc;
 use iced::widget::scrollable::Scrollbar;
 use iced::widget::{
-    Column, Container, Row, Space, button, container, scrollable, text, text_input, tooltip,
+    Column, Container, Row, Space, button, canvas, container, scrollable, text, text_input, tooltip,
 };
 use iced::{Bottom, Center, Element, Fill, Padding, Task};
 use meshtastic::Message as _;
@@ -46,16 +70,22 @@ use meshtastic::protobufs::config::device_config;
 use meshtastic::protobufs::from_radio::PayloadVariant;
 use meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded;
 use meshtastic::protobufs::telemetry::Variant::DeviceMetrics;
-use meshtastic::protobufs::{Channel, FromRadio, MeshPacket, NodeInfo, PortNum, Position, User};
+use meshtastic::protobufs::{
+    Channel, FromRadio, MeshPacket, Neighbor, NeighborInfo, NodeInfo, PortNum, Position,
+    RouteDiscovery, User, Waypoint,
+};
+use meshtastic::utils::generate_rand_id;
 use std::collections::HashMap;
+use std::time::Instant;
 use tokio::sync::mpsc::Sender;
 
 #[derive(Clone, PartialEq)]
 pub enum ConnectionState {
-    Disconnected(Option<BDAddr>, Option<String>),
-    Connecting(BDAddr),
-    Connected(BDAddr),
-    Disconnecting(BDAddr),
+    Disconnected(Option<DeviceId>, Option<String>),
+    Connecting(DeviceId),
+    Connected(DeviceId),
+    Reconnecting(DeviceId, u32), // attempt number
+    Disconnecting(DeviceId),
 }
 
 impl Default for ConnectionState {
@@ -66,8 +96,8 @@ impl Default for ConnectionState {
 
 #[derive(Debug, Clone)]
 pub enum DeviceViewMessage {
-    ConnectRequest(BDAddr, Option<ChannelId>),
-    DisconnectRequest(BDAddr, bool), // bool is to exit or not
+    ConnectRequest(DeviceId, Option<ChannelId>),
+    DisconnectRequest(DeviceId, bool), // bool is to exit or not
     SubscriptionMessage(SubscriptionEvent),
     ShowChannel(Option<ChannelId>),
     ChannelMsg(ChannelViewMessage),
@@ -78,10 +108,35 @@ pub enum DeviceViewMessage {
     SearchInput(String),
     StartEditingAlias(u32),
     AliasInput(String),
-    StartForwardingMessage(ChannelViewEntry),
+    StartForwardingMessage(Box<ChannelViewEntry>),
     ForwardMessage(ChannelId),
     StopForwardingMessage,
     ClearFilter,
+    HistoryLoaded(HashMap<ChannelId, ChannelHistory>),
+    ResendMessage(ChannelId, u32), // message id of the entry to send again
+    CheckOutbox,                   // check for messages not acknowledged in time
+    ShowNodeDetail(u32),
+    ShowNodes(NodesView),
+    RequestNodeInfo(u32),
+    Traceroute(u32),
+    NewWaypoint,
+    EditWaypoint(Waypoint),
+    WaypointEditorMsg(WaypointEditorMessage),
+    SendWaypointMessage(ChannelId),
+    DeleteWaypoint(ChannelId, Waypoint),
+    StopEditingWaypoint,
+    NodeListMsg(NodeListMessage),
+    RefreshPresence, // time has passed, so the presence of nodes may have changed
+    ShowSearch,
+    MessageSearchMsg(MessageSearchMessage),
+    ShowMessage(ChannelId, u32), // open a channel at one of its entries
+}
+
+/// Views that show all the known nodes together
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodesView {
+    Map,
+    Topology,
 }
 
 #[derive(Default)]
@@ -92,20 +147,35 @@ pub struct DeviceView {
     my_position: Option<Position>,
     my_info: bool,
     viewing_channel: Option<ChannelId>,
+    viewing_node: Option<u32>, // node whose details are being shown
+    viewing_nodes: Option<NodesView>,
     /// Map of ChannelViews, indexed by ChannelId
     pub channel_views: HashMap<ChannelId, ChannelView>,
     channels: Vec<Channel>,
     nodes: HashMap<u32, NodeInfo>, // all nodes known to the connected radio
+    telemetry: HashMap<u32, NodeTelemetry>, // telemetry reported by each node
+    traceroutes: HashMap<u32, traceroute::Traceroute>, // latest traceroute to each node
+    neighbors: HashMap<u32, Vec<Neighbor>>, // neighbors reported by each node
+    waypoints: HashMap<u32, Waypoint>, // waypoints shared on the mesh, by waypoint id
     filter: String,
+    node_list: NodeList, // how the list of nodes is sorted and filtered
+    searching_messages: bool,
+    message_search: MessageSearch, // what is being searched for in the messages of all channels
     exit_pending: bool,
     battery_level: Option<u32>,
     editing_alias: Option<u32>,
     alias: String,
     pub forwarding_message: Option<ChannelViewEntry>,
+    pub editing_waypoint: Option<WaypointEditor>,
+    /// History loaded from disk for channels the radio has not told us about yet
+    pending_history: HashMap<ChannelId, ChannelHistory>,
+    /// Text messages we have sent, that have not been delivered yet
+    outbox: Outbox,
+    join_message_parts: bool, // join the numbered parts of long received messages into one
 }
 
-async fn request_connection(sender: Sender<SubscriberMessage>, mac_address: BDAddr) {
-    let _ = sender.send(Connect(mac_address)).await;
+async fn request_connection(sender: Sender<SubscriberMessage>, device_id: DeviceId) {
+    let _ = sender.send(Connect(device_id)).await;
 }
 
 async fn request_send_text(
@@ -113,8 +183,11 @@ async fn request_send_text(
     text: String,
     channel_id: ChannelId,
     reply_to_id: Option<u32>,
+    packet_id: u32,
 ) {
-    let _ = sender.send(SendText(text, channel_id, reply_to_id)).await;
+    let _ = sender
+        .send(SendText(text, channel_id, reply_to_id, packet_id))
+        .await;
 }
 
 async fn request_send_emoji_reply(
@@ -136,8 +209,24 @@ async fn request_send_position(
     let _ = sender.send(SendPosition(channel_id, position)).await;
 }
 
-async fn request_send_info(sender: Sender<SubscriberMessage>, channel_id: ChannelId) {
-    let _ = sender.send(SendInfo(channel_id)).await;
+async fn request_send_info(
+    sender: Sender<SubscriberMessage>,
+    channel_id: ChannelId,
+    want_response: bool,
+) {
+    let _ = sender.send(SendInfo(channel_id, want_response)).await;
+}
+
+async fn request_send_waypoint(
+    sender: Sender<SubscriberMessage>,
+    channel_id: ChannelId,
+    waypoint: Waypoint,
+) {
+    let _ = sender.send(SendWaypoint(channel_id, waypoint)).await;
+}
+
+async fn request_traceroute(sender: Sender<SubscriberMessage>, node_num: u32) {
+    let _ = sender.send(SendTraceroute(node_num)).await;
 }
 
 async fn request_disconnection(sender: Sender<SubscriberMessage>) {
@@ -152,10 +241,16 @@ impl DeviceView {
         &self.connection_state
     }
 
+    /// Get our own position, if the radio has one
+    pub fn my_position(&self) -> Option<&Position> {
+        self.my_position.as_ref()
+    }
+
     /// Cancel or Exit any interactive modes underway
     pub fn cancel_interactive(&mut self) {
         self.stop_editing_alias();
         self.forwarding_message = None;
+        self.editing_waypoint = None;
         if let Some(viewing_channel) = &self.viewing_channel
             && let Some(channel_view) = self.channel_views.get_mut(viewing_channel)
         {
@@ -166,22 +261,25 @@ impl DeviceView {
     /// Return a true value to show we can show the device view, false for main to decide
     pub fn update(&mut self, device_view_message: DeviceViewMessage) -> Task<Message> {
         match device_view_message {
-            ConnectRequest(mac_address, channel_id) => {
+            ConnectRequest(device_id, channel_id) => {
                 // save the desired channel to show for when the connection is completed later
-                self.connection_state = Connecting(mac_address);
+                self.connection_state = Connecting(device_id.clone());
                 let sender = self.subscription_sender.clone();
-                return Task::perform(request_connection(sender.unwrap(), mac_address), |_| {
+                return Task::perform(request_connection(sender.unwrap(), device_id), |_| {
                     Navigation(View::Device(channel_id))
                 });
             }
-            DisconnectRequest(mac_address, exit) => {
+            DisconnectRequest(device_id, exit) => {
+                // Save any message being typed before disconnecting, or exiting
+                let save_task = self.save_channel_history(self.viewing_channel.clone());
                 self.exit_pending = exit;
-                self.connection_state = Disconnecting(mac_address);
+                self.connection_state = Disconnecting(device_id);
                 // Send a message to the subscription to disconnect
                 let sender = self.subscription_sender.clone();
-                return Task::perform(request_disconnection(sender.unwrap()), |_| {
-                    Navigation(DeviceList)
-                });
+                return save_task.chain(Task::perform(
+                    request_disconnection(sender.unwrap()),
+                    |_| Navigation(DeviceList),
+                ));
             }
             ShowChannel(channel_id) => {
                 return self.channel_change(channel_id.clone());
@@ -190,12 +288,18 @@ impl DeviceView {
                 return self.process_subscription_event(subscription_event);
             }
             SendTextMessage(message, channel_id, reply_to_id) => {
-                if let Some(sender) = self.subscription_sender.clone() {
-                    return Task::perform(
-                        request_send_text(sender, message, channel_id, reply_to_id),
-                        |_| Message::None,
-                    );
-                }
+                return self.queue_message(message, channel_id, reply_to_id);
+            }
+            ResendMessage(channel_id, entry_id) => {
+                return self.resend_message(channel_id, entry_id);
+            }
+            CheckOutbox => {
+                let deliveries = self.outbox.check_timeouts(Instant::now());
+                let tasks: Vec<Task<Message>> = deliveries
+                    .into_iter()
+                    .map(|delivery| self.apply_delivery(delivery))
+                    .collect();
+                return Task::batch(tasks);
             }
             SendPositionMessage(channel_id) => {
                 if let Some(position) = &self.my_position
@@ -209,22 +313,113 @@ impl DeviceView {
             }
             SendInfoMessage(channel_id) => {
                 let sender = self.subscription_sender.clone();
-                return Task::perform(request_send_info(sender.unwrap(), channel_id), |_| {
-                    Message::None
-                });
+                return Task::perform(
+                    request_send_info(sender.unwrap(), channel_id, false),
+                    |_| Message::None,
+                );
+            }
+            ShowNodeDetail(node_num) => {
+                self.viewing_channel = None;
+                self.viewing_node = Some(node_num);
+                self.viewing_nodes = None;
+                self.searching_messages = false;
+            }
+            ShowNodes(nodes_view) => {
+                self.viewing_channel = None;
+                self.viewing_node = None;
+                self.viewing_nodes = Some(nodes_view);
+                self.searching_messages = false;
+            }
+            ShowSearch => {
+                self.viewing_channel = None;
+                self.viewing_node = None;
+                self.viewing_nodes = None;
+                self.searching_messages = true;
+                self.search_messages();
+            }
+            MessageSearchMsg(message) => {
+                self.message_search.update(message);
+                self.search_messages();
+            }
+            ShowMessage(channel_id, entry_id) => {
+                let task = self.channel_change(Some(channel_id.clone()));
+                if let Some(channel_view) = self.channel_views.get_mut(&channel_id) {
+                    return task.chain(channel_view.show_entry(entry_id));
+                }
+                return task;
+            }
+            RequestNodeInfo(node_num) => {
+                if let Some(sender) = self.subscription_sender.clone() {
+                    return Task::perform(request_send_info(sender, Node(node_num), true), |_| {
+                        Message::None
+                    });
+                }
+            }
+            Traceroute(node_num) => {
+                if let Some(sender) = self.subscription_sender.clone() {
+                    self.traceroutes
+                        .insert(node_num, traceroute::Traceroute::Pending);
+                    return Task::perform(request_traceroute(sender, node_num), |_| Message::None);
+                }
+            }
+            NewWaypoint => {
+                if let Some(position) = &self.my_position {
+                    self.editing_waypoint = Some(WaypointEditor::new(
+                        generate_rand_id(),
+                        position.latitude_i.unwrap_or_default(),
+                        position.longitude_i.unwrap_or_default(),
+                    ));
+                }
+            }
+            EditWaypoint(waypoint) => {
+                self.editing_waypoint =
+                    Some(WaypointEditor::edit(&waypoint, Utc::now().timestamp()));
             }
+            WaypointEditorMsg(editor_message) => {
+                if let Some(editor) = &mut self.editing_waypoint {
+                    editor.update(editor_message);
+                }
+            }
+            SendWaypointMessage(channel_id) => {
+                if let Some(editor) = self.editing_waypoint.take()
+                    && let Some(waypoint) = editor.waypoint(Utc::now().timestamp())
+                    && let Some(sender) = self.subscription_sender.clone()
+                {
+                    return Task::perform(
+                        request_send_waypoint(sender, channel_id, waypoint),
+                        |_| Message::None,
+                    );
+                }
+            }
+            DeleteWaypoint(channel_id, mut waypoint) => {
+                if let Some(sender) = self.subscription_sender.clone() {
+                    waypoint.expire = crate::waypoint::DELETED;
+                    return Task::perform(
+                        request_send_waypoint(sender, channel_id, waypoint),
+                        |_| Message::None,
+                    );
+                }
+            }
+            StopEditingWaypoint => self.editing_waypoint = None,
             ChannelMsg(msg) => {
-                if let Some(channel_id) = &self.viewing_channel
-                    && let Some(channel_view) = self.channel_views.get_mut(channel_id)
+                if let Some(channel_id) = self.viewing_channel.clone()
+                    && let Some(channel_view) = self.channel_views.get_mut(&channel_id)
                 {
-                    return channel_view.update(msg);
+                    // Only save the history when an entry is seen for the first time
+                    let newly_seen = matches!(msg, MessageSeen(_, message_id)
+                        if channel_view.is_unseen(message_id));
+                    let task = channel_view.update(msg);
+                    if newly_seen {
+                        return task.chain(self.save_channel_history(Some(channel_id)));
+                    }
+                    return task;
                 }
             }
             SearchInput(filter) => self.filter = filter,
             AliasInput(alias) => self.alias = alias,
             StartEditingAlias(node_id) => self.start_editing_alias(node_id),
             StartForwardingMessage(channel_view_entry) => {
-                self.forwarding_message = Some(channel_view_entry)
+                self.forwarding_message = Some(*channel_view_entry)
             }
             StopForwardingMessage => self.forwarding_message = None,
             ForwardMessage(channel_id) => {
@@ -232,6 +427,9 @@ impl DeviceView {
                 return self.forward_message(channel_id, entry);
             }
             ClearFilter => self.filter.clear(),
+            NodeListMsg(node_list_message) => self.node_list.update(node_list_message),
+            RefreshPresence => {} // Nothing to update, only the view to be redrawn
+            HistoryLoaded(history) => self.restore_history(history),
             SendEmojiReplyMessage(reply_to_id, emoji, channel_id) => {
                 if let Some(sender) = self.subscription_sender.clone() {
                     return Task::perform(
@@ -248,19 +446,34 @@ impl DeviceView {
     /// ave the new channel (which could be None) and if we are connected to a device
     /// asynchronously, save the modified config
     fn channel_change(&mut self, channel_id: Option<ChannelId>) -> Task<Message> {
+        self.viewing_node = None;
+        self.viewing_nodes = None;
+        self.searching_messages = false;
         if self.viewing_channel != channel_id {
+            // Save any message being typed in the channel being left, and open the new channel
+            // at the first message not seen yet
+            let mut task = self.save_channel_history(self.viewing_channel.clone());
             self.viewing_channel = channel_id.clone();
+            if let Some(channel) = &channel_id
+                && let Some(channel_view) = self.channel_views.get_mut(channel)
+            {
+                task = task.chain(channel_view.show_first_unseen());
+            }
 
             if let Some(channel) = &channel_id
-                && let Connected(mac_address) = &self.connection_state
+                && let Connected(device_id) = &self.connection_state
                 && self.channel_views.contains_key(channel)
             {
                 let channel_id = channel_id.clone();
-                let mac_address = *mac_address;
-                return Task::perform(empty(), move |_| {
-                    Message::ConfigChange(DeviceAndChannel(Some(mac_address), channel_id))
-                });
+                let device_id = device_id.clone();
+                return task.chain(Task::perform(empty(), move |_| {
+                    Message::ConfigChange(DeviceAndChannel(
+                        Some(device_id.clone()),
+                        channel_id.clone(),
+                    ))
+                }));
             }
+            return task;
         }
         Task::none()
     }
@@ -271,17 +484,187 @@ impl DeviceView {
         channel_id: ChannelId,
         entry: Option<ChannelViewEntry>,
     ) -> Task<Message> {
-        if let Some(channel_view_entry) = entry
-            && let Some(sender) = self.subscription_sender.clone()
-        {
+        if let Some(channel_view_entry) = entry {
             let message_text = format!(
                 "FWD from '{}': {}\n",
                 short_name(&self.nodes, channel_view_entry.from()),
                 channel_view_entry.payload()
             );
+            self.queue_message(message_text, channel_id.clone(), None)
+                .chain(Task::perform(empty(), |_| {
+                    DeviceViewEvent(ShowChannel(Some(channel_id)))
+                }))
+        } else {
+            Task::none()
+        }
+    }
+
+    /// Add a text message we are sending to its channel and to the outbox, and send it to the
+    /// radio if we are connected. If not, it will be sent once we are.
+    /// A message too long for one packet is sent as numbered parts, in order, and only the first
+    /// part is a reply.
+    fn queue_message(
+        &mut self,
+        text: String,
+        channel_id: ChannelId,
+        reply_to_id: Option<u32>,
+    ) -> Task<Message> {
+        let Some(my_node_num) = self.my_node_num else {
+            return Task::none();
+        };
+        let Some(channel_view) = self.channel_views.get_mut(&channel_id) else {
+            return Task::none();
+        };
+
+        let mut items = vec![];
+        for (index, part) in message_parts::split(&text, MAX_MESSAGE_BYTES)
+            .into_iter()
+            .enumerate()
+        {
+            let reply_to_id = reply_to_id.filter(|_| index == 0);
+            let packet_id = generate_rand_id();
+            let payload = match reply_to_id {
+                Some(reply_to_id) => TextMessageReply(reply_to_id, part.clone()),
+                None => NewTextMessage(part.clone()),
+            };
+            let mut entry = ChannelViewEntry::new(payload, my_node_num, packet_id);
+            entry.set_status(Queued);
+            channel_view.new_message(entry);
+
+            items.push(self.outbox.add(
+                channel_id.clone(),
+                packet_id,
+                packet_id,
+                part,
+                reply_to_id,
+            ));
+        }
+        items
+            .into_iter()
+            .fold(self.save_channel_history(Some(channel_id)), |task, item| {
+                task.chain(self.send_from_outbox(item))
+            })
+    }
+
+    /// Send a message that failed to be delivered again, when the user asks
+    fn resend_message(&mut self, channel_id: ChannelId, entry_id: u32) -> Task<Message> {
+        if let Some(channel_view) = self.channel_views.get_mut(&channel_id)
+            && let Some((text, reply_to_id)) = channel_view
+                .entry(entry_id)
+                .and_then(|entry| entry.resendable())
+        {
+            channel_view.set_status(entry_id, Queued);
+            let item = self
+                .outbox
+                .add(channel_id, entry_id, generate_rand_id(), text, reply_to_id);
+            self.send_from_outbox(item)
+        } else {
+            Task::none()
+        }
+    }
+
+    /// Send a message in the outbox to the radio, if we are connected to it
+    fn send_from_outbox(&self, item: OutboxItem) -> Task<Message> {
+        if let Connected(_) = &self.connection_state
+            && let Some(sender) = self.subscription_sender.clone()
+        {
             Task::perform(
-                request_send_text(sender, message_text, channel_id.clone(), None),
-                |_| DeviceViewEvent(ShowChannel(Some(channel_id))),
+                request_send_text(
+                    sender,
+                    item.text,
+                    item.channel_id,
+                    item.reply_to_id,
+                    item.packet_id,
+                ),
+                |_| Message::None,
+            )
+        } else {
+            Task::none()
+        }
+    }
+
+    /// Act on a change to a message in the outbox, updating its entry or sending it again
+    fn apply_delivery(&mut self, delivery: Delivery) -> Task<Message> {
+        match delivery {
+            Delivery::Update(channel_id, entry_id, status) => {
+                if let Some(channel_view) = self.channel_views.get_mut(&channel_id)
+                    && channel_view.set_status(entry_id, status)
+                {
+                    self.save_channel_history(Some(channel_id))
+                } else {
+                    Task::none()
+                }
+            }
+            Delivery::Retry(item) => {
+                if let Some(channel_view) = self.channel_views.get_mut(&item.channel_id) {
+                    channel_view.set_status(item.entry_id, Queued);
+                }
+                self.send_from_outbox(item)
+            }
+        }
+    }
+
+    /// Set how many times a message is sent again if it fails, None for the default
+    pub fn set_message_retries(&mut self, message_retries: Option<u32>) {
+        self.outbox.set_max_retries(message_retries);
+    }
+
+    /// Set whether the numbered parts of long messages received are joined into one message
+    pub fn set_join_message_parts(&mut self, join_message_parts: bool) {
+        self.join_message_parts = join_message_parts;
+    }
+
+    /// Return true if there are messages we have sent that have not been delivered yet
+    pub fn awaiting_delivery(&self) -> bool {
+        !self.outbox.is_empty()
+    }
+
+    /// Restore history loaded from disk into the channel views that exist, keeping the rest
+    /// until the radio tells us about the channel or node it belongs to
+    fn restore_history(&mut self, history: HashMap<ChannelId, ChannelHistory>) {
+        for (channel_id, channel_history) in history {
+            if let Some(channel_view) = self.channel_views.get_mut(&channel_id) {
+                channel_view.restore_draft(&channel_history.draft);
+                channel_view.restore(channel_history.entries);
+            } else {
+                self.pending_history.insert(channel_id, channel_history);
+            }
+        }
+    }
+
+    /// Create a [ChannelView] for a channel or node, restoring any history loaded for it.
+    /// An existing [ChannelView] is kept, as the radio sends its channels and nodes again when
+    /// we reconnect to it
+    fn new_channel_view(&mut self, channel_id: ChannelId) {
+        if self.channel_views.contains_key(&channel_id) {
+            return;
+        }
+        let mut channel_view = ChannelView::new(channel_id.clone(), self.my_node_num.unwrap());
+        if let Some(channel_history) = self.pending_history.remove(&channel_id) {
+            channel_view.restore_draft(&channel_history.draft);
+            channel_view.restore(channel_history.entries);
+        }
+        self.channel_views.insert(channel_id, channel_view);
+    }
+
+    /// Save the history of a channel, and any message typed in it but not sent, to disk, if we
+    /// know which device it is from, including while connecting or disconnecting
+    fn save_channel_history(&self, channel_id: Option<ChannelId>) -> Task<Message> {
+        let device_id = match &self.connection_state {
+            Connecting(device_id)
+            | Connected(device_id)
+            | Reconnecting(device_id, _)
+            | Disconnecting(device_id) => device_id,
+            Disconnected(..) => return Task::none(),
+        };
+        if let Some(channel_id) = channel_id
+            && let Some(channel_view) = self.channel_views.get(&channel_id)
+        {
+            save_history(
+                device_id,
+                &channel_id,
+                channel_view.history(),
+                channel_view.draft(),
             )
         } else {
             Task::none()
@@ -306,14 +689,18 @@ impl DeviceView {
         subscription_event: SubscriptionEvent,
     ) -> Task<Message> {
         match subscription_event {
-            ConnectedEvent(mac_address) => {
-                self.connection_state = Connected(mac_address);
-                match &self.viewing_channel {
+            ConnectedEvent(device_id) => {
+                self.connection_state = Connected(device_id.clone());
+                // The radio sends all its channels and nodes again, when reconnecting
+                self.channels.clear();
+                self.nodes.clear();
+                let history_task = load_history(&device_id);
+                let show_task = match &self.viewing_channel {
                     None => {
                         let channel_id = self.viewing_channel.clone();
                         Task::perform(empty(), move |_| {
                             Message::ConfigChange(DeviceAndChannel(
-                                Some(mac_address),
+                                Some(device_id.clone()),
                                 channel_id.clone(),
                             ))
                         })
@@ -324,29 +711,46 @@ impl DeviceView {
                             DeviceViewEvent(ShowChannel(Some(channel_id.clone())))
                         })
                     }
-                }
+                };
+                // Send any messages queued while we were not connected
+                let queued: Vec<Task<Message>> = self
+                    .outbox
+                    .queued()
+                    .into_iter()
+                    .map(|item| self.send_from_outbox(item))
+                    .collect();
+                history_task.chain(show_task).chain(Task::batch(queued))
             }
             DisconnectedEvent(id) => {
                 if self.exit_pending {
                     std::process::exit(0);
                 }
+                let save_task = self.save_channel_history(self.viewing_channel.clone());
                 self.connection_state = Disconnected(Some(id), None);
-                self.channel_views.clear();
-                self.nodes.clear();
-                self.channels.clear();
-                self.my_node_num = None;
-                self.viewing_channel = None;
-                Task::perform(empty(), |_| Navigation(DeviceList))
+                self.clear_device();
+                save_task.chain(Task::perform(empty(), |_| Navigation(DeviceList)))
+            }
+            ReconnectingEvent(id, attempt) => {
+                // Keep the channels and their messages, so they are still there once reconnected
+                self.connection_state = Reconnecting(id, attempt);
+                Task::none()
             }
             Ready(sender) => {
                 self.subscription_sender = Some(sender);
                 Task::none()
             }
             DevicePacket(packet) => self.handle_from_radio(packet),
+            MessageSent(packet_id) => match self.outbox.sent(packet_id) {
+                Some(delivery) => self.apply_delivery(delivery),
+                None => Task::none(),
+            },
             DeviceMeshPacket(packet) => self.handle_mesh_packet(&packet),
             ConnectionError(id, summary, detail) => {
+                let save_task = self.save_channel_history(self.viewing_channel.clone());
                 self.connection_state = Disconnected(Some(id), Some(summary.clone()));
-                Task::perform(empty(), |_| Navigation(DeviceList))
+                self.clear_device();
+                save_task
+                    .chain(Task::perform(empty(), |_| Navigation(DeviceList)))
                     .chain(Task::perform(empty(), move |_| {
                         Message::AppError(summary.clone(), detail.clone())
                     }))
@@ -354,6 +758,25 @@ impl DeviceView {
         }
     }
 
+    /// Forget everything learned from the device, once we are no longer connected to it
+    fn clear_device(&mut self) {
+        self.channel_views.clear();
+        self.outbox.clear();
+        self.pending_history.clear();
+        self.nodes.clear();
+        self.telemetry.clear();
+        self.traceroutes.clear();
+        self.neighbors.clear();
+        self.waypoints.clear();
+        self.editing_waypoint = None;
+        self.channels.clear();
+        self.my_node_num = None;
+        self.viewing_channel = None;
+        self.viewing_node = None;
+        self.viewing_nodes = None;
+        self.searching_messages = false;
+    }
+
     /// Handle [FromRadio] packets coming from the radio, forwarded from the device_subscription
     fn handle_from_radio(&mut self, packet: Box<FromRadio>) -> Task<Message> {
         match packet.payload_variant {
@@ -392,15 +815,10 @@ impl DeviceView {
             self.my_info = true;
         }
 
-        if !node_info.is_ignored
-            && node_info.user.as_ref().map(|u| u.role()) == Some(device_config::Role::Client)
-        {
+        if !node_info.is_ignored && node_info.user.is_some() {
             let channel_id = Node(node_info.num);
             self.nodes.insert(node_info.num, node_info);
-            self.channel_views.insert(
-                channel_id.clone(),
-                ChannelView::new(channel_id, self.my_node_num.unwrap()),
-            );
+            self.new_channel_view(channel_id);
         }
     }
 
@@ -414,10 +832,7 @@ impl DeviceView {
             };
             self.channels.push(channel);
             let channel_id = ChannelId::Channel((self.channels.len() - 1) as i32);
-            self.channel_views.insert(
-                channel_id.clone(),
-                ChannelView::new(channel_id, self.my_node_num.unwrap()),
-            );
+            self.new_channel_view(channel_id);
         }
     }
 
@@ -441,10 +856,30 @@ impl DeviceView {
 
     /// Handle a packet we have received from the mesh, depending on the payload variant and portnum
     fn handle_mesh_packet(&mut self, mesh_packet: &MeshPacket) -> Task<Message> {
+        // The channel whose history has changed and needs to be saved
+        let mut changed_channel = None;
+
+        // Any packet from a node shows it is alive
+        if let Some(node) = self.nodes.get_mut(&mesh_packet.from) {
+            node.last_heard = match mesh_packet.rx_time {
+                0 => Utc::now().timestamp() as u32,
+                rx_time => rx_time,
+            };
+        }
+
         if let Some(Decoded(data)) = &mesh_packet.payload_variant {
             match PortNum::try_from(data.portnum) {
                 Ok(PortNum::RoutingApp) => {
-                    // An ACK
+                    // A response to a message sent from the outbox
+                    let error = routing_error(&data.payload);
+                    if let Some(delivery) =
+                        self.outbox
+                            .routing(data.request_id, mesh_packet.from, error)
+                    {
+                        return self.apply_delivery(delivery);
+                    }
+
+                    // An ACK of another message
                     let channel_id = if mesh_packet.from == mesh_packet.to {
                         // To a channel broadcast message
                         ChannelId::Channel(mesh_packet.channel as i32)
@@ -453,19 +888,20 @@ impl DeviceView {
                         Node(mesh_packet.from)
                     };
                     if let Some(channel_view) = &mut self.channel_views.get_mut(&channel_id) {
-                        channel_view.ack(data.request_id)
+                        channel_view.ack(data.request_id);
+                        changed_channel = Some(channel_id);
                     }
                 }
                 Ok(PortNum::AlertApp) => {
                     let channel_id = self.channel_id_from_packet(mesh_packet);
                     if let Some(channel_view) = &mut self.channel_views.get_mut(&channel_id) {
-                        let new_message = ChannelViewEntry::new(
+                        let new_message = ChannelViewEntry::received(
                             AlertMessage(String::from_utf8(data.payload.clone()).unwrap()),
-                            mesh_packet.from,
-                            mesh_packet.id,
+                            mesh_packet,
                         );
 
                         channel_view.new_message(new_message);
+                        changed_channel = Some(channel_id);
                     } else {
                         eprintln!("No channel for packet");
                     }
@@ -491,10 +927,14 @@ impl DeviceView {
                             }
                         };
 
-                        let new_message =
-                            ChannelViewEntry::new(message, mesh_packet.from, mesh_packet.id);
+                        let new_message = ChannelViewEntry::received(message, mesh_packet);
 
-                        channel_view.new_message(new_message);
+                        if self.join_message_parts {
+                            channel_view.new_message_part(new_message);
+                        } else {
+                            channel_view.new_message(new_message);
+                        }
+                        changed_channel = Some(channel_id);
                     } else {
                         eprintln!("No channel for packet");
                     }
@@ -507,12 +947,10 @@ impl DeviceView {
                         if let Some(lat) = position.latitude_i
                             && let Some(lon) = position.longitude_i
                         {
-                            let new_message = ChannelViewEntry::new(
-                                PositionMessage(lat, lon),
-                                mesh_packet.from,
-                                mesh_packet.id,
-                            );
+                            let new_message =
+                                ChannelViewEntry::received(PositionMessage(lat, lon), mesh_packet);
                             channel_view.new_message(new_message);
+                            changed_channel = Some(channel_id);
                         } else {
                             eprintln!("No lat/lon for Position: {:?}", position);
                         }
@@ -522,24 +960,88 @@ impl DeviceView {
                 }
                 Ok(PortNum::TelemetryApp) => {
                     let telemetry =
-                        meshtastic::protobufs::Telemetry::decode(&data.payload as &[u8]).unwrap();
-                    if mesh_packet.from == self.my_node_num.unwrap()
-                        && let Some(DeviceMetrics(metrics)) = telemetry.variant
-                    {
-                        self.battery_level = metrics.battery_level;
+                        match meshtastic::protobufs::Telemetry::decode(&data.payload as &[u8]) {
+                            Ok(telemetry) => telemetry,
+                            Err(e) => {
+                                eprintln!("TelemetryApp: Could not decode telemetry: {e}");
+                                return Task::none();
+                            }
+                        };
+                    if let Some(DeviceMetrics(metrics)) = telemetry.variant {
+                        if Some(mesh_packet.from) == self.my_node_num {
+                            self.battery_level = metrics.battery_level;
+                        }
+                        if let Some(node) = self.nodes.get_mut(&mesh_packet.from) {
+                            node.device_metrics = Some(metrics);
+                        }
+                    }
+                    let received = match mesh_packet.rx_time {
+                        0 => Utc::now().timestamp(),
+                        rx_time => rx_time as i64,
+                    };
+                    self.telemetry
+                        .entry(mesh_packet.from)
+                        .or_default()
+                        .add(telemetry, received);
+                }
+                Ok(PortNum::TracerouteApp) => {
+                    // Only the response to our request is of interest, not others passing by
+                    if Some(mesh_packet.to) == self.my_node_num {
+                        match RouteDiscovery::decode(&data.payload as &[u8]) {
+                            Ok(route_discovery) => {
+                                self.traceroutes.insert(
+                                    mesh_packet.from,
+                                    traceroute::Traceroute::new(
+                                        mesh_packet.to,
+                                        mesh_packet.from,
+                                        &route_discovery,
+                                    ),
+                                );
+                            }
+                            Err(e) => eprintln!("TracerouteApp: Could not decode route: {e}"),
+                        }
                     }
                 }
-                Ok(PortNum::NeighborinfoApp) => println!("Neighbor Info payload"),
+                Ok(PortNum::NeighborinfoApp) => {
+                    match NeighborInfo::decode(&data.payload as &[u8]) {
+                        Ok(neighbor_info) => {
+                            let node_id = match neighbor_info.node_id {
+                                0 => mesh_packet.from,
+                                node_id => node_id,
+                            };
+                            self.neighbors.insert(node_id, neighbor_info.neighbors);
+                        }
+                        Err(e) => eprintln!("NeighborinfoApp: Could not decode neighbors: {e}"),
+                    }
+                }
+                Ok(PortNum::WaypointApp) => match Waypoint::decode(&data.payload as &[u8]) {
+                    Ok(waypoint) => {
+                        // An expired waypoint is how a node tells others it has been deleted
+                        if crate::waypoint::expired(&waypoint, Utc::now().timestamp()) {
+                            self.waypoints.remove(&waypoint.id);
+                        } else {
+                            self.waypoints.insert(waypoint.id, waypoint.clone());
+                        }
+                        let channel_id = self.channel_id_from_packet(mesh_packet);
+                        if let Some(channel_view) = &mut self.channel_views.get_mut(&channel_id) {
+                            let new_message =
+                                ChannelViewEntry::received(WaypointMessage(waypoint), mesh_packet);
+                            channel_view.new_message(new_message);
+                            changed_channel = Some(channel_id);
+                        } else {
+                            eprintln!("WaypointApp: No channel for: {}", channel_id);
+                        }
+                    }
+                    Err(e) => eprintln!("WaypointApp: Could not decode waypoint: {e}"),
+                },
                 Ok(PortNum::NodeinfoApp) => {
                     let user = User::decode(&data.payload as &[u8]).unwrap();
                     let channel_id = self.channel_id_from_packet(mesh_packet);
                     if let Some(channel_view) = &mut self.channel_views.get_mut(&channel_id) {
-                        let new_message = ChannelViewEntry::new(
-                            UserMessage(user),
-                            mesh_packet.from,
-                            mesh_packet.id,
-                        );
+                        let new_message =
+                            ChannelViewEntry::received(UserMessage(user), mesh_packet);
                         channel_view.new_message(new_message);
+                        changed_channel = Some(channel_id);
                     } else {
                         eprintln!("NodeInfoApp: No channel for: {}", user.long_name);
                     }
@@ -549,7 +1051,12 @@ impl DeviceView {
             }
         }
 
-        Task::none()
+        // Keep the messages found up to date while searching
+        if self.searching_messages && changed_channel.is_some() {
+            self.search_messages();
+        }
+
+        self.save_channel_history(changed_channel)
     }
 
     /// If the Node is known already, then update its Position with a PositionApp update
@@ -558,6 +1065,9 @@ impl DeviceView {
     /// accuracy is set per channel. Consider deciding whether to update or not if it has lower
     /// accuracy depending on the age of the previous position?
     fn update_node_position(&mut self, from: u32, position: &Position) {
+        if Some(from) == self.my_node_num {
+            self.my_position = Some(*position);
+        }
         if let Some(node) = self.nodes.get_mut(&from) {
             node.position = Some(*position);
         }
@@ -581,10 +1091,10 @@ impl DeviceView {
             Disconnected(_, _) => header
                 .push(Space::new().width(Fill))
                 .push(button("Disconnected").style(button_chip_style)),
-            Connecting(mac_address) => {
+            Connecting(device_id) => {
                 let name_button = button(text(format!(
                     "📱 {}",
-                    device_list_view.device_name_or_alias(mac_address, config)
+                    device_list_view.device_name_or_alias(device_id, config)
                 )))
                 .style(button_chip_style);
                 header = header.push(name_button);
@@ -602,7 +1112,11 @@ impl DeviceView {
                     .push(Self::unread_counter(self.unread_count()));
                 let mut button = button(name_row).style(button_chip_style);
                 // If viewing a channel of the device, allow navigating back to the device view
-                if self.viewing_channel.is_some() {
+                if self.viewing_channel.is_some()
+                    || self.viewing_node.is_some()
+                    || self.viewing_nodes.is_some()
+                    || self.searching_messages
+                {
                     button = button.on_press(DeviceViewEvent(ShowChannel(None)));
                 }
 
@@ -611,6 +1125,21 @@ impl DeviceView {
                     .push(Space::new().width(4))
                     .push(self.battery_level())
             }
+            Reconnecting(device, _) => {
+                let mut button = button(text(format!(
+                    "📱 {}",
+                    device_list_view.device_name_or_alias(device, config)
+                )))
+                .style(button_chip_style);
+                if self.viewing_channel.is_some()
+                    || self.viewing_node.is_some()
+                    || self.viewing_nodes.is_some()
+                    || self.searching_messages
+                {
+                    button = button.on_press(DeviceViewEvent(ShowChannel(None)));
+                }
+                header.push(button)
+            }
             Disconnecting(device) => {
                 let button = button(text(format!(
                     "📱 {}",
@@ -635,19 +1164,56 @@ impl DeviceView {
             }
             Some(Node(node_id)) => {
                 if let Some(node_name) = self.aliased_long_name(config, *node_id) {
+                    let last_heard = self.nodes[node_id].last_heard;
+                    let now = Utc::now().timestamp();
+                    header = header
+                        .push(button(text(node_name)).style(button_chip_style))
+                        .push(Space::new().width(6))
+                        .push(Self::presence_dot(last_heard, now, config))
+                        .push(text(heard_text(last_heard, now)).size(14))
+                }
+            }
+            None => {
+                if let Some(node_id) = self.viewing_node
+                    && let Some(node_name) = self.aliased_long_name(config, node_id)
+                {
                     header = header.push(button(text(node_name)).style(button_chip_style))
+                } else if let Some(nodes_view) = self.viewing_nodes {
+                    let title = match nodes_view {
+                        NodesView::Map => "Map",
+                        NodesView::Topology => "Topology",
+                    };
+                    header = header.push(button(title).style(button_chip_style))
+                } else if self.searching_messages {
+                    header = header.push(button("Search messages").style(button_chip_style))
                 }
             }
-            None => {}
         }
 
-        // Add a disconnect button on the right if we are connected
-        if let Connected(mac_address) = state {
-            header = header.push(Space::new().width(Fill)).push(
-                button("Disconnect")
-                    .on_press(DeviceViewEvent(DisconnectRequest(*mac_address, false)))
-                    .style(button_chip_style),
-            )
+        // Add a disconnect button on the right if we are connected, or trying to reconnect
+        match state {
+            Connected(device_id) => {
+                header = header.push(Space::new().width(Fill)).push(
+                    button("Disconnect")
+                        .on_press(DeviceViewEvent(DisconnectRequest(device_id.clone(), false)))
+                        .style(button_chip_style),
+                )
+            }
+            Reconnecting(device_id, attempt) => {
+                header = header
+                    .push(Space::new().width(Fill))
+                    .push(
+                        button(text(format!("Reconnecting (attempt {attempt})")))
+                            .style(button_chip_style),
+                    )
+                    .push(Space::new().width(4))
+                    .push(
+                        button("Disconnect")
+                            .on_press(DeviceViewEvent(DisconnectRequest(device_id.clone(), false)))
+                            .style(button_chip_style),
+                    )
+            }
+            _ => {}
         }
 
         header.into()
@@ -682,6 +1248,43 @@ impl DeviceView {
 
     /// Create the Element that shows the channels, nodes, etc.
     pub fn view<'a>(&'a self, config: &'a Config) -> Element<'a, Message> {
+        if let Some(node_num) = self.viewing_node
+            && let Some(node_info) = self.nodes.get(&node_num)
+        {
+            let connected = matches!(self.connection_state, Connected(_));
+            return node_detail(
+                node_info,
+                self.telemetry.get(&node_num),
+                self.traceroutes.get(&node_num),
+                self.neighbors.get(&node_num),
+                &self.nodes,
+                config,
+                connected,
+            );
+        }
+
+        match self.viewing_nodes {
+            Some(NodesView::Map) => {
+                let map = NodeMap::new(
+                    self.my_node_num,
+                    self.my_position.as_ref(),
+                    &self.nodes,
+                    config,
+                );
+                return canvas(map).width(Fill).height(Fill).into();
+            }
+            Some(NodesView::Topology) => {
+                let topology =
+                    Topology::new(self.my_node_num, &self.nodes, &self.neighbors, config);
+                return canvas(topology).width(Fill).height(Fill).into();
+            }
+            None => {}
+        }
+
+        if self.searching_messages {
+            return self.message_search_view(config);
+        }
+
         if let Some(channel_number) = &self.viewing_channel
             && let Some(channel_view) = self.channel_views.get(channel_number)
         {
@@ -700,12 +1303,77 @@ impl DeviceView {
         let channel_and_node_scroll = self.channel_and_node_list(config, true, select);
 
         // Add a search box at the top, outside the scrollable area
+        let users = self.nodes.values().filter_map(|node| node.user.as_ref());
+        let node_list_controls = self.node_list.view(
+            users.clone().map(|user| user.hw_model).collect(),
+            users.map(|user| user.role).collect(),
+            config,
+        );
+
         Column::new()
             .push(self.search_box())
+            .push(node_list_controls)
             .push(channel_and_node_scroll)
             .into()
     }
 
+    /// Search the messages of all the channels, and the stored history of channels the radio has
+    /// not told us about yet, when the search changes or new messages arrive while searching
+    fn search_messages(&mut self) {
+        let channels = self
+            .channel_views
+            .iter()
+            .map(|(channel_id, channel_view)| (channel_id, channel_view.entries().collect(), true))
+            .chain(self.pending_history.iter().map(|(channel_id, history)| {
+                (channel_id, history.entries.iter().collect(), false)
+            }));
+        self.message_search.search(channels);
+    }
+
+    /// Show the search of the messages, and the messages found
+    fn message_search_view<'a>(&'a self, config: &'a Config) -> Element<'a, Message> {
+        let mut channel_choices: Vec<ChannelChoice> = self
+            .channel_views
+            .keys()
+            .chain(self.pending_history.keys())
+            .map(|channel_id| {
+                ChannelChoice(
+                    Some(channel_id.clone()),
+                    self.channel_id_name(config, channel_id),
+                )
+            })
+            .collect();
+        let mut sender_choices: Vec<SenderChoice> = self
+            .message_search
+            .senders()
+            .iter()
+            .map(|from| SenderChoice(Some(*from), self.sender_name(config, *from)))
+            .collect();
+        sender_choices.sort_by(|left, right| left.1.cmp(&right.1));
+        channel_choices.sort_by(|left, right| left.1.cmp(&right.1));
+
+        self.message_search.view(sender_choices, channel_choices)
+    }
+
+    /// Return the name of a channel, or of the node for a direct chat
+    fn channel_id_name(&self, config: &Config, channel_id: &ChannelId) -> String {
+        match channel_id {
+            ChannelId::Channel(index) => match self.channels.get(*index as usize) {
+                Some(channel) => Self::channel_name(channel),
+                None => format!("Channel {index}"),
+            },
+            Node(node_id) => format!("📱  {}", self.sender_name(config, *node_id)),
+        }
+    }
+
+    /// Return the name of the node that sent a message, using its alias if it has one
+    fn sender_name(&self, config: &Config, from: u32) -> String {
+        match self.aliased_long_name(config, from) {
+            Some(name) => name.to_string(),
+            None => format!("Node {from}"),
+        }
+    }
+
     /// Create a list of channels and nodes in this device with a button to select one of them
     pub fn channel_and_node_list<'a>(
         &'a self,
@@ -722,6 +1390,11 @@ impl DeviceView {
         // Add the list of non-favourite nodes
         channels_list = self.nodes_list(channels_list, config, add_buttons, select);
 
+        // Add the waypoints shared on the mesh, when they can be shown on the map
+        if add_buttons {
+            channels_list = self.waypoints_list(channels_list);
+        }
+
         // Wrap the whole thing in a scrollable area
         scrollable(channels_list)
             .direction({
@@ -756,9 +1429,11 @@ impl DeviceView {
             }
 
             let channel_id = ChannelId::Channel(index as i32);
+            let channel_view = self.channel_views.get(&channel_id).unwrap();
             let channel_row = Self::channel_row(
                 channel_name,
-                self.channel_views.get(&channel_id).unwrap().unread_count(),
+                channel_view.unread_count(),
+                channel_view.has_draft(),
                 channel_id,
                 add_buttons,
                 select,
@@ -792,23 +1467,21 @@ impl DeviceView {
 
         // filter out my own node if the node number is known yet
         fav_nodes.retain(|fav_node_id| Some(*fav_node_id) != self.my_node_num);
+        let fav_nodes = self.sorted_and_filtered(fav_nodes, config);
 
         // If there are favourite nodes, show the header and list them
         if !fav_nodes.is_empty() {
             channels_list = channels_list
                 .push(self.section_header(format!("Favourite Nodes ({})", fav_nodes.len())));
-            for fav_node_id in fav_nodes {
-                let channel_id = Node(fav_node_id);
-                if let Some(channel_view) = self.channel_views.get(&channel_id) {
-                    channels_list = channels_list.push(self.node_row(
-                        channel_view.unread_count(),
-                        fav_node_id,
-                        true, // Favourite
-                        config,
-                        add_buttons,
-                        select,
-                    ));
-                }
+            for fav_node in fav_nodes {
+                channels_list = channels_list.push(self.node_row(
+                    fav_node.unread,
+                    fav_node.num,
+                    true, // Favourite
+                    config,
+                    add_buttons,
+                    select,
+                ));
             }
         }
 
@@ -829,28 +1502,39 @@ impl DeviceView {
         let other_nodes_list = self
             .nodes
             .keys()
+            .copied()
             .filter(|node_id| {
-                !config.fav_nodes.contains(node_id) && Some(**node_id) != self.my_node_num
+                !config.fav_nodes.contains(node_id) && Some(*node_id) != self.my_node_num
             })
             .filter(|node_id| {
-                if let Some(node_name) = self.aliased_long_name(config, **node_id) {
+                if let Some(node_name) = self.aliased_long_name(config, *node_id) {
                     node_name.contains(&self.filter)
                 } else {
                     false
                 }
             })
             .collect::<Vec<_>>();
+        let other_nodes_list = self.sorted_and_filtered(other_nodes_list, config);
 
         if !other_nodes_list.is_empty() {
             channels_list = channels_list
                 .push(self.section_header(format!("Nodes ({})", other_nodes_list.len())));
 
-            for node_id in other_nodes_list {
-                let channel_id = Node(*node_id);
+            let mut previous_group = None;
+            for node in other_nodes_list {
+                // Add a label when the group of nodes changes, if the sort groups them
+                let group = self.node_list.group(&node);
+                if group.is_some() && group != previous_group {
+                    channels_list = channels_list.push(
+                        container(text(group.clone().unwrap_or_default()).size(14))
+                            .padding(Padding::from([2, 12])),
+                    );
+                }
+                previous_group = group;
 
                 channels_list = channels_list.push(self.node_row(
-                    self.channel_views.get(&channel_id).unwrap().unread_count(),
-                    *node_id,
+                    node.unread,
+                    node.num,
                     false, // Not a Favourite
                     config,
                     add_buttons,
@@ -862,6 +1546,85 @@ impl DeviceView {
         channels_list
     }
 
+    /// Return a summary of each of the nodes that pass the node list filters, in the selected order
+    fn sorted_and_filtered(&self, node_ids: Vec<u32>, config: &Config) -> Vec<NodeSummary> {
+        let summaries = node_ids
+            .into_iter()
+            .filter_map(|num| {
+                let node = self.nodes.get(&num)?;
+                let user = node.user.as_ref();
+                if user.is_some_and(|user| config.role_hidden(user.role)) {
+                    return None;
+                }
+                Some(NodeSummary {
+                    num,
+                    name: self
+                        .aliased_long_name(config, num)
+                        .unwrap_or_default()
+                        .to_string(),
+                    last_heard: node.last_heard,
+                    distance: self.distance_to(num),
+                    snr: node.snr,
+                    hops_away: node.hops_away,
+                    unread: self
+                        .channel_views
+                        .get(&Node(num))
+                        .map(ChannelView::unread_count)
+                        .unwrap_or_default(),
+                    hw_model: user.map(|user| user.hw_model).unwrap_or_default(),
+                    role: user.map(|user| user.role).unwrap_or_default(),
+                })
+            })
+            .collect();
+        self.node_list.apply(summaries, Utc::now().timestamp())
+    }
+
+    /// Return the distance in metres from us to a node, if we both have a known position
+    fn distance_to(&self, node_id: u32) -> Option<f64> {
+        let position = self.nodes.get(&node_id)?.position.as_ref()?;
+        distance(self.my_position.as_ref()?, position)
+    }
+
+    /// Add the list of waypoints, each with a button to show its location, to the channels_list
+    /// If there is a filter, only show waypoints that contain the filter in their name
+    fn waypoints_list<'a>(&'a self, mut channels_list: Column<'a, Message>) -> Column<'a, Message> {
+        let mut waypoints: Vec<&Waypoint> = self
+            .waypoints
+            .values()
+            .filter(|waypoint| waypoint.name.contains(&self.filter))
+            .collect();
+        waypoints.sort_by(|a, b| a.name.cmp(&b.name));
+
+        if !waypoints.is_empty() {
+            channels_list =
+                channels_list.push(self.section_header(format!("Waypoints ({})", waypoints.len())));
+
+            for waypoint in waypoints {
+                let mut name_button = button(text(waypoint_text(waypoint)))
+                    .width(Fill)
+                    .style(channel_row_style);
+                if let (Some(lat), Some(long)) = (waypoint.latitude_i, waypoint.longitude_i) {
+                    name_button = name_button.on_press(ShowLocation(lat, long));
+                }
+                let row: Element<'a, Message> = if waypoint.description.is_empty() {
+                    name_button.into()
+                } else {
+                    tooltip(
+                        name_button,
+                        text(waypoint.description.clone()),
+                        tooltip::Position::Right,
+                    )
+                    .style(tooltip_style)
+                    .into()
+                };
+                channels_list =
+                    channels_list.push(Row::new().push(row).push(Space::new().width(10)));
+            }
+        }
+
+        channels_list
+    }
+
     /// Add a section header between areas of the list
     fn section_header(&self, title: String) -> Element<'_, Message> {
         Column::new()
@@ -898,6 +1661,33 @@ impl DeviceView {
         Some(&user.long_name)
     }
 
+    /// A dot coloured by whether a node is online, stale or offline, with a tooltip saying which
+    fn presence_dot(last_heard: u32, now: i64, config: &Config) -> Element<'static, Message> {
+        let presence = Presence::new(last_heard, now, config);
+        tooltip(
+            text("● ").color(presence.color()),
+            text(presence.name()),
+            tooltip::Position::Right,
+        )
+        .style(tooltip_style)
+        .into()
+    }
+
+    /// A badge showing the role of a node, if it is not a plain client
+    fn role_badge(node_info: Option<&NodeInfo>) -> Element<'static, Message> {
+        match node_info.and_then(|node_info| node_info.user.as_ref()) {
+            Some(user) if user.role() != device_config::Role::Client => Row::new()
+                .push(
+                    container(text(role_name(user.role)).size(12))
+                        .padding([0, 6])
+                        .style(badge_style),
+                )
+                .push(Space::new().width(4))
+                .into(),
+            _ => Space::new().width(0).into(),
+        }
+    }
+
     /// An element that will show a count of unread messages if greater than zero, or nothing
     fn unread_counter(num_messages: usize) -> Element<'static, Message> {
         if num_messages > 0 {
@@ -915,12 +1705,28 @@ impl DeviceView {
         }
     }
 
+    /// A marker shown on a channel or node with a message typed in it but not sent yet, or nothing
+    fn draft_marker(has_draft: bool) -> Element<'static, Message> {
+        if has_draft {
+            tooltip(
+                text(" ✎ draft").size(14).color(TIME_TEXT_COLOR),
+                text("Message typed but not sent"),
+                tooltip::Position::Right,
+            )
+            .style(tooltip_style)
+            .into()
+        } else {
+            Space::new().width(0).into()
+        }
+    }
+
     /// Create a Button that represents either a Channel or a Node
     /// DeviceViewEvent(ShowChannel(Some(channel_id)))
     /// DeviceViewEvent(ShowChannel(Some(channel_id)))
     fn channel_row(
         name: String,
         num_messages: usize,
+        draft: bool,
         channel_id: ChannelId,
         _add_buttons: bool,
         select: fn(ChannelId) -> Message,
@@ -928,7 +1734,8 @@ impl DeviceView {
         let name_row = Row::new()
             .push(text(name))
             .push(Space::new().width(4))
-            .push(Self::unread_counter(num_messages));
+            .push(Self::unread_counter(num_messages))
+            .push(Self::draft_marker(draft));
 
         Row::new()
             .push(
@@ -978,13 +1785,42 @@ impl DeviceView {
             text(user_name.to_string()).into()
         };
 
-        let name_row = Row::new()
+        let now = Utc::now().timestamp();
+        let last_heard = self
+            .nodes
+            .get(&node_id)
+            .map(|node| node.last_heard)
+            .unwrap_or_default();
+
+        let mut name_row = Row::new()
+            .push(Self::presence_dot(last_heard, now, config))
             .push("📱  ")
             .push(name_element)
             .push(Space::new().width(4))
+            .push(Self::role_badge(self.nodes.get(&node_id)))
             .push(Self::unread_counter(num_messages))
+            .push(Self::draft_marker(
+                self.channel_views
+                    .get(&Node(node_id))
+                    .is_some_and(ChannelView::has_draft),
+            ))
             .align_y(Center);
 
+        // Show when the node was last heard, and how far away the node is, and in which direction,
+        // if we both know our position
+        let mut node_info = vec![heard_text(last_heard, now)];
+        if let Some(my_position) = &self.my_position
+            && let Some(position) = self.nodes.get(&node_id).and_then(|node| node.position)
+            && let Some(distance) =
+                distance_and_bearing_text(my_position, &position, config.distance_unit)
+        {
+            node_info.push(distance);
+        }
+        name_row = name_row
+            .push(Space::new().width(Fill))
+            .push(text(node_info.join(" · ")).size(14).color(TIME_TEXT_COLOR))
+            .push(Space::new().width(8));
+
         let mut node_row = Row::new().align_y(Bottom);
 
         let channel_id = Node(node_id);
@@ -1013,6 +1849,19 @@ impl DeviceView {
         favourite: bool,
         config: &'a Config,
     ) -> Row<'a, Message> {
+        // Add a button to show the details of this node
+        node_row = node_row.push(
+            tooltip(
+                button(text("ℹ"))
+                    .on_press(DeviceViewEvent(ShowNodeDetail(node_id)))
+                    .style(fav_button_style),
+                "Show node details",
+                tooltip::Position::Left,
+            )
+            .gap(6)
+            .style(tooltip_style),
+        );
+
         // Add a button to add or remove an alias for this node '👤'
         let (tooltip_text, message) = if config.aliases.contains_key(&node_id) {
             ("Remove alias for this node", RemoveNodeAlias(node_id))
@@ -1074,6 +1923,24 @@ impl DeviceView {
             .push(Space::new().width(10))
     }
 
+    /// A button to switch to one of the views of all the nodes
+    fn nodes_view_button(
+        icon: &'static str,
+        nodes_view: NodesView,
+        tooltip_text: &'static str,
+    ) -> Element<'static, Message> {
+        tooltip(
+            button(text(icon).size(18))
+                .style(button_chip_style)
+                .padding(Padding::from([6, 6]))
+                .on_press(DeviceViewEvent(ShowNodes(nodes_view))),
+            text(tooltip_text),
+            tooltip::Position::Left,
+        )
+        .style(tooltip_style)
+        .into()
+    }
+
     fn search_box(&self) -> Element<'static, Message> {
         let mut clear_button = button(text("⨂").size(18))
             .style(button_chip_style)
@@ -1092,6 +1959,30 @@ impl DeviceView {
             .push(Space::new().width(4.0))
             .push(clear_button)
             .push(Space::new().width(4.0))
+            .push(
+                tooltip(
+                    button(text("🔍").size(18))
+                        .style(button_chip_style)
+                        .padding(Padding::from([6, 6]))
+                        .on_press(DeviceViewEvent(ShowSearch)),
+                    text("Search the messages in all channels"),
+                    tooltip::Position::Left,
+                )
+                .style(tooltip_style),
+            )
+            .push(Space::new().width(4.0))
+            .push(Self::nodes_view_button(
+                "🗺",
+                NodesView::Map,
+                "Show a map of the nodes",
+            ))
+            .push(Space::new().width(4.0))
+            .push(Self::nodes_view_button(
+                "🕸",
+                NodesView::Topology,
+                "Show the links between nodes",
+            ))
+            .push(Space::new().width(4.0))
             .padding([0, 4])
             .align_y(Center)
             .into()
@@ -1107,3 +1998,482 @@ pub fn short_name(nodes: &HashMap<u32, NodeInfo>, from: u32) -> &str {
         .map(|user: &User| user.short_name.as_ref())
         .unwrap_or("????")
 }
+
+#[cfg(test)]
+mod test {
+    use crate::channel_id::ChannelId::Node;
+    use crate::channel_view_entry::ChannelViewEntry;
+    use crate::channel_view_entry::Payload::NewTextMessage;
+    use crate::config::Config;
+    use crate::device_id::DeviceId;
+    use crate::device_view::ConnectionState::{
+        Connected, Connecting, Disconnected, Disconnecting, Reconnecting,
+    };
+    use crate::device_view::DeviceViewMessage::{
+        HistoryLoaded, MessageSearchMsg, NewWaypoint, NodeListMsg, ShowChannel, ShowMessage,
+        ShowNodeDetail, ShowNodes, ShowSearch, StopEditingWaypoint,
+    };
+    use crate::device_view::{DeviceView, NodesView};
+    use crate::history::ChannelHistory;
+    use crate::node_list::{NodeListMessage, NodeSort};
+    use crate::search::MessageSearchMessage;
+    use crate::telemetry::Metric;
+    use crate::traceroute::Traceroute;
+    use meshtastic::Message as _;
+    use meshtastic::protobufs::config::device_config;
+    use meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded;
+    use meshtastic::protobufs::telemetry::Variant::{DeviceMetrics, EnvironmentMetrics};
+    use meshtastic::protobufs::{
+        Data, MeshPacket, Neighbor, NeighborInfo, NodeInfo, PortNum, Position, RouteDiscovery,
+        Telemetry, User, Waypoint,
+    };
+
+    fn device_view_with_node(node_num: u32) -> DeviceView {
+        let mut device_view = DeviceView {
+            my_node_num: Some(1000),
+            ..Default::default()
+        };
+        device_view.add_node(NodeInfo {
+            num: node_num,
+            user: Some(User {
+                long_name: "Alice".into(),
+                role: device_config::Role::Client as i32,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        device_view
+    }
+
+    #[test]
+    fn search_and_open_message() {
+        let mut device_view = device_view_with_node(1001);
+        let message = ChannelViewEntry::new(NewTextMessage("Hello there".into()), 1001, 42);
+        device_view
+            .channel_views
+            .get_mut(&Node(1001))
+            .unwrap()
+            .new_message(message);
+
+        let _ = device_view.update(ShowSearch);
+        let _ = device_view.update(MessageSearchMsg(MessageSearchMessage::Query(
+            "hello".into(),
+        )));
+        assert!(device_view.searching_messages);
+
+        let _ = device_view.update(ShowMessage(Node(1001), 42));
+        assert!(!device_view.searching_messages);
+        assert_eq!(device_view.viewing_channel, Some(Node(1001)));
+    }
+
+    #[test]
+    fn drafts_restored_from_history() {
+        let mut device_view = device_view_with_node(1001);
+        let history = |node_num, draft: &str| {
+            (
+                Node(node_num),
+                ChannelHistory {
+                    channel_id: Node(node_num),
+                    draft: draft.into(),
+                    entries: vec![],
+                },
+            )
+        };
+        let _ = device_view.update(HistoryLoaded(
+            [history(1001, "Hi Alice"), history(1002, "Hi Bob")].into(),
+        ));
+        assert_eq!(device_view.channel_views[&Node(1001)].draft(), "Hi Alice");
+
+        // The draft for a node the radio has not told us about yet is restored once it does
+        device_view.add_node(NodeInfo {
+            num: 1002,
+            user: Some(User {
+                long_name: "Bob".into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert_eq!(device_view.channel_views[&Node(1002)].draft(), "Hi Bob");
+    }
+
+    #[test]
+    fn drafts_saved_until_disconnected() {
+        let mut device_view = device_view_with_node(1001);
+        let save =
+            |device_view: &DeviceView| device_view.save_channel_history(Some(Node(1001))).units();
+        for state in [
+            Connecting(DeviceId::Simulated),
+            Connected(DeviceId::Simulated),
+            Reconnecting(DeviceId::Simulated, 1),
+            Disconnecting(DeviceId::Simulated),
+        ] {
+            device_view.connection_state = state;
+            assert_eq!(save(&device_view), 1);
+        }
+        device_view.connection_state = Disconnected(Some(DeviceId::Simulated), None);
+        assert_eq!(save(&device_view), 0);
+    }
+
+    #[test]
+    fn show_node_detail() {
+        let mut device_view = device_view_with_node(1001);
+        let _ = device_view.update(ShowNodeDetail(1001));
+        assert_eq!(device_view.viewing_node, Some(1001));
+
+        let _ = device_view.update(ShowChannel(None));
+        assert_eq!(device_view.viewing_node, None);
+    }
+
+    #[test]
+    fn show_nodes_views() {
+        let mut device_view = device_view_with_node(1001);
+        let _ = device_view.update(ShowNodes(NodesView::Map));
+        assert_eq!(device_view.viewing_nodes, Some(NodesView::Map));
+        let _ = device_view.update(ShowNodes(NodesView::Topology));
+        assert_eq!(device_view.viewing_nodes, Some(NodesView::Topology));
+
+        let _ = device_view.update(ShowNodeDetail(1001));
+        assert_eq!(device_view.viewing_nodes, None);
+        let _ = device_view.update(ShowNodes(NodesView::Map));
+        let _ = device_view.update(ShowChannel(None));
+        assert_eq!(device_view.viewing_nodes, None);
+    }
+
+    #[test]
+    fn node_device_metrics_updated() {
+        let mut device_view = device_view_with_node(1001);
+        let telemetry = Telemetry {
+            variant: Some(DeviceMetrics(meshtastic::protobufs::DeviceMetrics {
+                voltage: Some(3.9),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let _ = device_view.handle_mesh_packet(&MeshPacket {
+            from: 1001,
+            to: u32::MAX,
+            payload_variant: Some(Decoded(Data {
+                portnum: PortNum::TelemetryApp as i32,
+                payload: telemetry.encode_to_vec(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+
+        let metrics = device_view.nodes[&1001].device_metrics.unwrap();
+        assert_eq!(metrics.voltage, Some(3.9));
+        // Our own battery level is only taken from our own node
+        assert_eq!(device_view.battery_level, None);
+    }
+
+    #[test]
+    fn malformed_telemetry_ignored() {
+        let mut device_view = device_view_with_node(1001);
+        let _ = device_view.handle_mesh_packet(&MeshPacket {
+            from: 1001,
+            to: u32::MAX,
+            payload_variant: Some(Decoded(Data {
+                portnum: PortNum::TelemetryApp as i32,
+                payload: vec![0xff],
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+
+        assert!(device_view.nodes[&1001].device_metrics.is_none());
+        assert!(!device_view.telemetry.contains_key(&1001));
+    }
+
+    #[test]
+    fn node_telemetry_stored() {
+        let mut device_view = device_view_with_node(1001);
+        for (rx_time, temperature) in [(100, 20.0), (200, 21.5)] {
+            let telemetry = Telemetry {
+                variant: Some(EnvironmentMetrics(
+                    meshtastic::protobufs::EnvironmentMetrics {
+                        temperature: Some(temperature),
+                        ..Default::default()
+                    },
+                )),
+                ..Default::default()
+            };
+            let _ = device_view.handle_mesh_packet(&MeshPacket {
+                from: 1001,
+                to: u32::MAX,
+                rx_time,
+                payload_variant: Some(Decoded(Data {
+                    portnum: PortNum::TelemetryApp as i32,
+                    payload: telemetry.encode_to_vec(),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            });
+        }
+
+        assert_eq!(
+            device_view.telemetry[&1001].series(Metric::Temperature),
+            vec![(100, 20.0), (200, 21.5)]
+        );
+    }
+
+    #[test]
+    fn traceroute_response_stored() {
+        let mut device_view = device_view_with_node(1001);
+        let route_discovery = RouteDiscovery {
+            route: vec![1002],
+            snr_towards: vec![20, 8],
+            ..Default::default()
+        };
+        // A traceroute for another node passing through ours is ignored
+        let mut packet = MeshPacket {
+            from: 1001,
+            to: 1003,
+            payload_variant: Some(Decoded(Data {
+                portnum: PortNum::TracerouteApp as i32,
+                payload: route_discovery.encode_to_vec(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        let _ = device_view.handle_mesh_packet(&packet);
+        assert!(device_view.traceroutes.is_empty());
+
+        // A malformed response is ignored
+        let _ = device_view.handle_mesh_packet(&MeshPacket {
+            from: 1001,
+            to: 1000,
+            payload_variant: Some(Decoded(Data {
+                portnum: PortNum::TracerouteApp as i32,
+                payload: vec![0xff],
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+        assert!(device_view.traceroutes.is_empty());
+
+        packet.to = 1000;
+        let _ = device_view.handle_mesh_packet(&packet);
+        let Some(Traceroute::Complete { towards, back }) = device_view.traceroutes.get(&1001)
+        else {
+            panic!("Expected a complete traceroute")
+        };
+        let nodes: Vec<u32> = towards.iter().map(|hop| hop.node).collect();
+        assert_eq!(nodes, vec![1000, 1002, 1001]);
+        assert_eq!(towards[2].snr, Some(2.0));
+        assert!(back.is_none());
+    }
+
+    #[test]
+    fn neighbor_info_stored() {
+        let mut device_view = device_view_with_node(1001);
+        // A malformed packet is ignored
+        let _ = device_view.handle_mesh_packet(&MeshPacket {
+            from: 1001,
+            to: u32::MAX,
+            payload_variant: Some(Decoded(Data {
+                portnum: PortNum::NeighborinfoApp as i32,
+                payload: vec![0xff],
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+        assert!(device_view.neighbors.is_empty());
+
+        let neighbor_info = NeighborInfo {
+            node_id: 1001,
+            neighbors: vec![Neighbor {
+                node_id: 1000,
+                snr: 6.5,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let _ = device_view.handle_mesh_packet(&MeshPacket {
+            from: 1001,
+            to: u32::MAX,
+            payload_variant: Some(Decoded(Data {
+                portnum: PortNum::NeighborinfoApp as i32,
+                payload: neighbor_info.encode_to_vec(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+
+        assert_eq!(device_view.neighbors[&1001], neighbor_info.neighbors);
+    }
+
+    fn waypoint_packet(id: u32, waypoint: &Waypoint) -> MeshPacket {
+        MeshPacket {
+            id,
+            from: 1001,
+            to: 1000,
+            payload_variant: Some(Decoded(Data {
+                portnum: PortNum::WaypointApp as i32,
+                payload: waypoint.encode_to_vec(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn waypoint_received_and_deleted() {
+        let mut device_view = device_view_with_node(1001);
+        let mut waypoint = Waypoint {
+            id: 7,
+            latitude_i: Some(515_000_000),
+            longitude_i: Some(-1_000_000),
+            name: "Meet here".into(),
+            ..Default::default()
+        };
+        let _ = device_view.handle_mesh_packet(&waypoint_packet(1, &waypoint));
+        assert_eq!(device_view.waypoints.get(&7), Some(&waypoint));
+        assert!(device_view.channel_views[&Node(1001)].entry(1).is_some());
+
+        waypoint.expire = crate::waypoint::DELETED;
+        let _ = device_view.handle_mesh_packet(&waypoint_packet(2, &waypoint));
+        assert!(device_view.waypoints.is_empty());
+        assert!(device_view.channel_views[&Node(1001)].entry(2).is_some());
+
+        // A malformed waypoint is ignored
+        let mut malformed = waypoint_packet(3, &waypoint);
+        if let Some(Decoded(data)) = &mut malformed.payload_variant {
+            data.payload = vec![0xff];
+        }
+        let _ = device_view.handle_mesh_packet(&malformed);
+        assert!(device_view.channel_views[&Node(1001)].entry(3).is_none());
+    }
+
+    #[test]
+    fn new_waypoint_at_my_position() {
+        let mut device_view = device_view_with_node(1001);
+        let _ = device_view.update(NewWaypoint);
+        assert!(device_view.editing_waypoint.is_none());
+
+        device_view.my_position = Some(Position {
+            latitude_i: Some(515_000_000),
+            longitude_i: Some(-1_000_000),
+            ..Default::default()
+        });
+        let _ = device_view.update(NewWaypoint);
+        assert!(device_view.editing_waypoint.is_some());
+
+        let _ = device_view.update(StopEditingWaypoint);
+        assert!(device_view.editing_waypoint.is_none());
+    }
+
+    #[test]
+    fn nodes_sorted_by_distance() {
+        let position = |latitude_i| Position {
+            latitude_i: Some(latitude_i),
+            longitude_i: Some(-1_000_000),
+            ..Default::default()
+        };
+        let mut device_view = device_view_with_node(1001);
+        device_view.my_position = Some(position(515_000_000));
+        for (num, latitude_i) in [(1001, 516_000_000), (1002, 515_100_000)] {
+            device_view.add_node(NodeInfo {
+                num,
+                user: Some(User {
+                    long_name: format!("Node {num}"),
+                    role: device_config::Role::Client as i32,
+                    ..Default::default()
+                }),
+                position: Some(position(latitude_i)),
+                ..Default::default()
+            });
+        }
+
+        let config = Config::default();
+        let nums = |device_view: &DeviceView| {
+            device_view
+                .sorted_and_filtered(vec![1001, 1002, 1003], &config)
+                .iter()
+                .map(|node| node.num)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(nums(&device_view), vec![1001, 1002]); // sorted by name, 1003 is not known
+
+        let _ = device_view.update(NodeListMsg(NodeListMessage::Sort(NodeSort::Distance)));
+        assert_eq!(nums(&device_view), vec![1002, 1001]);
+    }
+
+    #[test]
+    fn routers_listed_unless_hidden() {
+        let mut device_view = device_view_with_node(1001);
+        device_view.add_node(NodeInfo {
+            num: 1002,
+            user: Some(User {
+                long_name: "Hilltop".into(),
+                role: device_config::Role::Router as i32,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert!(device_view.channel_views.contains_key(&Node(1002)));
+
+        let mut config = Config::default();
+        let nums = |config: &Config| {
+            device_view
+                .sorted_and_filtered(vec![1001, 1002], config)
+                .iter()
+                .map(|node| node.num)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(nums(&config), vec![1001, 1002]);
+
+        config.toggle_role_hidden(device_config::Role::Router as i32);
+        assert_eq!(nums(&config), vec![1001]);
+    }
+
+    #[test]
+    fn packets_update_last_heard() {
+        let mut device_view = device_view_with_node(1001);
+        assert_eq!(device_view.nodes[&1001].last_heard, 0);
+
+        let _ = device_view.handle_mesh_packet(&MeshPacket {
+            from: 1001,
+            to: u32::MAX,
+            rx_time: 1_750_000_000,
+            ..Default::default()
+        });
+        assert_eq!(device_view.nodes[&1001].last_heard, 1_750_000_000);
+
+        let _ = device_view.handle_mesh_packet(&MeshPacket {
+            from: 1001,
+            to: u32::MAX,
+            ..Default::default()
+        });
+        assert!(device_view.nodes[&1001].last_heard > 1_750_000_000);
+    }
+
+    #[test]
+    fn radio_info_kept_with_message() {
+        let mut device_view = device_view_with_node(1001);
+        let _ = device_view.handle_mesh_packet(&MeshPacket {
+            id: 1,
+            from: 1001,
+            to: 1000,
+            rx_snr: 6.25,
+            rx_rssi: -80,
+            hop_start: 3,
+            hop_limit: 1,
+            via_mqtt: true,
+            rx_time: 1_750_000_000,
+            payload_variant: Some(Decoded(Data {
+                portnum: PortNum::TextMessageApp as i32,
+                payload: "Hello".as_bytes().to_vec(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+
+        let entry = device_view.channel_views[&Node(1001)].entry(1).unwrap();
+        let radio_info = entry.radio_info().unwrap();
+        assert_eq!(radio_info.snr, 6.25);
+        assert_eq!(radio_info.rssi, -80);
+        assert_eq!(radio_info.hops, Some(2));
+        assert!(radio_info.via_mqtt);
+        assert_eq!(radio_info.rx_time, 1_750_000_000);
+    }
+}
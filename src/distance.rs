@@ -0,0 +1,113 @@
+//! The distance and compass bearing from our own position to other nodes and positions
+use crate::map::{lat_lon, offset_metres};
+use meshtastic::protobufs::Position;
+use serde::{Deserialize, Serialize};
+
+const METRES_PER_MILE: f64 = 1609.344;
+const FEET_PER_METRE: f64 = 3.28084;
+
+/// The units distances are shown in, set by `distance_unit` in the config file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistanceUnit {
+    #[default]
+    Kilometres,
+    Miles,
+}
+
+/// Return the distance in metres, and the compass bearing in degrees, from one position to another,
+/// if both have a location
+pub fn distance_and_bearing(from: &Position, to: &Position) -> Option<(f64, f64)> {
+    let (east, north) = offset_metres(lat_lon(from)?, lat_lon(to)?);
+    let bearing = east.atan2(north).to_degrees().rem_euclid(360.0);
+    Some((east.hypot(north), bearing))
+}
+
+/// Return the distance in metres from one position to another, if both have a location
+pub fn distance(from: &Position, to: &Position) -> Option<f64> {
+    distance_and_bearing(from, to).map(|(metres, _)| metres)
+}
+
+/// Return the point of the compass, e.g. "NE", closest to a bearing in degrees
+pub fn compass_point(bearing: f64) -> &'static str {
+    const POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    POINTS[((bearing / 45.0).round() as usize) % POINTS.len()]
+}
+
+/// Return a distance as text in `unit`, using metres or feet when it is short
+pub fn distance_text(metres: f64, unit: DistanceUnit) -> String {
+    match unit {
+        DistanceUnit::Kilometres if metres < 1000.0 => format!("{metres:.0} m"),
+        DistanceUnit::Kilometres => format!("{:.1} km", metres / 1000.0),
+        DistanceUnit::Miles if metres < METRES_PER_MILE / 10.0 => {
+            format!("{:.0} ft", metres * FEET_PER_METRE)
+        }
+        DistanceUnit::Miles => format!("{:.1} mi", metres / METRES_PER_MILE),
+    }
+}
+
+/// Return how far, and in which direction, a position is from ours, e.g. "1.2 km NE"
+pub fn distance_and_bearing_text(
+    from: &Position,
+    to: &Position,
+    unit: DistanceUnit,
+) -> Option<String> {
+    let (metres, bearing) = distance_and_bearing(from, to)?;
+    Some(format!(
+        "{} {}",
+        distance_text(metres, unit),
+        compass_point(bearing)
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::distance::DistanceUnit::{Kilometres, Miles};
+    use crate::distance::{
+        compass_point, distance, distance_and_bearing, distance_and_bearing_text, distance_text,
+    };
+    use meshtastic::protobufs::Position;
+
+    fn position(latitude: f64, longitude: f64) -> Position {
+        Position {
+            latitude_i: Some((latitude * 1e7) as i32),
+            longitude_i: Some((longitude * 1e7) as i32),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bearings() {
+        let me = position(51.5, -0.1);
+        let (metres, bearing) = distance_and_bearing(&me, &position(51.51, -0.1)).unwrap();
+        assert!((metres - 1112.0).abs() < 1.0);
+        assert!(bearing.abs() < 0.01);
+
+        let (_, bearing) = distance_and_bearing(&me, &position(51.5, -0.12)).unwrap();
+        assert!((bearing - 270.0).abs() < 0.01);
+
+        assert_eq!(compass_point(0.0), "N");
+        assert_eq!(compass_point(50.0), "NE");
+        assert_eq!(compass_point(200.0), "S");
+        assert_eq!(compass_point(350.0), "N");
+    }
+
+    #[test]
+    fn no_location() {
+        let me = position(51.5, -0.1);
+        assert_eq!(distance(&me, &Position::default()), None);
+        assert_eq!(distance(&Position::default(), &me), None);
+    }
+
+    #[test]
+    fn distances_in_units() {
+        assert_eq!(distance_text(350.4, Kilometres), "350 m");
+        assert_eq!(distance_text(1234.0, Kilometres), "1.2 km");
+        assert_eq!(distance_text(100.0, Miles), "328 ft");
+        assert_eq!(distance_text(3218.7, Miles), "2.0 mi");
+
+        let text =
+            distance_and_bearing_text(&position(51.5, -0.1), &position(51.51, -0.09), Kilometres);
+        assert_eq!(text, Some("1.3 km NE".into()));
+    }
+}
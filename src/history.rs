@@ -0,0 +1,373 @@
+//! A local, on-disk store of the chat history, with a file per device and channel, so that
+//! conversations, and messages typed but not sent yet, survive a disconnection or restart of the app
+use crate::Message;
+use crate::Message::DeviceViewEvent;
+use crate::channel_id::ChannelId;
+use crate::channel_view_entry::ChannelViewEntry;
+use crate::device_id::DeviceId;
+use crate::device_view::DeviceViewMessage::HistoryLoaded;
+use directories::ProjectDirs;
+use iced::Task;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
+use tokio::fs::DirBuilder;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// The contents of a history file for one channel of a device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelHistory {
+    pub channel_id: ChannelId,
+    #[serde(default)]
+    pub draft: String, // message typed in the channel but not sent yet
+    #[serde(default = "Vec::new")]
+    pub entries: Vec<ChannelViewEntry>,
+}
+
+/// Used to number each save of a history file, in the order the UI asked for them
+static SAVE_COUNT: AtomicUsize = AtomicUsize::new(1);
+
+/// The number of the latest save written to each history file. Saving a file holds its lock, so
+/// the saves of a channel are written one at a time, and an older snapshot of a channel, from a
+/// task that ran late, is never written over a newer one
+static SAVES_WRITTEN: LazyLock<std::sync::Mutex<HashMap<PathBuf, Arc<Mutex<usize>>>>> =
+    LazyLock::new(Default::default);
+
+/// Return the directory where the history for a device is stored
+fn device_history_dir(device_id: &DeviceId) -> Option<PathBuf> {
+    ProjectDirs::from("net", "Mackenzie Serres", "meshchat").map(|proj_dirs| {
+        proj_dirs
+            .data_dir()
+            .join("history")
+            .join(device_dir_name(device_id))
+    })
+}
+
+/// Return a directory name for a device that is safe to use on all platforms. Bytes that are
+/// not ASCII letters or digits are percent encoded, so two devices never share a directory
+fn device_dir_name(device_id: &DeviceId) -> String {
+    let prefix = match device_id {
+        DeviceId::Ble(_) => "ble",
+        DeviceId::Serial(_) => "serial",
+        DeviceId::Tcp(_) => "tcp",
+        DeviceId::Simulated => "sim",
+    };
+    let name: String = device_id
+        .to_string()
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() {
+                (b as char).to_string()
+            } else {
+                format!("%{b:02X}")
+            }
+        })
+        .collect();
+    format!("{prefix}_{name}")
+}
+
+/// Return the file name used to store the history of a channel
+fn channel_file_name(channel_id: &ChannelId) -> String {
+    match channel_id {
+        ChannelId::Channel(index) => format!("channel_{index}.toml"),
+        ChannelId::Node(node_id) => format!("node_{node_id}.toml"),
+    }
+}
+
+// Private methods for async reading and writing of history files
+async fn save(
+    dir: PathBuf,
+    channel_id: ChannelId,
+    entries: Vec<ChannelViewEntry>,
+    draft: String,
+    save_number: usize,
+) -> io::Result<()> {
+    let history_path = dir.join(channel_file_name(&channel_id));
+    let save_written = SAVES_WRITTEN
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(history_path.clone())
+        .or_default()
+        .clone();
+    let mut save_written = save_written.lock().await;
+    if *save_written > save_number {
+        return Ok(());
+    }
+
+    DirBuilder::new().recursive(true).create(&dir).await?;
+    let history = ChannelHistory {
+        channel_id: channel_id.clone(),
+        draft,
+        entries,
+    };
+    let history_str = toml::to_string(&history).map_err(io::Error::other)?;
+
+    // Write to a temporary file and then rename it, so a history file is never left half written
+    let temp_path = history_path.with_extension(format!("toml.{save_number}.tmp"));
+    let mut temp_file = File::create(&temp_path).await?;
+    temp_file.write_all(history_str.as_bytes()).await?;
+    temp_file.sync_all().await?;
+    tokio::fs::rename(&temp_path, &history_path).await?;
+    *save_written = save_number;
+    Ok(())
+}
+
+/// Load the history of all the channels of a device, skipping any history files that cannot be
+/// read, which are returned with the error reading them
+async fn load(
+    dir: PathBuf,
+) -> io::Result<(
+    HashMap<ChannelId, ChannelHistory>,
+    Vec<(PathBuf, io::Error)>,
+)> {
+    let mut history = HashMap::new();
+    let mut failures = vec![];
+
+    if !dir.exists() {
+        return Ok((history, failures));
+    }
+
+    let mut dir_entries = tokio::fs::read_dir(&dir).await?;
+    while let Some(dir_entry) = dir_entries.next_entry().await? {
+        let path = dir_entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some("toml") {
+            match load_channel(&path).await {
+                Ok(channel_history) => {
+                    history.insert(channel_history.channel_id.clone(), channel_history);
+                }
+                Err(e) => failures.push((path, e)),
+            }
+        }
+    }
+
+    Ok((history, failures))
+}
+
+async fn load_channel(path: &Path) -> io::Result<ChannelHistory> {
+    let history_str = tokio::fs::read_to_string(path).await?;
+    toml::from_str(&history_str).map_err(io::Error::other)
+}
+
+/// Use `save_history` to save the entries, and any unsent draft, of a channel of a device to disk
+/// from the UI
+pub fn save_history(
+    device_id: &DeviceId,
+    channel_id: &ChannelId,
+    entries: Vec<ChannelViewEntry>,
+    draft: String,
+) -> Task<Message> {
+    if let Some(dir) = device_history_dir(device_id) {
+        let save_number = SAVE_COUNT.fetch_add(1, Ordering::Relaxed);
+        let save = save(dir.clone(), channel_id.clone(), entries, draft, save_number);
+        Task::perform(save, {
+            move |result| match result {
+                Ok(_) => Message::None,
+                Err(e) => Message::AppError(
+                    format!("Error saving history in: '{}'", dir.to_string_lossy()),
+                    e.to_string(),
+                ),
+            }
+        })
+    } else {
+        Task::none()
+    }
+}
+
+/// Use `load_history` to load all the stored history of a device from disk from the UI.
+/// A history file that cannot be read is reported, and the history in the others is still loaded
+pub fn load_history(device_id: &DeviceId) -> Task<Message> {
+    if let Some(dir) = device_history_dir(device_id) {
+        Task::future(load(dir.clone())).then(move |result| match result {
+            Ok((history, failures)) => Task::batch(
+                std::iter::once(Task::done(DeviceViewEvent(HistoryLoaded(history)))).chain(
+                    failures.into_iter().map(|(path, e)| {
+                        Task::done(Message::AppError(
+                            format!("Error loading history from: '{}'", path.to_string_lossy()),
+                            e.to_string(),
+                        ))
+                    }),
+                ),
+            ),
+            Err(e) => Task::done(Message::AppError(
+                format!("Error loading history from: '{}'", dir.to_string_lossy()),
+                e.to_string(),
+            )),
+        })
+    } else {
+        Task::none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::channel_id::ChannelId;
+    use crate::channel_view_entry::Payload::{NewTextMessage, TextMessageReply};
+    use crate::channel_view_entry::{ChannelViewEntry, DeliveryStatus};
+    use crate::device_id::DeviceId;
+    use crate::history::{device_dir_name, load, save};
+
+    #[test]
+    fn device_dir_names() {
+        assert_eq!(
+            device_dir_name(&DeviceId::Serial("/dev/ttyACM0".into())),
+            "serial_%2Fdev%2FttyACM0"
+        );
+        assert_eq!(
+            device_dir_name(&DeviceId::Tcp("localhost:4403".into())),
+            "tcp_localhost%3A4403"
+        );
+        // Names that differ only in punctuation get different directories
+        assert_ne!(
+            device_dir_name(&DeviceId::Tcp("host-a:4403".into())),
+            device_dir_name(&DeviceId::Tcp("host_a:4403".into()))
+        );
+        assert_ne!(
+            device_dir_name(&DeviceId::Serial("/dev/tty.usb-1".into())),
+            device_dir_name(&DeviceId::Serial("/dev/tty_usb_1".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn load_missing_history() {
+        let tempdir = tempfile::Builder::new()
+            .prefix("meshchat")
+            .tempdir()
+            .expect("Could not create a temp dir for test");
+        let (history, failures) = load(tempdir.path().join("no_such_device"))
+            .await
+            .expect("Could not load history");
+        assert!(history.is_empty());
+        assert!(failures.is_empty());
+    }
+
+    #[tokio::test]
+    async fn save_and_load_history() {
+        let tempdir = tempfile::Builder::new()
+            .prefix("meshchat")
+            .tempdir()
+            .expect("Could not create a temp dir for test");
+
+        let mut message = ChannelViewEntry::new(NewTextMessage("Hello".into()), 1, 100);
+        message.seen = true;
+        message.ack();
+        message.add_emoji("👍".into(), 2);
+        let reply = ChannelViewEntry::new(TextMessageReply(100, "Hi".into()), 2, 101);
+
+        save(
+            tempdir.path().to_path_buf(),
+            ChannelId::Channel(0),
+            vec![message.clone(), reply.clone()],
+            String::new(),
+            1,
+        )
+        .await
+        .expect("Could not save history");
+        save(
+            tempdir.path().to_path_buf(),
+            ChannelId::Node(2),
+            vec![reply.clone()],
+            "Unsent\nreply".into(),
+            2,
+        )
+        .await
+        .expect("Could not save history");
+
+        let (history, _) = load(tempdir.path().to_path_buf())
+            .await
+            .expect("Could not load history");
+        assert_eq!(history.len(), 2);
+
+        let channel_entries = &history.get(&ChannelId::Channel(0)).unwrap().entries;
+        assert_eq!(channel_entries.len(), 2);
+        let loaded = &channel_entries[0];
+        assert_eq!(loaded.message_id(), 100);
+        assert_eq!(loaded.from(), 1);
+        assert_eq!(loaded.time(), message.time());
+        assert_eq!(loaded.payload().to_string(), "Hello");
+        assert!(loaded.seen);
+        assert_eq!(loaded.status(), Some(&DeliveryStatus::Acked));
+        assert_eq!(loaded.emojis().get("👍"), Some(&vec![2]));
+        assert!(matches!(
+            channel_entries[1].payload(),
+            TextMessageReply(100, _)
+        ));
+
+        assert!(
+            history
+                .get(&ChannelId::Channel(0))
+                .unwrap()
+                .draft
+                .is_empty()
+        );
+
+        let node_history = history.get(&ChannelId::Node(2)).unwrap();
+        assert_eq!(node_history.entries.len(), 1);
+        assert_eq!(node_history.draft, "Unsent\nreply");
+    }
+
+    #[tokio::test]
+    async fn corrupt_history_skipped() {
+        let tempdir = tempfile::Builder::new()
+            .prefix("meshchat")
+            .tempdir()
+            .expect("Could not create a temp dir for test");
+        let message = ChannelViewEntry::new(NewTextMessage("Hello".into()), 1, 100);
+        save(
+            tempdir.path().to_path_buf(),
+            ChannelId::Channel(0),
+            vec![message],
+            String::new(),
+            1,
+        )
+        .await
+        .expect("Could not save history");
+        let corrupt_path = tempdir.path().join("channel_1.toml");
+        std::fs::write(&corrupt_path, "channel_id = { Chan").expect("Could not write file");
+
+        let (history, failures) = load(tempdir.path().to_path_buf())
+            .await
+            .expect("Could not load history");
+        assert_eq!(history.len(), 1);
+        assert!(history.contains_key(&ChannelId::Channel(0)));
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, corrupt_path);
+    }
+
+    #[tokio::test]
+    async fn older_save_not_written_over_newer() {
+        let tempdir = tempfile::Builder::new()
+            .prefix("meshchat")
+            .tempdir()
+            .expect("Could not create a temp dir for test");
+        let older = ChannelViewEntry::new(NewTextMessage("Hello".into()), 1, 100);
+        let newer = ChannelViewEntry::new(NewTextMessage("Hello again".into()), 1, 101);
+        save(
+            tempdir.path().to_path_buf(),
+            ChannelId::Channel(0),
+            vec![older.clone(), newer],
+            String::new(),
+            2,
+        )
+        .await
+        .expect("Could not save history");
+        save(
+            tempdir.path().to_path_buf(),
+            ChannelId::Channel(0),
+            vec![older],
+            String::new(),
+            1,
+        )
+        .await
+        .expect("Could not save history");
+
+        let (history, _) = load(tempdir.path().to_path_buf())
+            .await
+            .expect("Could not load history");
+        assert_eq!(history[&ChannelId::Channel(0)].entries.len(), 2);
+    }
+}
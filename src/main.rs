@@ -2,38 +2,50 @@
 //! meshtastic compatible radios connected to the host running it
 
 use crate::Message::{
-    AddDeviceAlias, AddNodeAlias, AppError, AppNotification, ConfigChange, CopyToClipBoard,
-    DeviceListViewEvent, DeviceViewEvent, Exit, Navigation, NewConfig, RemoveDeviceAlias,
-    RemoveNodeAlias, RemoveNotification, ShowLocation, ToggleNodeFavourite, WindowEvent,
+    AddDeviceAlias, AddNodeAlias, AddTcpDevice, AppError, AppNotification, ConfigChange,
+    CopyToClipBoard, DeviceListViewEvent, DeviceViewEvent, Exit, Navigation, NewConfig,
+    RemoveDeviceAlias, RemoveNodeAlias, RemoveNotification, RemoveTcpDevice, ShowLocation,
+    ToggleNodeFavourite, ToggleRoleHidden, WindowEvent,
 };
 use crate::View::DeviceList;
+use crate::bridge::BridgeEvent;
 use crate::channel_id::ChannelId;
 use crate::config::{Config, load_config, save_config};
-use crate::device_list_view::{DeviceListEvent, DeviceListView, ble_discovery};
-use crate::device_view::ConnectionState::{Connected, Connecting, Disconnecting};
+use crate::device_id::DeviceId;
+use crate::device_list_view::{DeviceListEvent, DeviceListView, ble_discovery, serial_discovery};
+use crate::device_subscription::SubscriptionEvent;
+use crate::device_view::ConnectionState::{Connected, Connecting, Disconnecting, Reconnecting};
 use crate::device_view::DeviceView;
 use crate::device_view::DeviceViewMessage;
 use crate::device_view::DeviceViewMessage::{DisconnectRequest, SubscriptionMessage};
 use crate::linear::Linear;
 use crate::notification::{Notification, Notifications};
-use btleplug::api::BDAddr;
 use iced::keyboard::key;
 use iced::widget::{Column, Space, operation};
 use iced::window::icon;
 use iced::{Element, Fill, event};
-use iced::{Event, Subscription, Task, clipboard, keyboard, window};
+use iced::{Event, Subscription, Task, clipboard, keyboard, time, window};
 use std::cmp::PartialEq;
 use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
 
 mod battery;
+mod bridge;
 mod channel_view;
 mod channel_view_entry;
+mod cli;
+mod composer;
 mod config;
+mod device_id;
 mod device_list_view;
 mod device_subscription;
 mod device_view;
+mod distance;
 mod easing;
+mod history;
 mod linear;
+mod map;
+mod message_parts;
 mod styles;
 
 #[rustfmt::skip]
@@ -41,9 +53,20 @@ mod styles;
 mod icons;
 mod channel_id;
 mod emoji_picker;
+mod node_detail;
+mod node_list;
 mod notification;
+mod outbox;
+mod presence;
+mod received_message;
+mod search;
+mod simulator;
+mod telemetry;
 #[cfg(test)]
 mod test_helper;
+mod topology;
+mod traceroute;
+mod waypoint;
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub enum View {
@@ -59,11 +82,12 @@ struct MeshChat {
     device_list_view: DeviceListView,
     device_view: DeviceView,
     notifications: Notifications,
+    bridge: Option<UnboundedSender<SubscriptionEvent>>, // to pass radio events to the bridge
 }
 
 #[derive(Debug, Clone)]
 pub enum ConfigChangeMessage {
-    DeviceAndChannel(Option<BDAddr>, Option<ChannelId>),
+    DeviceAndChannel(Option<DeviceId>, Option<ChannelId>),
 }
 
 /// These are the messages that MeshChat responds to
@@ -81,16 +105,25 @@ pub enum Message {
     AppError(String, String),
     RemoveNotification(usize),
     ToggleNodeFavourite(u32),
+    ToggleRoleHidden(i32), // hide or show nodes with a device_config::Role
     CopyToClipBoard(String),
     AddNodeAlias(u32, String),
     RemoveNodeAlias(u32),
-    AddDeviceAlias(BDAddr, String),
-    RemoveDeviceAlias(BDAddr),
+    AddDeviceAlias(DeviceId, String),
+    RemoveDeviceAlias(DeviceId),
+    AddTcpDevice(String), // Address entered by the user, maybe without a port
+    RemoveTcpDevice(DeviceId),
+    Bridge(BridgeEvent),
     Event(Event),
     None,
 }
 
 fn main() -> iced::Result {
+    // Run a command given on the command line without the UI, e.g. "meshchat send Hello"
+    if let Some(command) = cli::parse_args(std::env::args().skip(1)) {
+        std::process::exit(cli::run(command));
+    }
+
 	let icon_bytes = include_bytes!("../assets/images/icon.ico");
 	
 	let app_icon = icon::from_file_data(icon_bytes, None);
@@ -99,7 +132,7 @@ fn main() -> iced::Result {
     //    .expect("Failed to load window icon from bytes");
 	
 	let window_settings = window::Settings {
-		icon: Some(app_icon),
+		icon: app_icon.ok(),
 		..Default::default()
 	};
 	
@@ -144,7 +177,15 @@ impl MeshChat {
             DeviceListViewEvent(device_list_event) => {
                 self.device_list_view.update(device_list_event)
             }
-            DeviceViewEvent(device_event) => self.device_view.update(device_event),
+            DeviceViewEvent(device_event) => {
+                // Pass events from the radio on to the bridge, for other programs to use
+                if let SubscriptionMessage(event) = &device_event
+                    && let Some(bridge) = &self.bridge
+                {
+                    let _ = bridge.send(event.clone());
+                }
+                self.device_view.update(device_event)
+            }
             Exit => window::latest().and_then(window::close),
             AppNotification(summary, detail) => {
                 self.notifications.add(Notification::Info(summary, detail))
@@ -155,9 +196,19 @@ impl MeshChat {
             Message::None => Task::none(),
             NewConfig(config) => {
                 self.config = config;
-                if let Some(mac_address) = &self.config.device_mac_address {
+                self.device_view
+                    .set_message_retries(self.config.message_retries);
+                self.device_view
+                    .set_join_message_parts(self.config.join_message_parts);
+                if self.config.simulated_radio {
+                    self.device_list_view.add_device(DeviceId::Simulated);
+                }
+                for device_id in &self.config.tcp_devices {
+                    self.device_list_view.add_tcp_device(device_id.clone());
+                }
+                if let Some(device_id) = &self.config.device {
                     self.device_view.update(DeviceViewMessage::ConnectRequest(
-                        *mac_address,
+                        device_id.clone(),
                         self.config.channel_id.clone(),
                     ))
                 } else {
@@ -167,8 +218,8 @@ impl MeshChat {
             ConfigChange(config_change) => {
                 // Merge in what has changed
                 match config_change {
-                    ConfigChangeMessage::DeviceAndChannel(mac_address, channel) => {
-                        self.config.device_mac_address = mac_address;
+                    ConfigChangeMessage::DeviceAndChannel(device_id, channel) => {
+                        self.config.device = device_id;
                         self.config.channel_id = channel;
                     }
                 }
@@ -188,6 +239,10 @@ impl MeshChat {
                 // and save the config asynchronously, so that we don't block the GUI thread
                 save_config(&self.config)
             }
+            ToggleRoleHidden(role) => {
+                self.config.toggle_role_hidden(role);
+                save_config(&self.config)
+            }
             CopyToClipBoard(string) => clipboard::write(string),
             AddNodeAlias(node_id, alias) => {
                 self.device_view.stop_editing_alias();
@@ -202,19 +257,48 @@ impl MeshChat {
                 self.config.aliases.remove(&node_id);
                 save_config(&self.config)
             }
-            AddDeviceAlias(mac_address, alias) => {
+            AddDeviceAlias(device_id, alias) => {
                 self.device_list_view.stop_editing_alias();
                 if !alias.is_empty() {
-                    self.config.device_aliases.insert(mac_address, alias);
+                    self.config.device_aliases.insert(device_id, alias);
                     save_config(&self.config)
                 } else {
                     Task::none()
                 }
             }
-            RemoveDeviceAlias(mac_address) => {
-                self.config.device_aliases.remove(&mac_address);
+            RemoveDeviceAlias(device_id) => {
+                self.config.device_aliases.remove(&device_id);
+                save_config(&self.config)
+            }
+            AddTcpDevice(address) => {
+                if address.trim().is_empty() {
+                    return Task::none();
+                }
+                let device_id = DeviceId::tcp(&address);
+                self.device_list_view.add_tcp_device(device_id.clone());
+                if !self.config.tcp_devices.contains(&device_id) {
+                    self.config.tcp_devices.push(device_id);
+                }
+                save_config(&self.config)
+            }
+            RemoveTcpDevice(device_id) => {
+                self.device_list_view.remove_tcp_device(&device_id);
+                self.config.tcp_devices.retain(|id| id != &device_id);
+                self.config.device_aliases.remove(&device_id);
                 save_config(&self.config)
             }
+            Message::Bridge(bridge_event) => match bridge_event {
+                BridgeEvent::Ready(sender) => {
+                    self.bridge = Some(sender);
+                    Task::none()
+                }
+                BridgeEvent::Request(device_view_message) => {
+                    self.device_view.update(device_view_message)
+                }
+                BridgeEvent::Error(summary, detail) => {
+                    self.notifications.add(Notification::Error(summary, detail))
+                }
+            },
             Message::Event(event) => match event {
                 Event::Keyboard(keyboard::Event::KeyPressed {
                     key: keyboard::Key::Named(key::Named::Tab),
@@ -262,7 +346,7 @@ impl MeshChat {
         let mut stack = Column::new().push(header);
 
         // If busy of connecting or disconnecting, add a busy bar to the header
-        if scanning || matches!(state, Connecting(_) | Disconnecting(_)) {
+        if scanning || matches!(state, Connecting(_) | Reconnecting(_, _) | Disconnecting(_)) {
             stack = stack.push(Space::new().width(Fill)).push(
                 Linear::new()
                     .easing(easing::emphasized_accelerate())
@@ -287,14 +371,36 @@ impl MeshChat {
 
     /// Subscribe to events from Discover and from Windows and from Devices (Radios)
     fn subscription(&self) -> Subscription<Message> {
-        let subscriptions = vec![
+        let mut subscriptions = vec![
             event::listen().map(WindowEvent),
             Subscription::run(ble_discovery).map(DeviceListViewEvent),
+            Subscription::run(serial_discovery).map(DeviceListViewEvent),
             Subscription::run(device_subscription::subscribe)
                 .map(|m| DeviceViewEvent(SubscriptionMessage(m))),
             event::listen().map(Message::Event),
         ];
 
+        // Let other programs use the radio via the bridge, if it is enabled
+        if let Some(port) = self.config.bridge_port {
+            subscriptions.push(Subscription::run_with(port, bridge::serve).map(Message::Bridge));
+        }
+
+        // Redraw the presence of nodes as time passes, while connected to a radio
+        if matches!(self.device_view.connection_state(), Connected(_)) {
+            subscriptions.push(
+                time::every(Duration::from_secs(30))
+                    .map(|_| DeviceViewEvent(DeviceViewMessage::RefreshPresence)),
+            );
+        }
+
+        // Check for messages that have not been acknowledged in time, while there are any
+        if self.device_view.awaiting_delivery() {
+            subscriptions.push(
+                time::every(Duration::from_secs(10))
+                    .map(|_| DeviceViewEvent(DeviceViewMessage::CheckOutbox)),
+            );
+        }
+
         Subscription::batch(subscriptions)
     }
 
@@ -312,9 +418,11 @@ impl MeshChat {
     /// Handle window events, like close button or minimize button
     fn window_handler(&mut self, event: Event) -> Task<Message> {
         if let Event::Window(window::Event::CloseRequested) = event {
-            if let Connected(mac_address) = self.device_view.connection_state() {
+            if let Connected(device_id) | Reconnecting(device_id, _) =
+                self.device_view.connection_state()
+            {
                 self.device_view
-                    .update(DisconnectRequest(*mac_address, true))
+                    .update(DisconnectRequest(device_id.clone(), true))
             } else {
                 window::latest().and_then(window::close)
             }
@@ -327,7 +435,10 @@ impl MeshChat {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::channel_view_entry::Payload;
+    use crate::channel_view_entry::{DeliveryStatus, Payload};
+    use crate::device_subscription::SubscriberMessage::SendText;
+    use crate::device_subscription::SubscriptionEvent;
+    use crate::simulator::SimulatedRadio;
 
     #[test]
     fn test_location_url() {
@@ -354,6 +465,167 @@ mod tests {
         assert_eq!(test_app.title(), "MeshChat (1 unread)".to_string());
     }
 
+    #[test]
+    fn reconnecting_keeps_messages() {
+        let mut test_app = test_helper::test_app();
+        test_app.new_message(Payload::NewTextMessage("Hello World".into()));
+        let device_id = DeviceId::Tcp("localhost:4403".into());
+
+        let _ = test_app.update(DeviceViewEvent(SubscriptionMessage(
+            SubscriptionEvent::ReconnectingEvent(device_id.clone(), 2),
+        )));
+        assert!(matches!(
+            test_app.device_view.connection_state(),
+            Reconnecting(id, 2) if *id == device_id
+        ));
+        assert_eq!(test_app.title(), "MeshChat (1 unread)".to_string());
+
+        let _ = test_app.update(DeviceViewEvent(SubscriptionMessage(
+            SubscriptionEvent::ConnectedEvent(device_id.clone()),
+        )));
+        assert!(matches!(
+            test_app.device_view.connection_state(),
+            Connected(id) if *id == device_id
+        ));
+        assert_eq!(test_app.title(), "MeshChat (1 unread)".to_string());
+    }
+
+    #[test]
+    fn message_queued_until_sent() {
+        let mut test_app = test_helper::test_app();
+        let _ = test_app.update(DeviceViewEvent(DeviceViewMessage::SendTextMessage(
+            "Hello".into(),
+            ChannelId::Channel(0),
+            None,
+        )));
+        assert!(test_app.device_view.awaiting_delivery());
+
+        let channel_view = &test_app.device_view.channel_views[&ChannelId::Channel(0)];
+        let entries = channel_view.history();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].status(), Some(&DeliveryStatus::Queued));
+
+        let _ = test_app.update(DeviceViewEvent(SubscriptionMessage(
+            SubscriptionEvent::MessageSent(entries[0].message_id()),
+        )));
+        let channel_view = &test_app.device_view.channel_views[&ChannelId::Channel(0)];
+        assert_eq!(
+            channel_view.history()[0].status(),
+            Some(&DeliveryStatus::Sent)
+        );
+    }
+
+    #[test]
+    fn long_message_sent_in_parts() {
+        let mut test_app = test_helper::test_app();
+        let _ = test_app.update(DeviceViewEvent(DeviceViewMessage::SendTextMessage(
+            "word ".repeat(50),
+            ChannelId::Channel(0),
+            Some(42),
+        )));
+
+        let entries = test_app.device_view.channel_views[&ChannelId::Channel(0)].history();
+        assert_eq!(entries.len(), 2);
+        assert!(
+            matches!(entries[0].payload(), Payload::TextMessageReply(42, text) if text.starts_with("1/2 word"))
+        );
+        assert!(
+            matches!(entries[1].payload(), Payload::NewTextMessage(text) if text.starts_with("2/2 word"))
+        );
+    }
+
+    #[tokio::test]
+    async fn chat_with_simulated_radio() {
+        let radio = SimulatedRadio::new(1000, "ME", "Me")
+            .node(1001, "ALIC", "Alice")
+            .node(1003, "CARL", "Carol")
+            .channel_message(Duration::ZERO, 1001, 0, "Hello")
+            .unreachable(1003)
+            .echo(true);
+        let mut app = MeshChat::default();
+        app.device_view.set_message_retries(Some(0));
+        let (mut events, sender) = test_helper::connect_simulated(&mut app, radio).await;
+
+        test_helper::run_until(&mut app, &mut events, |app| {
+            app.title() == "MeshChat (1 unread)"
+        })
+        .await;
+        assert!(matches!(app.device_view.connection_state(), Connected(_)));
+
+        // A direct message to Alice is acknowledged by her and echoed back
+        let alice = ChannelId::Node(1001);
+        let _ = app.update(DeviceViewEvent(DeviceViewMessage::SendTextMessage(
+            "Hi Alice".into(),
+            alice.clone(),
+            None,
+        )));
+        let sent_id = test_helper::entries(&app, &alice)[0].message_id();
+        let _ = sender
+            .send(SendText("Hi Alice".into(), alice.clone(), None, sent_id))
+            .await;
+        test_helper::run_until(&mut app, &mut events, |app| {
+            let entries = test_helper::entries(app, &alice);
+            entries.len() == 2
+                && entries.iter().any(|entry| {
+                    entry.message_id() == sent_id && entry.status() == Some(&DeliveryStatus::Acked)
+                })
+        })
+        .await;
+        assert!(
+            test_helper::entries(&app, &alice)
+                .iter()
+                .all(|entry| entry.payload().to_string() == "Hi Alice")
+        );
+
+        // A direct message to Carol fails, as she is out of range
+        let carol = ChannelId::Node(1003);
+        let _ = app.update(DeviceViewEvent(DeviceViewMessage::SendTextMessage(
+            "Hi Carol".into(),
+            carol.clone(),
+            None,
+        )));
+        let sent_id = test_helper::entries(&app, &carol)[0].message_id();
+        let _ = sender
+            .send(SendText("Hi Carol".into(), carol.clone(), None, sent_id))
+            .await;
+        test_helper::run_until(&mut app, &mut events, |app| {
+            matches!(
+                test_helper::entries(app, &carol)[0].status(),
+                Some(DeliveryStatus::Failed(_))
+            )
+        })
+        .await;
+        assert!(!app.device_view.awaiting_delivery());
+    }
+
+    #[tokio::test]
+    async fn reconnect_to_simulated_radio() {
+        let radio = SimulatedRadio::new(1000, "ME", "Me")
+            .channel_message(Duration::ZERO, 1000, 0, "Hello")
+            .drop_links(1);
+        let mut app = MeshChat::default();
+        let (mut events, _sender) = test_helper::connect_simulated(&mut app, radio).await;
+
+        test_helper::run_until(&mut app, &mut events, |app| {
+            matches!(app.device_view.connection_state(), Reconnecting(_, 1))
+        })
+        .await;
+
+        // Once reconnected, the channels are not duplicated, and the message is received.
+        // The config is sent again before the message, so all the channels are known by then
+        test_helper::run_until(&mut app, &mut events, |app| {
+            !test_helper::entries(app, &ChannelId::Channel(0)).is_empty()
+        })
+        .await;
+        assert!(matches!(app.device_view.connection_state(), Connected(_)));
+        let mut channel_ids: Vec<_> = app.device_view.channel_views.keys().collect();
+        channel_ids.sort_by_key(|channel_id| format!("{channel_id:?}"));
+        assert_eq!(
+            channel_ids,
+            vec![&ChannelId::Channel(0), &ChannelId::Node(1000)]
+        );
+    }
+
     #[test]
     fn test_default_view() {
         let meshchat = test_helper::test_app();
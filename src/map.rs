@@ -0,0 +1,388 @@
+//! An offline map of the nodes that have shared their position, drawn on a canvas relative to our
+//! own position, so it needs no network connection or map tile server.
+//! Clicking on a node opens the channel to send it direct messages.
+use crate::Message;
+use crate::Message::DeviceViewEvent;
+use crate::channel_id::ChannelId;
+use crate::config::Config;
+use crate::device_view::DeviceViewMessage::ShowChannel;
+use crate::styles::{COLOR_BLUE, COLOR_GRAY_50, COLOR_YELLOW};
+use iced::widget::canvas::{self, Action, Event, Frame, Geometry, Path, Stroke, Text};
+use iced::{Color, Point, Rectangle, Renderer, Size, Theme, Vector, mouse};
+use meshtastic::protobufs::{NodeInfo, Position};
+use std::collections::HashMap;
+
+/// Mean radius of the Earth in metres
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Radius of the dot drawn for a node, and how close a click needs to be to select it
+const NODE_RADIUS: f32 = 5.0;
+const CLICK_DISTANCE: f32 = 12.0;
+
+/// Space to leave around the nodes furthest from the centre, so their labels fit
+const MARGIN: f32 = 60.0;
+
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 256.0;
+
+/// A node to draw on the map, with its offset from the centre of the map in metres
+struct MapNode {
+    num: u32,
+    label: String,
+    east: f64,
+    north: f64,
+    favourite: bool,
+}
+
+/// The zoom level chosen by the user, with the mouse wheel
+pub struct MapState {
+    zoom: f32,
+}
+
+impl Default for MapState {
+    fn default() -> Self {
+        MapState { zoom: 1.0 }
+    }
+}
+
+/// A map of the nodes with known positions, centred on our own position if we know it
+pub struct NodeMap {
+    nodes: Vec<MapNode>,
+    centred_on_me: bool,
+}
+
+impl NodeMap {
+    pub fn new(
+        my_node_num: Option<u32>,
+        my_position: Option<&Position>,
+        nodes: &HashMap<u32, NodeInfo>,
+        config: &Config,
+    ) -> Self {
+        let located: Vec<(&NodeInfo, (f64, f64))> = nodes
+            .values()
+            .filter(|node_info| Some(node_info.num) != my_node_num)
+            .filter_map(|node_info| Some((node_info, lat_lon(node_info.position.as_ref()?)?)))
+            .collect();
+
+        // Without our own position, centre the map on the middle of the other nodes
+        let my_lat_lon = my_position.and_then(lat_lon);
+        let centre = my_lat_lon.unwrap_or_else(|| {
+            let count = located.len().max(1) as f64;
+            let (lat, lon) = located
+                .iter()
+                .fold((0.0, 0.0), |(lat, lon), (_, (node_lat, node_lon))| {
+                    (lat + node_lat, lon + node_lon)
+                });
+            (lat / count, lon / count)
+        });
+
+        let nodes = located
+            .into_iter()
+            .map(|(node_info, lat_lon)| {
+                let (east, north) = offset_metres(centre, lat_lon);
+                MapNode {
+                    num: node_info.num,
+                    label: node_label(node_info.num, Some(node_info), config),
+                    east,
+                    north,
+                    favourite: config.fav_nodes.contains(&node_info.num),
+                }
+            })
+            .collect();
+
+        NodeMap {
+            nodes,
+            centred_on_me: my_lat_lon.is_some(),
+        }
+    }
+
+    /// Return how many pixels are used to draw a metre, to fit all the nodes in `size`
+    fn pixels_per_metre(&self, size: Size, zoom: f32) -> f64 {
+        let furthest = self
+            .nodes
+            .iter()
+            .map(|node| node.east.abs().max(node.north.abs()))
+            .fold(100.0, f64::max); // fit at least 100m, to not zoom in too far
+        let half_size = (size.width.min(size.height) / 2.0 - MARGIN).max(10.0) as f64;
+        half_size / furthest * zoom as f64
+    }
+
+    /// Return where to draw a node within `bounds`
+    fn node_point(&self, node: &MapNode, size: Size, scale: f64) -> Point {
+        Point::new(
+            size.width / 2.0 + (node.east * scale) as f32,
+            size.height / 2.0 - (node.north * scale) as f32,
+        )
+    }
+
+    /// Return the node drawn at (or near) a point on the map, if there is one
+    fn node_at(&self, point: Point, size: Size, zoom: f32) -> Option<&MapNode> {
+        let scale = self.pixels_per_metre(size, zoom);
+        self.nodes
+            .iter()
+            .map(|node| (node, self.node_point(node, size, scale).distance(point)))
+            .filter(|(_, distance)| *distance <= CLICK_DISTANCE)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(node, _)| node)
+    }
+
+    /// Draw a scale bar in the bottom left corner, of a round distance
+    fn draw_scale(frame: &mut Frame, scale: f64, color: Color) {
+        let metres = scale_distance(frame.width() as f64 / 4.0 / scale);
+        let length = (metres * scale) as f32;
+        let y = frame.height() - 20.0;
+        let bar = Path::new(|path| {
+            path.move_to(Point::new(20.0, y - 5.0));
+            path.line_to(Point::new(20.0, y));
+            path.line_to(Point::new(20.0 + length, y));
+            path.line_to(Point::new(20.0 + length, y - 5.0));
+        });
+        frame.stroke(&bar, Stroke::default().with_color(color).with_width(2.0));
+        frame.fill_text(Text {
+            content: distance_text(metres),
+            position: Point::new(20.0, y - 20.0),
+            color,
+            ..Default::default()
+        });
+    }
+}
+
+impl canvas::Program<Message> for NodeMap {
+    type State = MapState;
+
+    fn update(
+        &self,
+        state: &mut Self::State,
+        event: &Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> Option<Action<Message>> {
+        let point = cursor.position_in(bounds)?;
+        match event {
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) => {
+                let lines = match delta {
+                    mouse::ScrollDelta::Lines { y, .. } => *y,
+                    mouse::ScrollDelta::Pixels { y, .. } => *y / 20.0,
+                };
+                state.zoom = (state.zoom * 1.2f32.powf(lines)).clamp(MIN_ZOOM, MAX_ZOOM);
+                Some(Action::request_redraw().and_capture())
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let node = self.node_at(point, bounds.size(), state.zoom)?;
+                Some(
+                    Action::publish(DeviceViewEvent(ShowChannel(Some(ChannelId::Node(
+                        node.num,
+                    )))))
+                    .and_capture(),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    fn draw(
+        &self,
+        state: &Self::State,
+        renderer: &Renderer,
+        theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let text_color = theme.palette().text;
+        let size = bounds.size();
+        let scale = self.pixels_per_metre(size, state.zoom);
+        let centre = frame.center();
+
+        // Our own position, or a cross marking the middle of the nodes if we don't know it
+        if self.centred_on_me {
+            frame.fill(&Path::circle(centre, NODE_RADIUS + 2.0), COLOR_BLUE);
+            frame.fill_text(Text {
+                content: "Me".into(),
+                position: centre + Vector::new(NODE_RADIUS + 6.0, -8.0),
+                color: COLOR_BLUE,
+                ..Default::default()
+            });
+        } else {
+            let cross = Path::new(|path| {
+                path.move_to(centre + Vector::new(-6.0, 0.0));
+                path.line_to(centre + Vector::new(6.0, 0.0));
+                path.move_to(centre + Vector::new(0.0, -6.0));
+                path.line_to(centre + Vector::new(0.0, 6.0));
+            });
+            frame.stroke(&cross, Stroke::default().with_color(COLOR_GRAY_50));
+            frame.fill_text(Text {
+                content: "Our position is not known".into(),
+                position: Point::new(20.0, 20.0),
+                color: text_color,
+                ..Default::default()
+            });
+        }
+
+        for node in &self.nodes {
+            let point = self.node_point(node, size, scale);
+            let color = if node.favourite {
+                COLOR_YELLOW
+            } else {
+                text_color
+            };
+            frame.fill(&Path::circle(point, NODE_RADIUS), color);
+            frame.fill_text(Text {
+                content: node.label.clone(),
+                position: point + Vector::new(NODE_RADIUS + 4.0, -8.0),
+                color,
+                ..Default::default()
+            });
+        }
+
+        Self::draw_scale(&mut frame, scale, text_color);
+
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(
+        &self,
+        state: &Self::State,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> mouse::Interaction {
+        match cursor.position_in(bounds) {
+            Some(point) if self.node_at(point, bounds.size(), state.zoom).is_some() => {
+                mouse::Interaction::Pointer
+            }
+            _ => mouse::Interaction::default(),
+        }
+    }
+}
+
+/// Return a short label for a node drawn on a map or graph: its alias, short name or node id
+pub fn node_label(num: u32, node_info: Option<&NodeInfo>, config: &Config) -> String {
+    config
+        .aliases
+        .get(&num)
+        .cloned()
+        .or(node_info
+            .and_then(|node_info| node_info.user.as_ref())
+            .map(|user| user.short_name.clone()))
+        .unwrap_or(format!("!{num:08x}"))
+}
+
+/// Return the latitude and longitude of a position in degrees, if it has them
+pub fn lat_lon(position: &Position) -> Option<(f64, f64)> {
+    match (position.latitude_i, position.longitude_i) {
+        (Some(0), Some(0)) | (None, _) | (_, None) => None,
+        (Some(latitude_i), Some(longitude_i)) => {
+            Some((latitude_i as f64 * 1e-7, longitude_i as f64 * 1e-7))
+        }
+    }
+}
+
+/// Return how far east and north `to` is from `from`, in metres. This treats the Earth as flat
+/// around `from`, which is accurate enough over the distances LoRa radios reach
+pub fn offset_metres(from: (f64, f64), to: (f64, f64)) -> (f64, f64) {
+    let (from_lat, from_lon) = (from.0.to_radians(), from.1.to_radians());
+    let (to_lat, to_lon) = (to.0.to_radians(), to.1.to_radians());
+    let east = (to_lon - from_lon) * ((from_lat + to_lat) / 2.0).cos() * EARTH_RADIUS;
+    let north = (to_lat - from_lat) * EARTH_RADIUS;
+    (east, north)
+}
+
+/// Return the largest round distance (1, 2 or 5 times a power of ten metres) up to `metres`
+fn scale_distance(metres: f64) -> f64 {
+    let power = 10f64.powf(metres.max(1.0).log10().floor());
+    [5.0, 2.0, 1.0]
+        .into_iter()
+        .map(|step| step * power)
+        .find(|distance| *distance <= metres)
+        .unwrap_or(power)
+}
+
+/// Return a distance as text, in metres or kilometres
+fn distance_text(metres: f64) -> String {
+    if metres >= 1000.0 {
+        format!("{} km", metres / 1000.0)
+    } else {
+        format!("{metres} m")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::map::{NodeMap, distance_text, offset_metres, scale_distance};
+    use iced::{Point, Size};
+    use meshtastic::protobufs::{NodeInfo, Position, User};
+    use std::collections::HashMap;
+
+    fn node(num: u32, latitude: f64, longitude: f64) -> NodeInfo {
+        NodeInfo {
+            num,
+            user: Some(User {
+                short_name: format!("N{num}"),
+                ..Default::default()
+            }),
+            position: Some(Position {
+                latitude_i: Some((latitude * 1e7) as i32),
+                longitude_i: Some((longitude * 1e7) as i32),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn offsets() {
+        // One thousandth of a degree of latitude is about 111m everywhere
+        let (east, north) = offset_metres((51.5, -0.1), (51.501, -0.1));
+        assert!(east.abs() < 0.01);
+        assert!((north - 111.2).abs() < 0.5);
+
+        // A degree of longitude is shorter away from the equator
+        let (east, _) = offset_metres((60.0, 10.0), (60.0, 10.001));
+        assert!((east - 55.6).abs() < 0.5);
+    }
+
+    #[test]
+    fn scale_distances() {
+        assert_eq!(scale_distance(740.0), 500.0);
+        assert_eq!(scale_distance(1900.0), 1000.0);
+        assert_eq!(scale_distance(2500.0), 2000.0);
+        assert_eq!(distance_text(500.0), "500 m");
+        assert_eq!(distance_text(2000.0), "2 km");
+    }
+
+    #[test]
+    fn nodes_placed_around_me() {
+        let mut nodes = HashMap::new();
+        nodes.insert(1000, node(1000, 51.5, -0.1));
+        nodes.insert(1001, node(1001, 51.51, -0.1));
+        nodes.insert(1002, node(1002, 51.5, -0.12));
+        nodes.insert(1003, NodeInfo::default()); // no position, not on the map
+        let mut config = Config::default();
+        config.aliases.insert(1002, "Bob".into());
+        let my_position = nodes[&1000].position;
+
+        let map = NodeMap::new(Some(1000), my_position.as_ref(), &nodes, &config);
+        assert!(map.centred_on_me);
+        assert_eq!(map.nodes.len(), 2);
+
+        let size = Size::new(400.0, 400.0);
+        let scale = map.pixels_per_metre(size, 1.0);
+        let north = map.nodes.iter().find(|node| node.num == 1001).unwrap();
+        let point = map.node_point(north, size, scale);
+        assert!((point.x - 200.0).abs() < 0.1);
+        assert!(point.y < 200.0);
+
+        let west = map.nodes.iter().find(|node| node.num == 1002).unwrap();
+        assert_eq!(west.label, "Bob");
+        let point = map.node_point(west, size, scale);
+        assert!(point.x < 200.0);
+
+        assert_eq!(
+            map.node_at(point + iced::Vector::new(3.0, 3.0), size, 1.0)
+                .map(|node| node.num),
+            Some(1002)
+        );
+        assert!(map.node_at(Point::new(390.0, 390.0), size, 1.0).is_none());
+    }
+}
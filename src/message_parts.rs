@@ -0,0 +1,134 @@
+//! Splitting text messages that are too long for one packet into numbered parts, e.g. "1/3 ...",
+//! and recognising such parts when they are received
+/// The most bytes of UTF-8 text sent in one message, as other Meshtastic apps allow
+pub const MAX_MESSAGE_BYTES: usize = 200;
+
+/// Split `text` into parts of at most `max_bytes` bytes each, including a "1/3 " style prefix,
+/// breaking after whitespace where possible and never inside a character.
+/// Text that fits in one message is returned unchanged, without a prefix.
+pub fn split(text: &str, max_bytes: usize) -> Vec<String> {
+    if text.len() <= max_bytes {
+        return vec![text.to_string()];
+    }
+
+    // The room taken by the prefix depends on the number of parts, so split again
+    // with more room for it if there are too many parts for the digits allowed
+    let mut digits = 1;
+    loop {
+        let chunks = chunks(text, max_bytes - (2 * digits + 2));
+        let count = chunks.len();
+        if count.to_string().len() <= digits {
+            return chunks
+                .iter()
+                .enumerate()
+                .map(|(index, chunk)| format!("{}/{count} {chunk}", index + 1))
+                .collect();
+        }
+        digits = count.to_string().len();
+    }
+}
+
+/// Split `text` into chunks of at most `max_bytes` bytes, that join back into the same text
+fn chunks(text: &str, max_bytes: usize) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut rest = text;
+    while rest.len() > max_bytes {
+        let mut end = max_bytes;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if let Some((index, space)) = rest[..end]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            && index > 0
+        {
+            end = index + space.len_utf8();
+        }
+        let (chunk, remainder) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = remainder;
+    }
+    chunks.push(rest);
+    chunks
+}
+
+/// If `text` is one part of a split message, return its part number, the number of parts and
+/// the text of the part without its prefix
+pub fn part(text: &str) -> Option<(usize, usize, &str)> {
+    let (prefix, part_text) = text.split_once(' ')?;
+    let (number, count) = prefix.split_once('/')?;
+    let number: usize = number.parse().ok()?;
+    let count: usize = count.parse().ok()?;
+    (count > 1 && (1..=count).contains(&number)).then_some((number, count, part_text))
+}
+
+/// Return how long a message is, in bytes, and how many messages it will be sent as, for
+/// showing to the user as they type
+pub fn length_text(text: &str) -> String {
+    let parts = split(text, MAX_MESSAGE_BYTES).len();
+    if parts > 1 {
+        format!("{} bytes, {parts} messages", text.len())
+    } else {
+        format!("{}/{MAX_MESSAGE_BYTES}", text.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::message_parts::{MAX_MESSAGE_BYTES, length_text, part, split};
+
+    #[test]
+    fn short_message_not_split() {
+        assert_eq!(split("Hello", MAX_MESSAGE_BYTES), vec!["Hello".to_string()]);
+        assert_eq!(length_text("Hello"), "5/200");
+    }
+
+    #[test]
+    fn long_message_split_at_whitespace() {
+        let text = "The quick brown fox jumps over the lazy dog";
+        let parts = split(text, 20);
+        assert_eq!(
+            parts,
+            vec![
+                "1/3 The quick brown ",
+                "2/3 fox jumps over ",
+                "3/3 the lazy dog"
+            ]
+        );
+        assert!(parts.iter().all(|part| part.len() <= 20));
+
+        let joined: String = parts.iter().filter_map(|p| part(p)).map(|p| p.2).collect();
+        assert_eq!(joined, text);
+    }
+
+    #[test]
+    fn multibyte_characters_not_broken() {
+        let text = "😀".repeat(100);
+        assert_eq!(text.len(), 400);
+        let parts = split(&text, MAX_MESSAGE_BYTES);
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|part| part.len() <= MAX_MESSAGE_BYTES));
+
+        let joined: String = parts.iter().filter_map(|p| part(p)).map(|p| p.2).collect();
+        assert_eq!(joined, text);
+        assert_eq!(length_text(&text), "400 bytes, 3 messages");
+    }
+
+    #[test]
+    fn many_parts_leave_room_for_prefix() {
+        let text = "x".repeat(100);
+        let parts = split(&text, 12);
+        assert!(parts.len() >= 10);
+        assert!(parts.iter().all(|part| part.len() <= 12));
+        assert!(parts[0].starts_with("1/"));
+    }
+
+    #[test]
+    fn parts_recognised() {
+        assert_eq!(part("2/3 fox"), Some((2, 3, "fox")));
+        assert_eq!(part("Hello there"), None);
+        assert_eq!(part("4/3 too many"), None);
+        assert_eq!(part("1/1 only one"), None);
+    }
+}
@@ -0,0 +1,387 @@
+//! A panel showing everything the radio knows about a node, with actions to message it, request
+//! its info and traceroute it
+use crate::Message::{DeviceViewEvent, ShowLocation, ToggleRoleHidden};
+use crate::channel_id::ChannelId;
+use crate::config::Config;
+use crate::device_view::DeviceViewMessage::{RequestNodeInfo, ShowChannel};
+use crate::styles::{DAY_SEPARATOR_STYLE, button_chip_style, scrollbar_style};
+use crate::telemetry::{Chart, Metric, NodeTelemetry};
+use crate::traceroute::{Hop, Traceroute, hop_name};
+use crate::{Message, device_view};
+use chrono::{DateTime, Local, Utc};
+use iced::widget::scrollable::Scrollbar;
+use iced::widget::{Column, Container, Row, Space, button, canvas, scrollable, text};
+use iced::{Center, Element, Fill, Padding};
+use meshtastic::protobufs::config::device_config;
+use meshtastic::protobufs::{HardwareModel, Neighbor, NodeInfo};
+use std::collections::HashMap;
+
+/// Create the Element that shows the details of a node, charts of the telemetry it has reported,
+/// the result of the latest traceroute to it, the neighbors it reported, and the actions that can
+/// be taken on it.
+/// Actions that send to the radio are only enabled when `connected`
+pub fn node_detail<'a>(
+    node_info: &'a NodeInfo,
+    telemetry: Option<&NodeTelemetry>,
+    traceroute: Option<&Traceroute>,
+    neighbors: Option<&Vec<Neighbor>>,
+    nodes: &HashMap<u32, NodeInfo>,
+    config: &'a Config,
+    connected: bool,
+) -> Element<'a, Message> {
+    let now = Utc::now().timestamp();
+    let mut details = Column::new().spacing(4).padding([0, 8]);
+
+    details = details.push(section("Node"));
+    if let Some(user) = &node_info.user {
+        details = details
+            .push(detail_row("Name", &user.long_name))
+            .push(detail_row("Short name", &user.short_name));
+    }
+    if let Some(alias) = config.aliases.get(&node_info.num) {
+        details = details.push(detail_row("Alias", alias));
+    }
+    details = details.push(detail_row("Node number", &node_info.num.to_string()));
+    if let Some(user) = &node_info.user {
+        details = details
+            .push(detail_row("User id", &user.id))
+            .push(detail_row("Hardware", &hardware_name(user.hw_model)))
+            .push(role_row(user.role, config));
+        if let Some(fingerprint) = key_fingerprint(&user.public_key) {
+            details = details.push(detail_row("Public key", &fingerprint));
+        }
+    }
+
+    details = details.push(section("Radio"));
+    details = details.push(detail_row(
+        "Last heard",
+        &epoch_time(node_info.last_heard, now).unwrap_or("Never".into()),
+    ));
+    details = details.push(detail_row("SNR", &format!("{:.1} dB", node_info.snr)));
+    if let Some(hops_away) = node_info.hops_away {
+        details = details.push(detail_row("Hops away", &hops_text(hops_away)));
+    }
+    if node_info.via_mqtt {
+        details = details.push(detail_row("Heard via", "MQTT"));
+    }
+
+    if let Some(metrics) = &node_info.device_metrics {
+        details = details.push(section("Device Metrics"));
+        if let Some(battery_level) = metrics.battery_level {
+            let battery = if battery_level > 100 {
+                "Powered".to_string()
+            } else {
+                format!("{battery_level}%")
+            };
+            details = details.push(detail_row("Battery", &battery));
+        }
+        if let Some(voltage) = metrics.voltage {
+            details = details.push(detail_row("Voltage", &format!("{voltage:.2} V")));
+        }
+        if let Some(channel_utilization) = metrics.channel_utilization {
+            details = details.push(detail_row(
+                "Channel utilization",
+                &format!("{channel_utilization:.1}%"),
+            ));
+        }
+        if let Some(air_util_tx) = metrics.air_util_tx {
+            details = details.push(detail_row("Air util TX", &format!("{air_util_tx:.1}%")));
+        }
+        if let Some(uptime_seconds) = metrics.uptime_seconds {
+            details = details.push(detail_row("Uptime", &duration(uptime_seconds as i64)));
+        }
+    }
+
+    if let Some(telemetry) = telemetry {
+        details = telemetry_charts(details, telemetry);
+    }
+
+    if let Some(traceroute) = traceroute {
+        details = traceroute_route(details, traceroute, nodes, config);
+    }
+
+    if let Some(neighbors) = neighbors {
+        details = details.push(section("Neighbors"));
+        for neighbor in neighbors {
+            details = details.push(detail_row(
+                &hop_name(nodes, config, neighbor.node_id),
+                &format!("SNR {:.2} dB", neighbor.snr),
+            ));
+        }
+    }
+
+    if let Some(position) = &node_info.position
+        && let (Some(latitude_i), Some(longitude_i)) = (position.latitude_i, position.longitude_i)
+    {
+        details = details.push(section("Position"));
+        details = details.push(detail_row(
+            "Location",
+            &format!(
+                "{:.5}, {:.5}",
+                latitude_i as f64 * 1e-7,
+                longitude_i as f64 * 1e-7
+            ),
+        ));
+        if let Some(altitude) = position.altitude {
+            details = details.push(detail_row("Altitude", &format!("{altitude} m")));
+        }
+        if let Some(time) = epoch_time(position.time, now) {
+            details = details.push(detail_row("Time", &time));
+        }
+        details = details.push(
+            button("Show in maps")
+                .style(button_chip_style)
+                .on_press(ShowLocation(latitude_i, longitude_i)),
+        );
+    }
+
+    Column::new()
+        .push(actions(node_info.num, connected))
+        .push(
+            scrollable(details)
+                .direction(scrollable::Direction::Vertical(Scrollbar::new().width(10)))
+                .style(scrollbar_style)
+                .width(Fill)
+                .height(Fill),
+        )
+        .into()
+}
+
+/// A row of buttons for the actions that can be taken on the node
+fn actions(node_num: u32, connected: bool) -> Element<'static, Message> {
+    let action = |label: &'static str, message: device_view::DeviceViewMessage| {
+        button(label)
+            .style(button_chip_style)
+            .on_press_maybe(connected.then_some(DeviceViewEvent(message)))
+    };
+
+    Row::new()
+        .spacing(4)
+        .padding(4)
+        .push(action(
+            "Message",
+            ShowChannel(Some(ChannelId::Node(node_num))),
+        ))
+        .push(action("Request info", RequestNodeInfo(node_num)))
+        .push(action(
+            "Traceroute",
+            device_view::DeviceViewMessage::Traceroute(node_num),
+        ))
+        .into()
+}
+
+/// Add a chart for each metric the node has reported, with its latest value
+fn telemetry_charts<'a>(
+    mut details: Column<'a, Message>,
+    telemetry: &NodeTelemetry,
+) -> Column<'a, Message> {
+    let charts: Vec<(Metric, Vec<(i64, f32)>)> = Metric::ALL
+        .into_iter()
+        .map(|metric| (metric, telemetry.series(metric)))
+        .filter(|(_, series)| !series.is_empty())
+        .collect();
+    if charts.is_empty() {
+        return details;
+    }
+
+    details = details.push(section("Telemetry"));
+    for (metric, series) in charts {
+        let latest = series.last().map(|(_, value)| metric.format(*value));
+        details = details
+            .push(detail_row(metric.name(), &latest.unwrap_or_default()))
+            .push(canvas(Chart::new(metric, series)).width(Fill).height(60));
+    }
+    details
+}
+
+/// Add the hops of a traceroute, towards the node and back, with the SNR each hop was heard with
+fn traceroute_route<'a>(
+    mut details: Column<'a, Message>,
+    traceroute: &Traceroute,
+    nodes: &HashMap<u32, NodeInfo>,
+    config: &Config,
+) -> Column<'a, Message> {
+    details = details.push(section("Traceroute"));
+    let (towards, back) = match traceroute {
+        Traceroute::Pending => return details.push(text("Waiting for the route...")),
+        Traceroute::Complete { towards, back } => (towards, back),
+    };
+
+    details = hop_rows(details, "Towards", towards, nodes, config);
+    match back {
+        Some(back) => hop_rows(details, "Back", back, nodes, config),
+        None => details.push(text("The route back is not known")),
+    }
+}
+
+fn hop_rows<'a>(
+    mut details: Column<'a, Message>,
+    title: &'static str,
+    hops: &[Hop],
+    nodes: &HashMap<u32, NodeInfo>,
+    config: &Config,
+) -> Column<'a, Message> {
+    details = details.push(text(title).size(16));
+    for hop in hops {
+        let snr = hop
+            .snr
+            .map(|snr| format!("SNR {snr:.2} dB"))
+            .unwrap_or_default();
+        details = details.push(detail_row(
+            &format!("→ {}", hop_name(nodes, config, hop.node)),
+            &snr,
+        ));
+    }
+    details
+}
+
+fn section(title: &'static str) -> Element<'static, Message> {
+    Container::new(text(title).size(16))
+        .padding(Padding::from([6, 12]))
+        .style(|_| DAY_SEPARATOR_STYLE)
+        .width(Fill)
+        .align_x(Center)
+        .into()
+}
+
+fn detail_row(label: &str, value: &str) -> Element<'static, Message> {
+    Row::new()
+        .push(text(label.to_string()).width(180))
+        .push(Space::new().width(8))
+        .push(text(value.to_string()))
+        .into()
+}
+
+/// A row showing a node's role, with a button to hide or show nodes with that role in the list
+fn role_row(role: i32, config: &Config) -> Element<'static, Message> {
+    let hide_text = if config.role_hidden(role) {
+        "Show in list"
+    } else {
+        "Hide from list"
+    };
+    Row::new()
+        .align_y(Center)
+        .push(text("Role").width(180))
+        .push(Space::new().width(8))
+        .push(text(role_name(role)))
+        .push(Space::new().width(8))
+        .push(
+            button(text(hide_text).size(12))
+                .padding([0, 6])
+                .style(button_chip_style)
+                .on_press(ToggleRoleHidden(role)),
+        )
+        .into()
+}
+
+/// Return how many hops away a node is as text, e.g. "Direct" or "2 hops"
+pub fn hops_text(hops_away: u32) -> String {
+    match hops_away {
+        0 => "Direct".to_string(),
+        1 => "1 hop".to_string(),
+        hops => format!("{hops} hops"),
+    }
+}
+
+/// Return the name of a hardware model, e.g. "HELTEC V3"
+pub fn hardware_name(hw_model: i32) -> String {
+    HardwareModel::try_from(hw_model)
+        .map(|model| model.as_str_name().replace('_', " "))
+        .unwrap_or(format!("Unknown ({hw_model})"))
+}
+
+/// Return the name of a node's role, e.g. "Client Mute"
+pub fn role_name(role: i32) -> String {
+    device_config::Role::try_from(role)
+        .map(|role| {
+            role.as_str_name()
+                .split('_')
+                .map(|word| {
+                    let (first, rest) = word.split_at(1);
+                    format!("{first}{}", rest.to_lowercase())
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .unwrap_or(format!("Unknown ({role})"))
+}
+
+/// Return a short fingerprint of a public key to compare with other apps, or None if the node
+/// has no key
+fn key_fingerprint(public_key: &[u8]) -> Option<String> {
+    if public_key.is_empty() {
+        return None;
+    }
+    let hex: Vec<String> = public_key
+        .iter()
+        .take(8)
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Some(hex.join(":"))
+}
+
+/// Return a local time and how long ago it was, for a time in seconds since the epoch, or None
+/// if the time is not known (zero)
+fn epoch_time(seconds: u32, now: i64) -> Option<String> {
+    if seconds == 0 {
+        return None;
+    }
+    let time = DateTime::<Utc>::from_timestamp_secs(seconds as i64)?.with_timezone(&Local);
+    Some(format!(
+        "{} ({})",
+        time.format("%Y-%m-%d %H:%M"),
+        time_ago(now - seconds as i64)
+    ))
+}
+
+/// Return how long ago something happened, `seconds` ago, e.g. "5m ago"
+pub fn time_ago(seconds: i64) -> String {
+    if seconds < 60 {
+        "just now".to_string()
+    } else {
+        format!("{} ago", duration(seconds))
+    }
+}
+
+/// Return a short description of a duration in seconds, using its largest unit, e.g. "3h"
+pub fn duration(seconds: i64) -> String {
+    match seconds {
+        ..60 => format!("{}s", seconds.max(0)),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::node_detail::{hardware_name, key_fingerprint, role_name, time_ago};
+    use meshtastic::protobufs::HardwareModel;
+    use meshtastic::protobufs::config::device_config::Role;
+
+    #[test]
+    fn times_ago() {
+        assert_eq!(time_ago(5), "just now");
+        assert_eq!(time_ago(-30), "just now");
+        assert_eq!(time_ago(5 * 60), "5m ago");
+        assert_eq!(time_ago(3 * 3600 + 59), "3h ago");
+        assert_eq!(time_ago(2 * 86400), "2d ago");
+    }
+
+    #[test]
+    fn fingerprint() {
+        assert_eq!(key_fingerprint(&[]), None);
+        assert_eq!(
+            key_fingerprint(&[0xa1, 0x02, 0xff, 4, 5, 6, 7, 8, 9, 10]),
+            Some("a1:02:ff:04:05:06:07:08".into())
+        );
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(role_name(Role::ClientMute as i32), "Client Mute");
+        assert_eq!(role_name(Role::Router as i32), "Router");
+        assert_eq!(hardware_name(HardwareModel::HeltecV3 as i32), "HELTEC V3");
+        assert_eq!(hardware_name(-1), "Unknown (-1)");
+    }
+}
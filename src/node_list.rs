@@ -0,0 +1,431 @@
+//! Sorting, grouping and filtering of the list of nodes in the device view, so that meshes with
+//! hundreds of nodes can be narrowed down to the ones of interest
+use crate::Message;
+use crate::Message::{DeviceViewEvent, ToggleRoleHidden};
+use crate::config::Config;
+use crate::device_view::DeviceViewMessage::NodeListMsg;
+use crate::node_detail::{hardware_name, hops_text, role_name};
+use crate::node_list::NodeListMessage::{
+    ClearFilters, DirectOnly, Hardware, HeardWithin, Role, Sort,
+};
+use crate::styles::{button_chip_style, tooltip_style};
+use iced::widget::{Row, button, pick_list, text, tooltip};
+use iced::{Center, Element, Padding};
+use meshtastic::protobufs::config::device_config;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// The orders the list of nodes can be sorted in
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum NodeSort {
+    #[default]
+    Name,
+    LastHeard,
+    Distance,
+    Snr,
+    HopsAway,
+    Unread,
+}
+
+impl NodeSort {
+    const ALL: [NodeSort; 6] = [
+        NodeSort::Name,
+        NodeSort::LastHeard,
+        NodeSort::Distance,
+        NodeSort::Snr,
+        NodeSort::HopsAway,
+        NodeSort::Unread,
+    ];
+}
+
+impl fmt::Display for NodeSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NodeSort::Name => "Sort by name",
+            NodeSort::LastHeard => "Sort by last heard",
+            NodeSort::Distance => "Sort by distance",
+            NodeSort::Snr => "Sort by SNR",
+            NodeSort::HopsAway => "Sort by hops away",
+            NodeSort::Unread => "Sort by unread",
+        })
+    }
+}
+
+/// Only show nodes heard within this many hours, or all nodes if None
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HeardWithinHours(Option<u32>);
+
+impl HeardWithinHours {
+    const ALL: [HeardWithinHours; 5] = [
+        HeardWithinHours(None),
+        HeardWithinHours(Some(1)),
+        HeardWithinHours(Some(6)),
+        HeardWithinHours(Some(24)),
+        HeardWithinHours(Some(72)),
+    ];
+}
+
+impl fmt::Display for HeardWithinHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => f.write_str("Heard any time"),
+            Some(hours) => write!(f, "Heard in last {hours}h"),
+        }
+    }
+}
+
+/// Only show nodes with this hardware model, or all nodes if None
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HardwareChoice(Option<i32>);
+
+impl fmt::Display for HardwareChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => f.write_str("Any hardware"),
+            Some(hw_model) => f.write_str(&hardware_name(hw_model)),
+        }
+    }
+}
+
+/// Only show nodes with this role, or all nodes if None
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RoleChoice(Option<i32>);
+
+impl fmt::Display for RoleChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => f.write_str("Any role"),
+            Some(role) => f.write_str(&role_name(role)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum NodeListMessage {
+    Sort(NodeSort),
+    HeardWithin(HeardWithinHours),
+    DirectOnly(bool),
+    Hardware(HardwareChoice),
+    Role(RoleChoice),
+    ClearFilters,
+}
+
+/// What is known about a node, to sort and filter the list of nodes by
+#[derive(Debug, Default)]
+pub struct NodeSummary {
+    pub num: u32,
+    pub name: String,
+    pub last_heard: u32,
+    pub distance: Option<f64>, // metres from us
+    pub snr: f32,
+    pub hops_away: Option<u32>,
+    pub unread: usize,
+    pub hw_model: i32,
+    pub role: i32,
+}
+
+/// How the list of nodes is sorted and filtered
+#[derive(Debug, Default)]
+pub struct NodeList {
+    sort: NodeSort,
+    heard_within: HeardWithinHours,
+    direct_only: bool,
+    hardware: HardwareChoice,
+    role: RoleChoice,
+}
+
+impl NodeList {
+    pub fn update(&mut self, message: NodeListMessage) {
+        match message {
+            Sort(sort) => self.sort = sort,
+            HeardWithin(heard_within) => self.heard_within = heard_within,
+            DirectOnly(direct_only) => self.direct_only = direct_only,
+            Hardware(hardware) => self.hardware = hardware,
+            Role(role) => self.role = role,
+            ClearFilters => {
+                self.heard_within = HeardWithinHours::default();
+                self.direct_only = false;
+                self.hardware = HardwareChoice::default();
+                self.role = RoleChoice::default();
+            }
+        }
+    }
+
+    /// Return true if any filter is hiding some nodes
+    fn filtering(&self) -> bool {
+        self.heard_within.0.is_some()
+            || self.direct_only
+            || self.hardware.0.is_some()
+            || self.role.0.is_some()
+    }
+
+    /// Return true if the node passes all the filters, at time `now`
+    fn matches(&self, node: &NodeSummary, now: i64) -> bool {
+        if let Some(hours) = self.heard_within.0
+            && (node.last_heard == 0 || now - node.last_heard as i64 > hours as i64 * 3600)
+        {
+            return false;
+        }
+        (!self.direct_only || node.hops_away == Some(0))
+            && self
+                .hardware
+                .0
+                .is_none_or(|hw_model| hw_model == node.hw_model)
+            && self.role.0.is_none_or(|role| role == node.role)
+    }
+
+    /// Remove the nodes that do not pass the filters, at time `now`, and sort the rest
+    pub fn apply(&self, mut nodes: Vec<NodeSummary>, now: i64) -> Vec<NodeSummary> {
+        nodes.retain(|node| self.matches(node, now));
+        nodes.sort_by(|a, b| self.compare(a, b));
+        nodes
+    }
+
+    /// Order two nodes by the selected sort, then by name. Nodes where the value sorted by is
+    /// not known go last
+    fn compare(&self, a: &NodeSummary, b: &NodeSummary) -> Ordering {
+        let ordering = match self.sort {
+            NodeSort::Name => Ordering::Equal,
+            NodeSort::LastHeard => b.last_heard.cmp(&a.last_heard),
+            NodeSort::Distance => known_first(a.distance, b.distance, f64::total_cmp),
+            NodeSort::Snr => b.snr.total_cmp(&a.snr),
+            NodeSort::HopsAway => known_first(a.hops_away, b.hops_away, u32::cmp),
+            NodeSort::Unread => b.unread.cmp(&a.unread),
+        };
+        ordering
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+            .then(a.num.cmp(&b.num))
+    }
+
+    /// Return the name of the group a node is listed in, if the sort groups nodes
+    pub fn group(&self, node: &NodeSummary) -> Option<String> {
+        match self.sort {
+            NodeSort::HopsAway => Some(
+                node.hops_away
+                    .map(hops_text)
+                    .unwrap_or("Hops unknown".into()),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Create a row of controls to pick how to sort and filter the nodes. The hardware and role
+    /// filters offer the hardware models and roles of the nodes known, except the roles hidden in
+    /// the config, which each have a button to show them again
+    pub fn view(
+        &self,
+        mut hw_models: Vec<i32>,
+        mut roles: Vec<i32>,
+        config: &Config,
+    ) -> Element<'static, Message> {
+        let event = |message| DeviceViewEvent(NodeListMsg(message));
+
+        hw_models.sort_by_key(|hw_model| hardware_name(*hw_model));
+        hw_models.dedup();
+        let hardware_choices: Vec<HardwareChoice> = std::iter::once(HardwareChoice(None))
+            .chain(
+                hw_models
+                    .into_iter()
+                    .map(|hw_model| HardwareChoice(Some(hw_model))),
+            )
+            .collect();
+
+        roles.retain(|role| !config.role_hidden(*role));
+        roles.sort();
+        roles.dedup();
+        let role_choices: Vec<RoleChoice> = std::iter::once(RoleChoice(None))
+            .chain(roles.into_iter().map(|role| RoleChoice(Some(role))))
+            .collect();
+
+        let direct_text = if self.direct_only {
+            "✓ Direct only"
+        } else {
+            "Direct only"
+        };
+        let mut clear_button = button(text("Clear filters").size(14))
+            .style(button_chip_style)
+            .padding(Padding::from([4, 6]));
+        if self.filtering() {
+            clear_button = clear_button.on_press(event(ClearFilters));
+        }
+
+        let mut hidden_roles: Vec<i32> = config
+            .hidden_roles
+            .iter()
+            .filter_map(|name| device_config::Role::from_str_name(name))
+            .map(|role| role as i32)
+            .collect();
+        hidden_roles.sort();
+        let hidden_role_buttons = hidden_roles.into_iter().map(|role| {
+            tooltip(
+                button(text(format!("{} hidden ✕", role_name(role))).size(14))
+                    .style(button_chip_style)
+                    .padding(Padding::from([4, 6]))
+                    .on_press(ToggleRoleHidden(role)),
+                text("Show nodes with this role again"),
+                tooltip::Position::Bottom,
+            )
+            .style(tooltip_style)
+            .into()
+        });
+
+        Row::new()
+            .spacing(4)
+            .padding([2, 4])
+            .align_y(Center)
+            .push(
+                pick_list(NodeSort::ALL, Some(self.sort), move |sort| {
+                    event(Sort(sort))
+                })
+                .text_size(14),
+            )
+            .push(
+                pick_list(
+                    HeardWithinHours::ALL,
+                    Some(self.heard_within),
+                    move |within| event(HeardWithin(within)),
+                )
+                .text_size(14),
+            )
+            .push(
+                tooltip(
+                    button(text(direct_text).size(14))
+                        .style(button_chip_style)
+                        .padding(Padding::from([4, 6]))
+                        .on_press(event(DirectOnly(!self.direct_only))),
+                    text("Only show nodes heard directly, not relayed"),
+                    tooltip::Position::Bottom,
+                )
+                .style(tooltip_style),
+            )
+            .push(
+                pick_list(hardware_choices, Some(self.hardware), move |hardware| {
+                    event(Hardware(hardware))
+                })
+                .text_size(14),
+            )
+            .push(
+                pick_list(role_choices, Some(self.role), move |role| event(Role(role)))
+                    .text_size(14),
+            )
+            .push(clear_button)
+            .extend(hidden_role_buttons)
+            .wrap()
+            .into()
+    }
+}
+
+/// Order two optional values, with known values first
+fn known_first<T>(a: Option<T>, b: Option<T>, compare: fn(&T, &T) -> Ordering) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => compare(&a, &b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::node_list::NodeListMessage::{
+        ClearFilters, DirectOnly, Hardware, HeardWithin, Role, Sort,
+    };
+    use crate::node_list::{
+        HardwareChoice, HeardWithinHours, NodeList, NodeSort, NodeSummary, RoleChoice,
+    };
+
+    const NOW: i64 = 1_750_000_000;
+
+    fn nodes() -> Vec<NodeSummary> {
+        vec![
+            NodeSummary {
+                num: 1,
+                name: "bravo".into(),
+                last_heard: (NOW - 600) as u32,
+                distance: Some(5000.0),
+                snr: 2.0,
+                hops_away: Some(0),
+                unread: 1,
+                hw_model: 43,
+                ..Default::default()
+            },
+            NodeSummary {
+                num: 2,
+                name: "Alpha".into(),
+                last_heard: (NOW - 2 * 86400) as u32,
+                distance: Some(300.0),
+                snr: 8.0,
+                hops_away: Some(2),
+                role: 2,
+                ..Default::default()
+            },
+            NodeSummary {
+                num: 3,
+                name: "Charlie".into(),
+                unread: 4,
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn sorted(node_list: &NodeList) -> Vec<u32> {
+        node_list
+            .apply(nodes(), NOW)
+            .iter()
+            .map(|node| node.num)
+            .collect()
+    }
+
+    #[test]
+    fn sorts() {
+        let mut node_list = NodeList::default();
+        assert_eq!(sorted(&node_list), vec![2, 1, 3]);
+
+        for (sort, expected) in [
+            (NodeSort::LastHeard, vec![1, 2, 3]),
+            (NodeSort::Distance, vec![2, 1, 3]),
+            (NodeSort::Snr, vec![2, 1, 3]),
+            (NodeSort::HopsAway, vec![1, 2, 3]),
+            (NodeSort::Unread, vec![3, 1, 2]),
+        ] {
+            node_list.update(Sort(sort));
+            assert_eq!(sorted(&node_list), expected, "{sort}");
+        }
+    }
+
+    #[test]
+    fn filters() {
+        let mut node_list = NodeList::default();
+        node_list.update(HeardWithin(HeardWithinHours(Some(24))));
+        assert_eq!(sorted(&node_list), vec![1]);
+
+        node_list.update(ClearFilters);
+        node_list.update(DirectOnly(true));
+        assert_eq!(sorted(&node_list), vec![1]);
+
+        node_list.update(ClearFilters);
+        node_list.update(Hardware(HardwareChoice(Some(43))));
+        assert_eq!(sorted(&node_list), vec![1]);
+
+        node_list.update(ClearFilters);
+        node_list.update(Role(RoleChoice(Some(2))));
+        assert_eq!(sorted(&node_list), vec![2]);
+        assert!(node_list.filtering());
+
+        node_list.update(ClearFilters);
+        assert!(!node_list.filtering());
+        assert_eq!(sorted(&node_list).len(), 3);
+    }
+
+    #[test]
+    fn groups() {
+        let mut node_list = NodeList::default();
+        let nodes = nodes();
+        assert_eq!(node_list.group(&nodes[0]), None);
+
+        node_list.update(Sort(NodeSort::HopsAway));
+        assert_eq!(node_list.group(&nodes[0]), Some("Direct".into()));
+        assert_eq!(node_list.group(&nodes[1]), Some("2 hops".into()));
+        assert_eq!(node_list.group(&nodes[2]), Some("Hops unknown".into()));
+    }
+}
@@ -0,0 +1,335 @@
+//! An outbox of the text messages sent by the user, that tracks the delivery of each one until it
+//! is acknowledged, retrying those that fail or time out, up to a configurable limit
+use crate::channel_id::ChannelId;
+use crate::channel_view_entry::DeliveryStatus;
+use crate::channel_view_entry::DeliveryStatus::{Acked, Failed, Relayed, Sent, TimedOut};
+use meshtastic::Message;
+use meshtastic::protobufs::{Routing, routing};
+use meshtastic::utils::generate_rand_id;
+use std::time::{Duration, Instant};
+
+/// How many times a message is sent again, if it fails or times out, when not set in the config
+pub const DEFAULT_MESSAGE_RETRIES: u32 = 2;
+
+/// How long to wait for a message sent to the radio to be acknowledged before it times out
+const ACK_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// A text message waiting to be sent, or to be acknowledged
+#[derive(Debug, Clone)]
+pub struct OutboxItem {
+    pub channel_id: ChannelId,
+    /// The id of the entry in the [ChannelView], which is the id of the first packet sent
+    pub entry_id: u32,
+    /// The id of the packet most recently sent, which changes on each retry
+    pub packet_id: u32,
+    pub text: String,
+    pub reply_to_id: Option<u32>,
+    retries: u32,
+    sent_at: Option<Instant>,
+}
+
+/// A change to a message in the [Outbox] that the UI needs to act on
+#[derive(Debug)]
+pub enum Delivery {
+    /// The delivery status of the entry (`channel_id`, `entry_id`) has changed
+    Update(ChannelId, u32, DeliveryStatus),
+    /// The message failed or timed out, and should be sent again
+    Retry(OutboxItem),
+}
+
+#[derive(Debug, Default)]
+pub struct Outbox {
+    items: Vec<OutboxItem>,
+    max_retries: Option<u32>,
+}
+
+impl Outbox {
+    /// Set how many times a message is sent again, None to use [DEFAULT_MESSAGE_RETRIES]
+    pub fn set_max_retries(&mut self, max_retries: Option<u32>) {
+        self.max_retries = max_retries;
+    }
+
+    /// Add a message for the entry `entry_id` to the outbox, to be sent in a packet with
+    /// `packet_id`, and return the [OutboxItem] to be sent
+    pub fn add(
+        &mut self,
+        channel_id: ChannelId,
+        entry_id: u32,
+        packet_id: u32,
+        text: String,
+        reply_to_id: Option<u32>,
+    ) -> OutboxItem {
+        self.remove(entry_id);
+        let item = OutboxItem {
+            channel_id,
+            entry_id,
+            packet_id,
+            text,
+            reply_to_id,
+            retries: 0,
+            sent_at: None,
+        };
+        self.items.push(item.clone());
+        item
+    }
+
+    /// Return true if there are messages in the outbox
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Return the messages that have not been sent to the radio yet
+    pub fn queued(&self) -> Vec<OutboxItem> {
+        self.items
+            .iter()
+            .filter(|item| item.sent_at.is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Forget all the messages in the outbox
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// The packet `packet_id` has been sent to the radio
+    pub fn sent(&mut self, packet_id: u32) -> Option<Delivery> {
+        let item = self
+            .items
+            .iter_mut()
+            .find(|item| item.packet_id == packet_id)?;
+        item.sent_at = Some(Instant::now());
+        Some(Delivery::Update(
+            item.channel_id.clone(),
+            item.entry_id,
+            Sent,
+        ))
+    }
+
+    /// Process a routing response from node `from`, for the packet `request_id`
+    pub fn routing(
+        &mut self,
+        request_id: u32,
+        from: u32,
+        error: routing::Error,
+    ) -> Option<Delivery> {
+        let index = self
+            .items
+            .iter()
+            .position(|item| item.packet_id == request_id || item.entry_id == request_id)?;
+        let item = &self.items[index];
+
+        if error != routing::Error::None {
+            return if Self::is_retryable(error) {
+                self.retry(index, Failed(Self::reason(error)))
+            } else {
+                let item = self.items.remove(index);
+                Some(Delivery::Update(
+                    item.channel_id,
+                    item.entry_id,
+                    Failed(Self::reason(error)),
+                ))
+            };
+        }
+
+        match item.channel_id {
+            // Only the destination node can acknowledge a direct message as delivered. A response
+            // from any other node (including our own radio) means it was relayed on its way
+            ChannelId::Node(destination) if from != destination => Some(Delivery::Update(
+                item.channel_id.clone(),
+                item.entry_id,
+                Relayed,
+            )),
+            ChannelId::Node(_) => {
+                let item = self.items.remove(index);
+                Some(Delivery::Update(item.channel_id, item.entry_id, Acked))
+            }
+            // Nobody acknowledges a broadcast, the best we get is hearing it being relayed
+            ChannelId::Channel(_) => {
+                let item = self.items.remove(index);
+                Some(Delivery::Update(item.channel_id, item.entry_id, Relayed))
+            }
+        }
+    }
+
+    /// Find the messages sent to the radio that have not been acknowledged in time
+    pub fn check_timeouts(&mut self, now: Instant) -> Vec<Delivery> {
+        let mut deliveries = vec![];
+        let mut index = 0;
+        while index < self.items.len() {
+            let timed_out = self.items[index]
+                .sent_at
+                .is_some_and(|sent_at| now.duration_since(sent_at) >= ACK_TIMEOUT);
+            if timed_out && let Some(delivery) = self.retry(index, TimedOut) {
+                // A retried item stays in the outbox, a message that has finally failed does not
+                if matches!(delivery, Delivery::Retry(_)) {
+                    index += 1;
+                }
+                deliveries.push(delivery);
+            } else {
+                index += 1;
+            }
+        }
+        deliveries
+    }
+
+    /// Retry the message at `index`, with a new packet id, if it has not used all its retries.
+    /// If it has, then remove it and report it with the `failed` status
+    fn retry(&mut self, index: usize, failed: DeliveryStatus) -> Option<Delivery> {
+        let max_retries = self.max_retries.unwrap_or(DEFAULT_MESSAGE_RETRIES);
+        let item = self.items.get_mut(index)?;
+        if item.retries < max_retries {
+            item.retries += 1;
+            item.packet_id = generate_rand_id();
+            item.sent_at = None;
+            Some(Delivery::Retry(item.clone()))
+        } else {
+            let item = self.items.remove(index);
+            Some(Delivery::Update(item.channel_id, item.entry_id, failed))
+        }
+    }
+
+    /// Remove a message from the outbox
+    fn remove(&mut self, entry_id: u32) {
+        self.items.retain(|item| item.entry_id != entry_id);
+    }
+
+    /// Return true for routing errors that could be resolved by sending the message again
+    fn is_retryable(error: routing::Error) -> bool {
+        use routing::Error::*;
+        matches!(
+            error,
+            NoRoute | GotNak | Timeout | NoInterface | MaxRetransmit | NoResponse | DutyCycleLimit
+        )
+    }
+
+    /// Return a readable reason for a routing error, e.g. "max retransmit"
+    fn reason(error: routing::Error) -> String {
+        error.as_str_name().replace('_', " ").to_lowercase()
+    }
+}
+
+/// Return the error in the payload of a [Routing] packet, [routing::Error::None] for an ACK
+pub fn routing_error(payload: &[u8]) -> routing::Error {
+    match Routing::decode(payload).map(|routing| routing.variant) {
+        Ok(Some(routing::Variant::ErrorReason(reason))) => {
+            // Treat an error we don't know as a NAK, rather than as an ACK
+            routing::Error::try_from(reason).unwrap_or(routing::Error::GotNak)
+        }
+        _ => routing::Error::None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::channel_id::ChannelId;
+    use crate::channel_view_entry::DeliveryStatus::{Acked, Failed, Relayed, Sent, TimedOut};
+    use crate::outbox::{ACK_TIMEOUT, Delivery, Outbox};
+    use meshtastic::protobufs::routing;
+    use std::time::Instant;
+
+    #[test]
+    fn direct_message_relayed_then_acked() {
+        let mut outbox = Outbox::default();
+        outbox.add(ChannelId::Node(7), 100, 100, "Hi".into(), None);
+        assert_eq!(outbox.queued().len(), 1);
+
+        assert!(matches!(
+            outbox.sent(100),
+            Some(Delivery::Update(_, 100, Sent))
+        ));
+        assert!(outbox.queued().is_empty());
+
+        // Our own radio heard it being relayed
+        assert!(matches!(
+            outbox.routing(100, 1, routing::Error::None),
+            Some(Delivery::Update(_, 100, Relayed))
+        ));
+        // Then the destination acknowledged it
+        assert!(matches!(
+            outbox.routing(100, 7, routing::Error::None),
+            Some(Delivery::Update(_, 100, Acked))
+        ));
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn broadcast_relayed() {
+        let mut outbox = Outbox::default();
+        outbox.add(ChannelId::Channel(0), 100, 100, "Hi all".into(), None);
+        outbox.sent(100);
+        assert!(matches!(
+            outbox.routing(100, 1, routing::Error::None),
+            Some(Delivery::Update(ChannelId::Channel(0), 100, Relayed))
+        ));
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn unknown_packet_ignored() {
+        let mut outbox = Outbox::default();
+        assert!(outbox.sent(100).is_none());
+        assert!(outbox.routing(100, 1, routing::Error::None).is_none());
+    }
+
+    #[test]
+    fn retry_until_failed() {
+        let mut outbox = Outbox::default();
+        outbox.set_max_retries(Some(1));
+        outbox.add(ChannelId::Node(7), 100, 100, "Hi".into(), Some(42));
+        outbox.sent(100);
+
+        let Some(Delivery::Retry(item)) = outbox.routing(100, 1, routing::Error::MaxRetransmit)
+        else {
+            panic!("Message was not retried");
+        };
+        assert_eq!(item.entry_id, 100);
+        assert_ne!(item.packet_id, 100);
+        assert_eq!(item.reply_to_id, Some(42));
+        assert_eq!(outbox.queued().len(), 1);
+
+        outbox.sent(item.packet_id);
+        match outbox.routing(item.packet_id, 1, routing::Error::MaxRetransmit) {
+            Some(Delivery::Update(_, 100, Failed(reason))) => assert_eq!(reason, "max retransmit"),
+            other => panic!("Unexpected delivery: {:?}", other),
+        }
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn error_not_retried() {
+        let mut outbox = Outbox::default();
+        outbox.add(ChannelId::Node(7), 100, 100, "Hi".into(), None);
+        outbox.sent(100);
+        assert!(matches!(
+            outbox.routing(100, 7, routing::Error::NotAuthorized),
+            Some(Delivery::Update(_, 100, Failed(_)))
+        ));
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn retry_until_timed_out() {
+        let mut outbox = Outbox::default();
+        outbox.set_max_retries(Some(1));
+        outbox.add(ChannelId::Node(7), 100, 100, "Hi".into(), None);
+
+        // Not sent to the radio yet, so it cannot time out
+        let later = Instant::now() + ACK_TIMEOUT * 2;
+        assert!(outbox.check_timeouts(later).is_empty());
+
+        outbox.sent(100);
+        assert!(outbox.check_timeouts(Instant::now()).is_empty());
+        let deliveries = outbox.check_timeouts(later);
+        assert!(matches!(deliveries.as_slice(), [Delivery::Retry(_)]));
+
+        let packet_id = outbox.queued()[0].packet_id;
+        outbox.sent(packet_id);
+        let deliveries = outbox.check_timeouts(Instant::now() + ACK_TIMEOUT * 3);
+        assert!(matches!(
+            deliveries.as_slice(),
+            [Delivery::Update(_, 100, TimedOut)]
+        ));
+        assert!(outbox.is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+//! Whether a node is online, stale or offline, from how long ago it was last heard
+use crate::config::Config;
+use crate::node_detail::time_ago;
+use crate::styles::{COLOR_GRAY_50, COLOR_GREEN, COLOR_ORANGE};
+use iced::Color;
+
+/// Nodes heard within this many minutes are online, by default
+const DEFAULT_ONLINE_MINUTES: u32 = 30;
+/// Nodes not heard for this many minutes are offline, by default
+const DEFAULT_OFFLINE_MINUTES: u32 = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Presence {
+    Online,
+    Stale,
+    Offline,
+}
+
+impl Presence {
+    /// Return the presence of a node last heard at `last_heard` (seconds since the epoch, 0 if
+    /// never), at time `now`, using the thresholds in the config
+    pub fn new(last_heard: u32, now: i64, config: &Config) -> Self {
+        let online = config.online_minutes.unwrap_or(DEFAULT_ONLINE_MINUTES) as i64 * 60;
+        let offline = config.offline_minutes.unwrap_or(DEFAULT_OFFLINE_MINUTES) as i64 * 60;
+        match now - last_heard as i64 {
+            _ if last_heard == 0 => Presence::Offline,
+            age if age <= online => Presence::Online,
+            age if age <= offline => Presence::Stale,
+            _ => Presence::Offline,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Presence::Online => "Online",
+            Presence::Stale => "Stale",
+            Presence::Offline => "Offline",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Presence::Online => COLOR_GREEN,
+            Presence::Stale => COLOR_ORANGE,
+            Presence::Offline => COLOR_GRAY_50,
+        }
+    }
+}
+
+/// Return when a node was last heard, relative to `now`, e.g. "heard 5m ago"
+pub fn heard_text(last_heard: u32, now: i64) -> String {
+    if last_heard == 0 {
+        "never heard".into()
+    } else {
+        format!("heard {}", time_ago(now - last_heard as i64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::presence::{Presence, heard_text};
+
+    const NOW: i64 = 1_750_000_000;
+
+    #[test]
+    fn default_thresholds() {
+        let config = Config::default();
+        let heard = |minutes_ago: i64| (NOW - minutes_ago * 60) as u32;
+        assert_eq!(Presence::new(heard(5), NOW, &config), Presence::Online);
+        assert_eq!(Presence::new(heard(60), NOW, &config), Presence::Stale);
+        assert_eq!(Presence::new(heard(180), NOW, &config), Presence::Offline);
+        assert_eq!(Presence::new(0, NOW, &config), Presence::Offline);
+    }
+
+    #[test]
+    fn configured_thresholds() {
+        let config = Config {
+            online_minutes: Some(2),
+            offline_minutes: Some(10),
+            ..Default::default()
+        };
+        let heard = |minutes_ago: i64| (NOW - minutes_ago * 60) as u32;
+        assert_eq!(Presence::new(heard(1), NOW, &config), Presence::Online);
+        assert_eq!(Presence::new(heard(5), NOW, &config), Presence::Stale);
+        assert_eq!(Presence::new(heard(11), NOW, &config), Presence::Offline);
+    }
+
+    #[test]
+    fn heard_texts() {
+        assert_eq!(heard_text(0, NOW), "never heard");
+        assert_eq!(heard_text((NOW - 300) as u32, NOW), "heard 5m ago");
+        assert_eq!(heard_text((NOW - 10) as u32, NOW), "heard just now");
+    }
+}
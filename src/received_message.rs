@@ -0,0 +1,195 @@
+//! Text messages received from the mesh, described for programs outside the app, such as the
+//! command line mode and the bridge, that print them or send them as JSON
+use crate::channel_id::ChannelId;
+use chrono::{DateTime, Local, Utc};
+use meshtastic::protobufs::FromRadio;
+use meshtastic::protobufs::PortNum;
+use meshtastic::protobufs::from_radio::PayloadVariant;
+use meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// A text message received from the mesh, as given to programs outside the app
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceivedMessage {
+    id: u32,
+    time: DateTime<Utc>,
+    from: u32,
+    from_name: String,
+    to: u32,
+    channel_id: ChannelId,
+    channel_name: String,
+    reply_to_id: Option<u32>,
+    text: String,
+}
+
+impl Display for ReceivedMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} [{}] {}: {}",
+            self.time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"),
+            self.channel_name,
+            self.from_name,
+            self.text
+        )
+    }
+}
+
+/// The names of the nodes and channels learnt from the radio, used to describe messages
+#[derive(Default)]
+pub struct Names {
+    nodes: HashMap<u32, String>,
+    channels: HashMap<i32, String>,
+}
+
+impl Names {
+    /// Learn names from a packet from the radio, and return the message in it if it is a text message
+    pub fn received(
+        &mut self,
+        packet: FromRadio,
+        aliases: &HashMap<u32, String>,
+    ) -> Option<ReceivedMessage> {
+        match packet.payload_variant? {
+            PayloadVariant::NodeInfo(node_info) => {
+                if let Some(user) = node_info.user {
+                    self.nodes.insert(node_info.num, user.long_name);
+                }
+                None
+            }
+            PayloadVariant::Channel(channel) => {
+                if let Some(settings) = channel.settings {
+                    let name = if settings.name.is_empty() {
+                        "Default".to_string()
+                    } else {
+                        settings.name
+                    };
+                    self.channels.insert(channel.index, name);
+                }
+                None
+            }
+            PayloadVariant::Packet(mesh_packet) => {
+                let Some(Decoded(data)) = mesh_packet.payload_variant else {
+                    return None;
+                };
+                if data.portnum != PortNum::TextMessageApp as i32 {
+                    return None;
+                }
+
+                let (channel_id, channel_name) = if mesh_packet.to == u32::MAX {
+                    let index = mesh_packet.channel as i32;
+                    let name = self.channels.get(&index).cloned();
+                    (
+                        ChannelId::Channel(index),
+                        name.unwrap_or(format!("Channel {index}")),
+                    )
+                } else {
+                    (ChannelId::Node(mesh_packet.from), "Direct".to_string())
+                };
+                let time = match mesh_packet.rx_time {
+                    0 => None,
+                    rx_time => DateTime::from_timestamp_secs(rx_time as i64),
+                };
+
+                Some(ReceivedMessage {
+                    id: mesh_packet.id,
+                    time: time.unwrap_or_else(Utc::now),
+                    from: mesh_packet.from,
+                    from_name: self.node_name(mesh_packet.from, aliases),
+                    to: mesh_packet.to,
+                    channel_id,
+                    channel_name,
+                    reply_to_id: (data.reply_id != 0).then_some(data.reply_id),
+                    text: String::from_utf8_lossy(&data.payload).to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the alias given to a node in the app, or its name, or its node id
+    fn node_name(&self, node_num: u32, aliases: &HashMap<u32, String>) -> String {
+        aliases
+            .get(&node_num)
+            .or(self.nodes.get(&node_num))
+            .cloned()
+            .unwrap_or(format!("!{node_num:08x}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::received_message::Names;
+    use meshtastic::protobufs::from_radio::PayloadVariant;
+    use meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded;
+    use meshtastic::protobufs::{
+        Channel, ChannelSettings, Data, FromRadio, MeshPacket, NodeInfo, PortNum, User,
+    };
+    use std::collections::HashMap;
+
+    fn from_radio(payload_variant: PayloadVariant) -> FromRadio {
+        FromRadio {
+            id: 1,
+            payload_variant: Some(payload_variant),
+        }
+    }
+
+    #[test]
+    fn received_text_message() {
+        let aliases = HashMap::new();
+        let mut names = Names::default();
+        assert!(
+            names
+                .received(
+                    from_radio(PayloadVariant::NodeInfo(NodeInfo {
+                        num: 1001,
+                        user: Some(User {
+                            long_name: "Alice".into(),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    })),
+                    &aliases,
+                )
+                .is_none()
+        );
+        assert!(
+            names
+                .received(
+                    from_radio(PayloadVariant::Channel(Channel {
+                        index: 0,
+                        settings: Some(ChannelSettings::default()),
+                        role: 1,
+                    })),
+                    &aliases,
+                )
+                .is_none()
+        );
+
+        let message = names
+            .received(
+                from_radio(PayloadVariant::Packet(MeshPacket {
+                    from: 1001,
+                    to: u32::MAX,
+                    id: 42,
+                    rx_time: 1_700_000_000,
+                    payload_variant: Some(Decoded(Data {
+                        portnum: PortNum::TextMessageApp as i32,
+                        payload: b"Hello".to_vec(),
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                })),
+                &aliases,
+            )
+            .expect("Text message was not received");
+        assert!(message.to_string().ends_with("[Default] Alice: Hello"));
+
+        let json = serde_json::to_string(&message).expect("Could not convert to JSON");
+        assert!(json.contains(r#""fromName":"Alice""#));
+        assert!(json.contains(r#""time":"2023-11-14T22:13:20Z""#));
+        assert!(json.contains(r#""channelId":{"Channel":0}"#));
+    }
+}
@@ -0,0 +1,435 @@
+//! Searching the text of the messages in all the channels of a device, including the stored
+//! history of channels the radio has not told us about yet
+use crate::Message;
+use crate::Message::DeviceViewEvent;
+use crate::channel_id::ChannelId;
+use crate::channel_view_entry::ChannelViewEntry;
+use crate::device_view::DeviceViewMessage::{MessageSearchMsg, ShowMessage};
+use crate::search::MessageSearchMessage::{Channel, Clear, FromDate, Query, Sender, ToDate};
+use crate::styles::{
+    TIME_TEXT_COLOR, button_chip_style, channel_row_style, scrollbar_style, text_input_style,
+};
+use chrono::{DateTime, Local, NaiveDate};
+use iced::widget::scrollable::Scrollbar;
+use iced::widget::{Column, Row, Space, button, pick_list, scrollable, text, text_input};
+use iced::{Center, Element, Fill, Padding};
+use std::collections::HashSet;
+use std::fmt;
+
+/// How many characters either side of the text found are shown in a result
+const SNIPPET_CONTEXT: usize = 30;
+
+/// The format dates are typed in, to limit a search to the messages between two days
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Only find messages from this node, or from anyone if None, with the name to show for it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SenderChoice(pub Option<u32>, pub String);
+
+impl fmt::Display for SenderChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => f.write_str("From anyone"),
+            Some(_) => f.write_str(&self.1),
+        }
+    }
+}
+
+/// Only find messages in this channel, or in any channel if None, with the name to show for it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelChoice(pub Option<ChannelId>, pub String);
+
+impl fmt::Display for ChannelChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => f.write_str("In any channel"),
+            Some(_) => f.write_str(&self.1),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MessageSearchMessage {
+    Query(String),
+    Sender(SenderChoice),
+    Channel(ChannelChoice),
+    FromDate(String),
+    ToDate(String),
+    Clear,
+}
+
+/// A message found by a search
+#[derive(Debug)]
+pub struct SearchResult {
+    pub channel_id: ChannelId,
+    pub message_id: u32,
+    pub from: u32,
+    pub time: DateTime<Local>,
+    pub snippet: String,
+    pub can_open: bool, // false for history of a channel the radio has not told us about
+}
+
+/// The text searched for in messages, the filters that narrow down the messages searched, and
+/// the messages found. The search is done when it changes, not each time it is shown.
+#[derive(Debug, Default)]
+pub struct MessageSearch {
+    query: String,
+    sender: SenderChoice,
+    channel: ChannelChoice,
+    from_date: String, // first day of messages to find, as YYYY-MM-DD, or empty from any day
+    to_date: String,   // last day of messages to find, as YYYY-MM-DD, or empty to any day
+    results: Vec<SearchResult>, // newest first
+    senders: Vec<u32>, // the nodes that sent any of the messages searched
+}
+
+impl MessageSearch {
+    /// Change what is searched for. [MessageSearch::search] must be called after, to update the
+    /// messages found
+    pub fn update(&mut self, message: MessageSearchMessage) {
+        match message {
+            Query(query) => self.query = query,
+            Sender(sender) => self.sender = sender,
+            Channel(channel) => self.channel = channel,
+            FromDate(date) => self.from_date = date,
+            ToDate(date) => self.to_date = date,
+            Clear => *self = MessageSearch::default(),
+        }
+    }
+
+    /// Search the entries of channels, given with whether the channel can be opened, keeping the
+    /// messages found and the senders of all the messages, for showing in the view
+    pub fn search<'a>(
+        &mut self,
+        channels: impl Iterator<Item = (&'a ChannelId, Vec<&'a ChannelViewEntry>, bool)>,
+    ) {
+        let mut results = vec![];
+        let mut senders = HashSet::new();
+        for (channel_id, entries, can_open) in channels {
+            for entry in entries.into_iter().filter(|entry| entry.text().is_some()) {
+                senders.insert(entry.from());
+                if let Some(snippet) = self.matches(channel_id, entry) {
+                    results.push(SearchResult {
+                        channel_id: channel_id.clone(),
+                        message_id: entry.message_id(),
+                        from: entry.from(),
+                        time: entry.time(),
+                        snippet,
+                        can_open,
+                    });
+                }
+            }
+        }
+        results.sort_by_key(|result| std::cmp::Reverse(result.time));
+        self.results = results;
+        self.senders = senders.into_iter().collect();
+    }
+
+    /// Return the nodes that sent any of the messages searched
+    pub fn senders(&self) -> &[u32] {
+        &self.senders
+    }
+
+    /// If an entry in a channel contains the text searched for, and passes the filters, return a
+    /// snippet of its text around where it was found
+    fn matches(&self, channel_id: &ChannelId, entry: &ChannelViewEntry) -> Option<String> {
+        let query = self.query.trim();
+        let (from_date, to_date) = self.date_range()?;
+        let date = entry.time().date_naive();
+        if query.is_empty()
+            || self.sender.0.is_some_and(|from| from != entry.from())
+            || self.channel.0.as_ref().is_some_and(|id| id != channel_id)
+            || from_date.is_some_and(|from_date| date < from_date)
+            || to_date.is_some_and(|to_date| date > to_date)
+        {
+            return None;
+        }
+        snippet(entry.text()?, query)
+    }
+
+    /// Return the first and last days of messages to find, either of which can be None for no
+    /// limit, or None if either is not a valid date
+    fn date_range(&self) -> Option<(Option<NaiveDate>, Option<NaiveDate>)> {
+        let parse = |date: &str| match date.trim() {
+            "" => Some(None),
+            date => NaiveDate::parse_from_str(date, DATE_FORMAT).ok().map(Some),
+        };
+        Some((parse(&self.from_date)?, parse(&self.to_date)?))
+    }
+
+    /// Show the search box, the filters, and the messages found, newest first, naming their
+    /// senders and channels with the names in the choices offered
+    pub fn view(
+        &self,
+        senders: Vec<SenderChoice>,
+        channels: Vec<ChannelChoice>,
+    ) -> Element<'_, Message> {
+        let event = |message| DeviceViewEvent(MessageSearchMsg(message));
+
+        let mut clear_button = button(text("⨂").size(18))
+            .style(button_chip_style)
+            .padding(Padding::from([6, 6]));
+        if !self.query.is_empty() || self.filtering() {
+            clear_button = clear_button.on_press(event(Clear));
+        }
+        let search_row = Row::new()
+            .push(
+                text_input("Search for text in messages", &self.query)
+                    .style(text_input_style)
+                    .padding([6, 6])
+                    .on_input(move |query| event(Query(query))),
+            )
+            .push(Space::new().width(4.0))
+            .push(clear_button)
+            .padding([0, 4])
+            .align_y(Center);
+
+        let sender_name = |from: u32| match senders.iter().find(|sender| sender.0 == Some(from)) {
+            Some(sender) => sender.1.clone(),
+            None => format!("Node {from}"),
+        };
+        let channel_name = |channel_id: &ChannelId| {
+            channels
+                .iter()
+                .find(|channel| channel.0.as_ref() == Some(channel_id))
+                .map(|channel| channel.1.clone())
+                .unwrap_or_default()
+        };
+        let mut results_column = Column::new().padding([0, 10]).spacing(2);
+        for result in &self.results {
+            let heading = Row::new()
+                .push(text(channel_name(&result.channel_id)))
+                .push(text(" · ").color(TIME_TEXT_COLOR))
+                .push(text(sender_name(result.from)))
+                .push(Space::new().width(Fill))
+                .push(
+                    text(result.time.format("%Y-%m-%d %H:%M").to_string())
+                        .size(14)
+                        .color(TIME_TEXT_COLOR),
+                );
+            let mut result_button = button(
+                Column::new()
+                    .push(heading)
+                    .push(text(result.snippet.as_str()).size(14)),
+            )
+            .width(Fill)
+            .style(channel_row_style);
+            if result.can_open {
+                result_button = result_button.on_press(DeviceViewEvent(ShowMessage(
+                    result.channel_id.clone(),
+                    result.message_id,
+                )));
+            }
+            results_column = results_column.push(result_button);
+        }
+
+        let senders: Vec<SenderChoice> = std::iter::once(SenderChoice::default())
+            .chain(senders)
+            .collect();
+        let channels: Vec<ChannelChoice> = std::iter::once(ChannelChoice::default())
+            .chain(channels)
+            .collect();
+        let date_input = |placeholder, date, on_input: fn(String) -> MessageSearchMessage| {
+            text_input(placeholder, date)
+                .style(text_input_style)
+                .padding([4, 6])
+                .size(14)
+                .width(120)
+                .on_input(move |date| event(on_input(date)))
+        };
+        let filter_row = Row::new()
+            .spacing(4)
+            .padding([2, 4])
+            .align_y(Center)
+            .push(
+                pick_list(senders, Some(self.sender.clone()), move |sender| {
+                    event(Sender(sender))
+                })
+                .text_size(14),
+            )
+            .push(
+                pick_list(channels, Some(self.channel.clone()), move |channel| {
+                    event(Channel(channel))
+                })
+                .text_size(14),
+            )
+            .push(date_input("From YYYY-MM-DD", &self.from_date, FromDate))
+            .push(date_input("To YYYY-MM-DD", &self.to_date, ToDate));
+
+        let summary = if self.date_range().is_none() {
+            "Type dates as YYYY-MM-DD, e.g. 2025-06-30".to_string()
+        } else if self.query.trim().is_empty() {
+            "Type some text to search for in the messages".to_string()
+        } else {
+            match self.results.len() {
+                1 => "1 message found".to_string(),
+                found => format!("{found} messages found"),
+            }
+        };
+
+        Column::new()
+            .push(search_row)
+            .push(filter_row)
+            .push(
+                text(summary)
+                    .size(14)
+                    .color(TIME_TEXT_COLOR)
+                    .width(Fill)
+                    .align_x(Center),
+            )
+            .push(
+                scrollable(results_column)
+                    .direction({
+                        let scrollbar = Scrollbar::new().width(10);
+                        scrollable::Direction::Vertical(scrollbar)
+                    })
+                    .style(scrollbar_style)
+                    .width(Fill)
+                    .height(Fill),
+            )
+            .into()
+    }
+
+    /// Return true if the messages searched are narrowed down by any of the filters
+    fn filtering(&self) -> bool {
+        self.sender.0.is_some()
+            || self.channel.0.is_some()
+            || !self.from_date.is_empty()
+            || !self.to_date.is_empty()
+    }
+}
+
+/// If `text` contains `query`, ignoring case, return the part of `text` around where it was found,
+/// with "…" where it has been cut short
+fn snippet(text: &str, query: &str) -> Option<String> {
+    let lower = |text: &str| -> Vec<char> {
+        text.chars()
+            .map(|c| c.to_lowercase().next().unwrap_or(c))
+            .collect()
+    };
+    let chars: Vec<char> = text.chars().collect();
+    let text_lower = lower(text);
+    let query_lower = lower(query);
+    let found = text_lower
+        .windows(query_lower.len())
+        .position(|window| window == query_lower.as_slice())?;
+
+    let start = found.saturating_sub(SNIPPET_CONTEXT);
+    let end = (found + query_lower.len() + SNIPPET_CONTEXT).min(chars.len());
+    let mut snippet: String = chars[start..end]
+        .iter()
+        .map(|c| if *c == '\n' { ' ' } else { *c })
+        .collect();
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::channel_id::ChannelId;
+    use crate::channel_view_entry::ChannelViewEntry;
+    use crate::channel_view_entry::Payload::{NewTextMessage, PositionMessage};
+    use crate::search::MessageSearchMessage::{Channel, Clear, FromDate, Query, Sender, ToDate};
+    use crate::search::{ChannelChoice, MessageSearch, SenderChoice, snippet};
+    use chrono::{Local, TimeDelta};
+
+    #[test]
+    fn snippets() {
+        assert_eq!(
+            snippet("Meet at the Pub", "pub"),
+            Some("Meet at the Pub".into())
+        );
+        assert_eq!(snippet("Meet at the pub", "cafe"), None);
+
+        let long = format!("{}needle{}", "a".repeat(40), "b".repeat(40));
+        assert_eq!(
+            snippet(&long, "NEEDLE"),
+            Some(format!("…{}needle{}…", "a".repeat(30), "b".repeat(30)))
+        );
+        assert_eq!(snippet("Line 1\nLine 2", "2"), Some("Line 1 Line 2".into()));
+    }
+
+    #[test]
+    fn filters() {
+        let channel = ChannelId::Channel(0);
+        let node = ChannelId::Node(7);
+        let entry = ChannelViewEntry::new(NewTextMessage("Hello world".into()), 7, 1);
+        let position = ChannelViewEntry::new(PositionMessage(1, 2), 7, 2);
+
+        let mut search = MessageSearch::default();
+        assert_eq!(search.matches(&channel, &entry), None);
+
+        search.update(Query("WORLD".into()));
+        assert_eq!(search.matches(&channel, &entry), Some("Hello world".into()));
+        assert_eq!(search.matches(&channel, &position), None);
+
+        search.update(Sender(SenderChoice(Some(8), "Bob".into())));
+        assert_eq!(search.matches(&channel, &entry), None);
+        search.update(Sender(SenderChoice(Some(7), "Alice".into())));
+        assert!(search.matches(&channel, &entry).is_some());
+
+        search.update(Channel(ChannelChoice(Some(node.clone()), "Alice".into())));
+        assert_eq!(search.matches(&channel, &entry), None);
+        assert!(search.matches(&node, &entry).is_some());
+    }
+
+    #[test]
+    fn date_range() {
+        let channel = ChannelId::Channel(0);
+        let entry = ChannelViewEntry::new(NewTextMessage("Hello world".into()), 7, 1);
+        let date = |days| {
+            (Local::now() + TimeDelta::days(days))
+                .format("%Y-%m-%d")
+                .to_string()
+        };
+
+        let mut search = MessageSearch::default();
+        search.update(Query("hello".into()));
+        search.update(FromDate(date(-7)));
+        search.update(ToDate(date(-1)));
+        assert_eq!(search.matches(&channel, &entry), None);
+
+        search.update(ToDate(date(0)));
+        assert!(search.matches(&channel, &entry).is_some());
+        search.update(FromDate(date(0)));
+        assert!(search.matches(&channel, &entry).is_some());
+        search.update(FromDate(date(1)));
+        assert_eq!(search.matches(&channel, &entry), None);
+
+        search.update(FromDate("last week".into()));
+        assert_eq!(search.date_range(), None);
+        assert_eq!(search.matches(&channel, &entry), None);
+        search.update(FromDate(String::new()));
+        assert!(search.matches(&channel, &entry).is_some());
+    }
+
+    #[test]
+    fn results_kept_until_searched_again() {
+        let channel = ChannelId::Channel(0);
+        let older = ChannelViewEntry::new(NewTextMessage("Hello".into()), 7, 1);
+        let newer = ChannelViewEntry::new(NewTextMessage("Hello again".into()), 8, 2);
+        let entries = vec![&older, &newer];
+
+        let mut search = MessageSearch::default();
+        search.update(Query("hello".into()));
+        search.search(std::iter::once((&channel, entries.clone(), true)));
+        let mut found: Vec<u32> = search.results.iter().map(|r| r.message_id).collect();
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+        let mut senders = search.senders().to_vec();
+        senders.sort();
+        assert_eq!(senders, vec![7, 8]);
+
+        search.update(Query("again".into()));
+        assert_eq!(search.results.len(), 2);
+        search.search(std::iter::once((&channel, entries, true)));
+        assert_eq!(search.results.len(), 1);
+
+        search.update(Clear);
+        assert!(search.results.is_empty());
+    }
+}
@@ -0,0 +1,454 @@
+//! A simulated radio, that speaks the meshtastic protocol over an in-memory stream, so that the
+//! app can be developed, tested and demoed on a machine with no radio (or Bluetooth) at all.
+//! It sends its config (MyInfo, NodeInfo and Channels) when asked, then a script of packets,
+//! acknowledges packets sent to it and can have failures injected.
+use crate::device_id::DeviceId;
+use meshtastic::Message;
+use meshtastic::api::StreamHandle;
+use meshtastic::errors::Error;
+use meshtastic::protobufs::channel::Role;
+use meshtastic::protobufs::config::device_config;
+use meshtastic::protobufs::from_radio::PayloadVariant;
+use meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded;
+use meshtastic::protobufs::to_radio::PayloadVariant::{Packet, WantConfigId};
+use meshtastic::protobufs::{
+    Channel, ChannelSettings, Data, FromRadio, MeshPacket, MyNodeInfo, NodeInfo, PortNum, Position,
+    Routing, ToRadio, User, routing,
+};
+use meshtastic::utils::generate_rand_id;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+/// The magic bytes at the start of each packet sent over a stream
+const MAGIC: [u8; 2] = [0x94, 0xc3];
+
+/// A simulated radio, and the mesh it can hear, built up using the builder methods
+#[derive(Clone)]
+pub struct SimulatedRadio {
+    my_node_num: u32,
+    nodes: Vec<NodeInfo>,
+    channels: Vec<Channel>,
+    script: Vec<(Duration, MeshPacket)>, // packets to send, each after a delay
+    unreachable: Vec<u32>,               // nodes that direct messages fail to be delivered to
+    echo: bool,                          // nodes echo back direct messages sent to them
+    failed_connects: Arc<AtomicU32>,     // how many more attempts to connect should fail
+    dropped_links: Arc<AtomicU32>,       // how many more connections to drop after configuring
+}
+
+/// A simulated mesh to demo the app with, with a couple of nodes that chat and one unreachable
+impl Default for SimulatedRadio {
+    fn default() -> Self {
+        SimulatedRadio::new(1000, "SIM", "Simulated Radio")
+            .node(1001, "ALIC", "Alice")
+            .node(1002, "BOB", "Bob")
+            .node(1003, "CARL", "Carol")
+            .channel("Friends")
+            .channel_message(
+                Duration::from_secs(3),
+                1001,
+                0,
+                "Hello from the simulated mesh!",
+            )
+            .position(Duration::from_secs(2), 1001, 515_007_000, -1_246_000)
+            .direct_message(
+                Duration::from_secs(5),
+                1002,
+                "Hi, this is Bob. Say something!",
+            )
+            .channel_message(
+                Duration::from_secs(4),
+                1002,
+                1,
+                "Anyone up for a walk later?",
+            )
+            .unreachable(1003)
+            .echo(true)
+    }
+}
+
+impl SimulatedRadio {
+    /// Create a simulated radio with its own node number and names, and a primary channel
+    pub fn new(my_node_num: u32, short_name: &str, long_name: &str) -> Self {
+        let mut radio = SimulatedRadio {
+            my_node_num,
+            nodes: vec![],
+            channels: vec![],
+            script: vec![],
+            unreachable: vec![],
+            echo: false,
+            failed_connects: Arc::new(AtomicU32::new(0)),
+            dropped_links: Arc::new(AtomicU32::new(0)),
+        };
+        radio = radio.node(my_node_num, short_name, long_name);
+        radio.channels.push(Channel {
+            index: 0,
+            settings: Some(ChannelSettings::default()),
+            role: Role::Primary as i32,
+        });
+        radio
+    }
+
+    /// Add a node to the mesh
+    pub fn node(mut self, num: u32, short_name: &str, long_name: &str) -> Self {
+        self.nodes.push(NodeInfo {
+            num,
+            user: Some(User {
+                id: format!("!{num:08x}"),
+                long_name: long_name.to_string(),
+                short_name: short_name.to_string(),
+                role: device_config::Role::Client as i32,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Add a secondary channel
+    pub fn channel(mut self, name: &str) -> Self {
+        self.channels.push(Channel {
+            index: self.channels.len() as i32,
+            settings: Some(ChannelSettings {
+                name: name.to_string(),
+                ..Default::default()
+            }),
+            role: Role::Secondary as i32,
+        });
+        self
+    }
+
+    /// Script a text message from a node to a channel, sent after `delay`
+    pub fn channel_message(self, delay: Duration, from: u32, channel: u32, text: &str) -> Self {
+        let data = Self::data(PortNum::TextMessageApp, text.as_bytes().to_vec());
+        self.packet(delay, from, u32::MAX, channel, data)
+    }
+
+    /// Script a direct text message from a node to us, sent after `delay`
+    pub fn direct_message(self, delay: Duration, from: u32, text: &str) -> Self {
+        let data = Self::data(PortNum::TextMessageApp, text.as_bytes().to_vec());
+        let to = self.my_node_num;
+        self.packet(delay, from, to, 0, data)
+    }
+
+    /// Script a node sharing its position on the primary channel, sent after `delay`
+    pub fn position(self, delay: Duration, from: u32, latitude_i: i32, longitude_i: i32) -> Self {
+        let position = Position {
+            latitude_i: Some(latitude_i),
+            longitude_i: Some(longitude_i),
+            ..Default::default()
+        };
+        let data = Self::data(PortNum::PositionApp, position.encode_to_vec());
+        self.packet(delay, from, u32::MAX, 0, data)
+    }
+
+    /// Direct messages to this node fail, as if it was out of range
+    pub fn unreachable(mut self, node_num: u32) -> Self {
+        self.unreachable.push(node_num);
+        self
+    }
+
+    /// Nodes reply to a direct message with the same text
+    pub fn echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Fail the next `count` attempts to connect to the radio
+    #[cfg(test)]
+    pub fn fail_connects(self, count: u32) -> Self {
+        self.failed_connects.store(count, Ordering::SeqCst);
+        self
+    }
+
+    /// Drop the next `count` connections to the radio, once it has sent its config
+    #[cfg(test)]
+    pub fn drop_links(self, count: u32) -> Self {
+        self.dropped_links.store(count, Ordering::SeqCst);
+        self
+    }
+
+    /// Connect to the simulated radio, returning a [StreamHandle] for the app's end of the stream.
+    /// The radio runs in a task on the other end of the stream until the app disconnects.
+    pub fn connect(&self) -> Result<StreamHandle<DuplexStream>, Error> {
+        if Self::take_one(&self.failed_connects) {
+            return Err(Error::StreamBuildError {
+                source: Box::new(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    "Simulated connection failure",
+                )),
+                description: format!("Could not connect to {}", DeviceId::Simulated),
+            });
+        }
+
+        let (app_end, radio_end) = tokio::io::duplex(64 * 1024);
+        let drop_link = Self::take_one(&self.dropped_links);
+        tokio::spawn(self.clone().run(radio_end, drop_link));
+        Ok(StreamHandle::from_stream(app_end))
+    }
+
+    /// Decrement `counter` if it is not zero, returning true if it was decremented
+    fn take_one(counter: &AtomicU32) -> bool {
+        counter
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                count.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Run the radio, responding to packets from the app until it disconnects
+    async fn run(self, stream: DuplexStream, drop_link: bool) {
+        let (mut reader, writer) = tokio::io::split(stream);
+        let (to_app, from_radio) = unbounded_channel();
+        tokio::spawn(Self::write_packets(writer, from_radio));
+
+        while let Ok(to_radio) = read_to_radio(&mut reader).await {
+            match to_radio.payload_variant {
+                Some(WantConfigId(config_id)) => {
+                    self.send_config(config_id, &to_app);
+                    if drop_link {
+                        // Dropping the radio's end of the stream, once the config is written
+                        break;
+                    }
+                    tokio::spawn(Self::run_script(self.script.clone(), to_app.clone()));
+                }
+                Some(Packet(mesh_packet)) => self.respond(&mesh_packet, &to_app),
+                _ => {} // Heartbeats and requests to disconnect need no response
+            }
+        }
+    }
+
+    /// Write packets to the app, until there are no more to send or the app has gone
+    async fn write_packets<W: AsyncWrite + Unpin>(
+        mut writer: W,
+        mut from_radio: UnboundedReceiver<FromRadio>,
+    ) {
+        while let Some(packet) = from_radio.recv().await {
+            if writer
+                .write_all(&frame(packet.encode_to_vec()))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Send the scripted packets, each after its delay
+    async fn run_script(script: Vec<(Duration, MeshPacket)>, to_app: UnboundedSender<FromRadio>) {
+        for (delay, mesh_packet) in script {
+            tokio::time::sleep(delay).await;
+            if to_app.send(Self::from_radio(mesh_packet)).is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Send the radio's config, as a real radio does when the app asks for it
+    fn send_config(&self, config_id: u32, to_app: &UnboundedSender<FromRadio>) {
+        let my_info = PayloadVariant::MyInfo(MyNodeInfo {
+            my_node_num: self.my_node_num,
+            ..Default::default()
+        });
+        let _ = to_app.send(Self::from_radio_variant(my_info));
+        for node_info in &self.nodes {
+            let node_info = PayloadVariant::NodeInfo(node_info.clone());
+            let _ = to_app.send(Self::from_radio_variant(node_info));
+        }
+        for channel in &self.channels {
+            let channel = PayloadVariant::Channel(channel.clone());
+            let _ = to_app.send(Self::from_radio_variant(channel));
+        }
+        let config_complete = PayloadVariant::ConfigCompleteId(config_id);
+        let _ = to_app.send(Self::from_radio_variant(config_complete));
+    }
+
+    /// Respond to a packet sent by the app: acknowledge it and echo direct messages back
+    fn respond(&self, mesh_packet: &MeshPacket, to_app: &UnboundedSender<FromRadio>) {
+        let Some(Decoded(data)) = &mesh_packet.payload_variant else {
+            return;
+        };
+        let broadcast = mesh_packet.to == u32::MAX;
+        let unreachable = self.unreachable.contains(&mesh_packet.to);
+
+        if mesh_packet.want_ack {
+            // The destination acknowledges a direct message, our own radio a broadcast once it
+            // hears it relayed. Our own radio reports when a direct message could not be delivered
+            let (from, error) = if broadcast {
+                (self.my_node_num, routing::Error::None)
+            } else if unreachable {
+                (self.my_node_num, routing::Error::MaxRetransmit)
+            } else {
+                (mesh_packet.to, routing::Error::None)
+            };
+            let routing = Routing {
+                variant: Some(routing::Variant::ErrorReason(error as i32)),
+            };
+            let mut ack_data = Self::data(PortNum::RoutingApp, routing.encode_to_vec());
+            ack_data.request_id = mesh_packet.id;
+            let ack = self.mesh_packet(from, self.my_node_num, mesh_packet.channel, ack_data);
+            let _ = to_app.send(Self::from_radio(ack));
+        }
+
+        if self.echo && !broadcast && !unreachable && data.portnum == PortNum::TextMessageApp as i32
+        {
+            let echo_data = Self::data(PortNum::TextMessageApp, data.payload.clone());
+            let echo = self.mesh_packet(mesh_packet.to, self.my_node_num, 0, echo_data);
+            let _ = to_app.send(Self::from_radio(echo));
+        }
+    }
+
+    /// Add a packet to the script, sent after `delay`
+    fn packet(mut self, delay: Duration, from: u32, to: u32, channel: u32, data: Data) -> Self {
+        let mesh_packet = self.mesh_packet(from, to, channel, data);
+        self.script.push((delay, mesh_packet));
+        self
+    }
+
+    fn mesh_packet(&self, from: u32, to: u32, channel: u32, data: Data) -> MeshPacket {
+        MeshPacket {
+            from,
+            to,
+            channel,
+            id: generate_rand_id(),
+            payload_variant: Some(Decoded(data)),
+            ..Default::default()
+        }
+    }
+
+    fn data(portnum: PortNum, payload: Vec<u8>) -> Data {
+        Data {
+            portnum: portnum as i32,
+            payload,
+            ..Default::default()
+        }
+    }
+
+    fn from_radio(mesh_packet: MeshPacket) -> FromRadio {
+        Self::from_radio_variant(PayloadVariant::Packet(mesh_packet))
+    }
+
+    fn from_radio_variant(payload_variant: PayloadVariant) -> FromRadio {
+        FromRadio {
+            id: generate_rand_id(),
+            payload_variant: Some(payload_variant),
+        }
+    }
+}
+
+/// Frame a packet the way it is sent over a stream, with magic bytes and length header
+fn frame(payload: Vec<u8>) -> Vec<u8> {
+    let mut framed = MAGIC.to_vec();
+    framed.extend((payload.len() as u16).to_be_bytes());
+    framed.extend(payload);
+    framed
+}
+
+/// Read one framed [ToRadio] packet sent by the app
+async fn read_to_radio<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<ToRadio> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header).await?;
+    if header[..2] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Bad frame magic bytes",
+        ));
+    }
+    let mut payload = vec![0u8; u16::from_be_bytes([header[2], header[3]]) as usize];
+    reader.read_exact(&mut payload).await?;
+    ToRadio::decode(payload.as_slice()).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::device_id::DeviceId;
+    use crate::device_subscription::SubscriberMessage::Connect;
+    use crate::device_subscription::SubscriptionEvent::{
+        ConnectedEvent, ConnectionError, DevicePacket, Ready,
+    };
+    use crate::device_subscription::{SubscriptionEvent, subscribe_with};
+    use crate::simulator::SimulatedRadio;
+    use meshtastic::protobufs::from_radio::PayloadVariant;
+    use meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded;
+    use std::time::Duration;
+    use tokio_stream::{Stream, StreamExt};
+
+    async fn next_event<S: Stream<Item = SubscriptionEvent> + Unpin>(
+        events: &mut S,
+    ) -> SubscriptionEvent {
+        tokio::time::timeout(Duration::from_secs(10), events.next())
+            .await
+            .expect("Timed out waiting for an event")
+            .expect("Subscription ended")
+    }
+
+    /// Return the payload of a packet from the radio, if the event is one
+    fn payload(event: SubscriptionEvent) -> Option<PayloadVariant> {
+        match event {
+            DevicePacket(packet) => packet.payload_variant,
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_config_then_script() {
+        let radio = SimulatedRadio::new(1000, "ME", "Me")
+            .node(1001, "ALIC", "Alice")
+            .channel("Friends")
+            .channel_message(Duration::ZERO, 1001, 1, "Hello");
+        let mut events = Box::pin(subscribe_with(radio));
+        let Ready(sender) = next_event(&mut events).await else {
+            panic!("Subscription did not start with Ready");
+        };
+        let _ = sender.send(Connect(DeviceId::Simulated)).await;
+        assert!(matches!(
+            next_event(&mut events).await,
+            ConnectedEvent(DeviceId::Simulated)
+        ));
+
+        let mut nodes = vec![];
+        let mut channels = vec![];
+        loop {
+            match payload(next_event(&mut events).await) {
+                Some(PayloadVariant::MyInfo(my_info)) => assert_eq!(my_info.my_node_num, 1000),
+                Some(PayloadVariant::NodeInfo(node_info)) => nodes.push(node_info.num),
+                Some(PayloadVariant::Channel(channel)) => channels.push(channel.index),
+                Some(PayloadVariant::Packet(mesh_packet)) => {
+                    let Some(Decoded(data)) = mesh_packet.payload_variant else {
+                        panic!("Packet was not decoded");
+                    };
+                    assert_eq!(mesh_packet.from, 1001);
+                    assert_eq!(mesh_packet.channel, 1);
+                    assert_eq!(data.payload, b"Hello");
+                    break;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(nodes, vec![1000, 1001]);
+        assert_eq!(channels, vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn failed_connect_reported() {
+        let radio = SimulatedRadio::new(1000, "ME", "Me").fail_connects(1);
+        let mut events = Box::pin(subscribe_with(radio));
+        let Ready(sender) = next_event(&mut events).await else {
+            panic!("Subscription did not start with Ready");
+        };
+        // Failing to connect at first is reported at once, not retried
+        let _ = sender.send(Connect(DeviceId::Simulated)).await;
+        assert!(matches!(
+            next_event(&mut events).await,
+            ConnectionError(DeviceId::Simulated, _, _)
+        ));
+        let _ = sender.send(Connect(DeviceId::Simulated)).await;
+        assert!(matches!(
+            next_event(&mut events).await,
+            ConnectedEvent(DeviceId::Simulated)
+        ));
+    }
+}
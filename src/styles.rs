@@ -6,7 +6,7 @@ use iced::widget::button::Status;
 use iced::widget::button::Status::Hovered;
 use iced::widget::container::Style;
 use iced::widget::scrollable::{AutoScroll, Rail, Scroller};
-use iced::widget::{button, container, scrollable, text, text_input};
+use iced::widget::{button, container, scrollable, text, text_editor, text_input};
 use iced::{Background, Border, Color, Shadow, Theme};
 use iced_aw::menu;
 use iced_aw::style::colors::RED;
@@ -209,6 +209,22 @@ pub fn text_input_style(_theme: &Theme, status: text_input::Status) -> text_inpu
     }
 }
 
+/// Style the message composer the same as the text inputs
+pub fn text_editor_style(_theme: &Theme, status: text_editor::Status) -> text_editor::Style {
+    let border = match status {
+        text_editor::Status::Active | text_editor::Status::Hovered => TEXT_INPUT_BORDER,
+        text_editor::Status::Focused { .. } => TEXT_INPUT_BORDER_ACTIVE,
+        text_editor::Status::Disabled => TEXT_INPUT_BORDER_DISABLED,
+    };
+    text_editor::Style {
+        background: TEXT_INPUT_BACKGROUND,
+        border,
+        placeholder: TEXT_INPUT_PLACEHOLDER_COLOR,
+        value: Color::WHITE,
+        selection: Default::default(),
+    }
+}
+
 const BUTTON_BORDER_ACTIVE: Border = Border {
     radius: TEXT_INPUT_RADIUS, // rounded corners
     width: 2.0,
@@ -250,6 +266,29 @@ pub fn count_style(_theme: &Theme) -> Style {
     }
 }
 
+/// A small label on a node, such as its role
+pub fn badge_style(_theme: &Theme) -> Style {
+    Style {
+        text_color: Some(Color::WHITE),
+        background: Some(Background::Color(COLOR_DARK_BLUE)),
+        border: TOOLTIP_BORDER,
+        shadow: NO_SHADOW,
+        snap: false,
+    }
+}
+
+/// An outline around a message that has been jumped to, so it can be seen in the channel
+pub fn highlight_style(_theme: &Theme) -> Style {
+    Style {
+        border: Border {
+            radius: Radius::from(12.0), // rounded corners
+            width: 2.0,
+            color: COLOR_YELLOW,
+        },
+        ..Default::default()
+    }
+}
+
 pub fn fav_button_style(_theme: &Theme, status: Status) -> button::Style {
     match status {
         Status::Active => button::Style {
@@ -0,0 +1,258 @@
+//! Telemetry reported by nodes (device, environment, power, air quality metrics etc.), kept per
+//! node with the time it was taken, and a small chart to show how a metric changed over time
+use crate::Message;
+use crate::styles::COLOR_GRAY_50;
+use iced::widget::canvas::{self, Frame, Geometry, Path, Stroke, Text};
+use iced::{Color, Point, Rectangle, Renderer, Theme, mouse};
+use meshtastic::protobufs::Telemetry;
+use meshtastic::protobufs::telemetry::Variant;
+use std::collections::VecDeque;
+
+/// How many telemetry reports to keep for each node, dropping the oldest ones
+const MAX_RECORDS: usize = 500;
+
+/// A telemetry report from a node, with the time (seconds since the epoch) it was taken
+struct TelemetryRecord {
+    time: i64,
+    variant: Variant,
+}
+
+/// The telemetry reports received from one node, oldest first
+#[derive(Default)]
+pub struct NodeTelemetry {
+    records: VecDeque<TelemetryRecord>,
+}
+
+/// The metrics that can be charted over time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Temperature,
+    Humidity,
+    Pressure,
+    Voltage,
+    ChannelUtilization,
+    AirUtilTx,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 6] = [
+        Metric::Temperature,
+        Metric::Humidity,
+        Metric::Pressure,
+        Metric::Voltage,
+        Metric::ChannelUtilization,
+        Metric::AirUtilTx,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Temperature => "Temperature",
+            Metric::Humidity => "Humidity",
+            Metric::Pressure => "Pressure",
+            Metric::Voltage => "Voltage",
+            Metric::ChannelUtilization => "Channel utilization",
+            Metric::AirUtilTx => "Air util TX",
+        }
+    }
+
+    /// Return a value of this metric as text, with its unit
+    pub fn format(&self, value: f32) -> String {
+        match self {
+            Metric::Temperature => format!("{value:.1} °C"),
+            Metric::Humidity => format!("{value:.0}%"),
+            Metric::Pressure => format!("{value:.1} hPa"),
+            Metric::Voltage => format!("{value:.2} V"),
+            Metric::ChannelUtilization | Metric::AirUtilTx => format!("{value:.1}%"),
+        }
+    }
+
+    /// Return the value of this metric in a telemetry report, if it has one
+    fn value(&self, variant: &Variant) -> Option<f32> {
+        match (self, variant) {
+            (Metric::Temperature, Variant::EnvironmentMetrics(metrics)) => metrics.temperature,
+            (Metric::Humidity, Variant::EnvironmentMetrics(metrics)) => metrics.relative_humidity,
+            (Metric::Pressure, Variant::EnvironmentMetrics(metrics)) => metrics.barometric_pressure,
+            (Metric::Voltage, Variant::DeviceMetrics(metrics)) => metrics.voltage,
+            (Metric::ChannelUtilization, Variant::DeviceMetrics(metrics)) => {
+                metrics.channel_utilization
+            }
+            (Metric::AirUtilTx, Variant::DeviceMetrics(metrics)) => metrics.air_util_tx,
+            _ => None,
+        }
+    }
+}
+
+impl NodeTelemetry {
+    /// Add a telemetry report, using `received` (seconds since the epoch) as the time it was
+    /// taken if the node did not say
+    pub fn add(&mut self, telemetry: Telemetry, received: i64) {
+        let Some(variant) = telemetry.variant else {
+            return;
+        };
+        let time = if telemetry.time == 0 {
+            received
+        } else {
+            telemetry.time as i64
+        };
+        if self.records.len() == MAX_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(TelemetryRecord { time, variant });
+    }
+
+    /// Return the (time, value) pairs reported for a metric, oldest first
+    pub fn series(&self, metric: Metric) -> Vec<(i64, f32)> {
+        self.records
+            .iter()
+            .filter_map(|record| Some((record.time, metric.value(&record.variant)?)))
+            .collect()
+    }
+}
+
+/// A small line chart of a metric's values over time
+pub struct Chart {
+    series: Vec<(i64, f32)>,
+    metric: Metric,
+}
+
+impl Chart {
+    pub fn new(metric: Metric, series: Vec<(i64, f32)>) -> Self {
+        Chart { series, metric }
+    }
+}
+
+impl canvas::Program<Message> for Chart {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &Renderer,
+        theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let color = theme.palette().primary;
+        let (Some(first), Some(last)) = (self.series.first(), self.series.last()) else {
+            return vec![];
+        };
+
+        let (min, max) = self
+            .series
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), (_, value)| {
+                (min.min(*value), max.max(*value))
+            });
+        let value_range = (max - min).max(f32::EPSILON);
+        let time_range = (last.0 - first.0).max(1) as f32;
+
+        // Leave room on the left for the minimum and maximum values
+        let left = 70.0;
+        let width = (frame.width() - left - 4.0).max(1.0);
+        let height = (frame.height() - 8.0).max(1.0);
+        let point = |(time, value): (i64, f32)| {
+            Point::new(
+                left + (time - first.0) as f32 / time_range * width,
+                4.0 + (1.0 - (value - min) / value_range) * height,
+            )
+        };
+
+        let axis = Path::new(|path| {
+            path.move_to(Point::new(left, 4.0));
+            path.line_to(Point::new(left, 4.0 + height));
+            path.line_to(Point::new(left + width, 4.0 + height));
+        });
+        frame.stroke(&axis, Stroke::default().with_color(COLOR_GRAY_50));
+
+        if self.series.len() == 1 {
+            frame.fill(&Path::circle(point(*first), 2.5), color);
+        } else {
+            let line = Path::new(|path| {
+                path.move_to(point(*first));
+                for sample in &self.series[1..] {
+                    path.line_to(point(*sample));
+                }
+            });
+            frame.stroke(&line, Stroke::default().with_color(color).with_width(1.5));
+        }
+
+        let label = |content: String, y: f32| Text {
+            content,
+            position: Point::new(0.0, y),
+            color: Color {
+                a: 0.8,
+                ..theme.palette().text
+            },
+            size: 11.into(),
+            ..Default::default()
+        };
+        frame.fill_text(label(self.metric.format(max), 0.0));
+        frame.fill_text(label(self.metric.format(min), frame.height() - 14.0));
+
+        vec![frame.into_geometry()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::telemetry::{MAX_RECORDS, Metric, NodeTelemetry};
+    use meshtastic::protobufs::telemetry::Variant;
+    use meshtastic::protobufs::{DeviceMetrics, EnvironmentMetrics, Telemetry};
+
+    fn environment(time: u32, temperature: f32) -> Telemetry {
+        Telemetry {
+            time,
+            variant: Some(Variant::EnvironmentMetrics(EnvironmentMetrics {
+                temperature: Some(temperature),
+                relative_humidity: Some(50.0),
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn series_of_metrics() {
+        let mut telemetry = NodeTelemetry::default();
+        telemetry.add(environment(100, 20.5), 500);
+        telemetry.add(
+            Telemetry {
+                time: 0,
+                variant: Some(Variant::DeviceMetrics(DeviceMetrics {
+                    voltage: Some(3.7),
+                    ..Default::default()
+                })),
+            },
+            200,
+        );
+        telemetry.add(environment(300, 21.0), 500);
+
+        assert_eq!(
+            telemetry.series(Metric::Temperature),
+            vec![(100, 20.5), (300, 21.0)]
+        );
+        assert_eq!(telemetry.series(Metric::Humidity).len(), 2);
+        // The time it was received is used when the node does not say when it was taken
+        assert_eq!(telemetry.series(Metric::Voltage), vec![(200, 3.7)]);
+        assert!(telemetry.series(Metric::Pressure).is_empty());
+        assert_eq!(telemetry.records.len(), 3);
+    }
+
+    #[test]
+    fn oldest_records_dropped() {
+        let mut telemetry = NodeTelemetry::default();
+        for time in 0..MAX_RECORDS as u32 + 10 {
+            telemetry.add(environment(time + 1, 20.0), 0);
+        }
+        let series = telemetry.series(Metric::Temperature);
+        assert_eq!(series.len(), MAX_RECORDS);
+        assert_eq!(series[0].0, 11);
+    }
+
+    #[test]
+    fn formatted_values() {
+        assert_eq!(Metric::Temperature.format(21.04), "21.0 °C");
+        assert_eq!(Metric::Voltage.format(3.7), "3.70 V");
+        assert_eq!(Metric::Pressure.format(1013.3), "1013.3 hPa");
+    }
+}
@@ -1,12 +1,23 @@
+use crate::Message::DeviceViewEvent;
 use crate::channel_id::ChannelId;
-use crate::channel_view_entry::Payload;
-use crate::device_subscription::SubscriptionEvent::DevicePacket;
+use crate::channel_view_entry::{ChannelViewEntry, Payload};
+use crate::device_id::DeviceId;
+use crate::device_subscription::SubscriberMessage::Connect;
+use crate::device_subscription::SubscriptionEvent::{DevicePacket, Ready};
+use crate::device_subscription::{SubscriberMessage, SubscriptionEvent, subscribe_with};
 use crate::device_view::DeviceView;
-use crate::device_view::DeviceViewMessage::SubscriptionMessage;
+use crate::device_view::DeviceViewMessage::{ConnectRequest, SubscriptionMessage};
+use crate::simulator::SimulatedRadio;
 use crate::{MeshChat, channel_view_entry};
 use meshtastic::protobufs::channel::Role;
 use meshtastic::protobufs::from_radio::PayloadVariant;
 use meshtastic::protobufs::{Channel, ChannelSettings, FromRadio, MyNodeInfo};
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::sync::mpsc::Sender;
+use tokio_stream::{Stream, StreamExt};
+
+pub type Events = Pin<Box<dyn Stream<Item = SubscriptionEvent> + Send>>;
 
 pub fn test_app() -> MeshChat {
     let mut meshchat = MeshChat::default();
@@ -60,3 +71,46 @@ impl MeshChat {
         channel_view.new_message(channel_view_entry);
     }
 }
+
+/// Start a subscription using the [SimulatedRadio] and ask it to connect to it, as the app does.
+/// Returns the stream of events from the subscription, and the sender used to send it messages,
+/// as Tasks returned by the app are not run in tests
+pub async fn connect_simulated(
+    meshchat: &mut MeshChat,
+    radio: SimulatedRadio,
+) -> (Events, Sender<SubscriberMessage>) {
+    let mut events: Events = Box::pin(subscribe_with(radio));
+    let Some(Ready(sender)) = events.next().await else {
+        panic!("Subscription did not start with Ready");
+    };
+    let _ = meshchat.update(DeviceViewEvent(SubscriptionMessage(Ready(sender.clone()))));
+    let _ = meshchat.update(DeviceViewEvent(ConnectRequest(DeviceId::Simulated, None)));
+    let _ = sender.send(Connect(DeviceId::Simulated)).await;
+    (events, sender)
+}
+
+/// Pass events from the subscription to the app, until `done` returns true
+pub async fn run_until<F: Fn(&MeshChat) -> bool>(
+    meshchat: &mut MeshChat,
+    events: &mut Events,
+    done: F,
+) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !done(meshchat) {
+            let event = events.next().await.expect("Subscription ended");
+            let _ = meshchat.update(DeviceViewEvent(SubscriptionMessage(event)));
+        }
+    })
+    .await
+    .expect("Timed out waiting for the app")
+}
+
+/// Return the entries in a channel of the app, or none if it has no such channel
+pub fn entries(meshchat: &MeshChat, channel_id: &ChannelId) -> Vec<ChannelViewEntry> {
+    meshchat
+        .device_view
+        .channel_views
+        .get(channel_id)
+        .map(|channel_view| channel_view.history())
+        .unwrap_or_default()
+}
@@ -0,0 +1,353 @@
+//! A graph of the mesh, built from the neighbors each node reports in NeighborInfo packets.
+//! Our node is drawn in the centre, with other nodes on rings around it by how many links away
+//! they are, and nodes with no known links on the outside ring.
+//! Clicking on a node shows its details.
+use crate::Message;
+use crate::Message::DeviceViewEvent;
+use crate::config::Config;
+use crate::device_view::DeviceViewMessage::ShowNodeDetail;
+use crate::map::node_label;
+use crate::styles::{COLOR_BLUE, COLOR_GRAY_50, COLOR_GREEN, COLOR_ORANGE, COLOR_RED};
+use iced::widget::canvas::{self, Action, Event, Frame, Geometry, Path, Stroke, Text};
+use iced::{Color, Point, Rectangle, Renderer, Size, Theme, Vector, mouse};
+use meshtastic::protobufs::{Neighbor, NodeInfo};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::f32::consts::TAU;
+
+/// Radius of the dot drawn for a node, and how close a click needs to be to select it
+const NODE_RADIUS: f32 = 5.0;
+const CLICK_DISTANCE: f32 = 12.0;
+
+/// Space to leave around the outside ring, so labels fit
+const MARGIN: f32 = 50.0;
+
+/// SNR (dB) at or above which a link is considered good, and below which it is weak
+const GOOD_SNR: f32 = 5.0;
+const WEAK_SNR: f32 = -7.5;
+
+/// A node in the graph, with the ring it is drawn on (0 is the centre) and its position around it
+struct Vertex {
+    num: u32,
+    label: String,
+    ring: usize,
+    angle: f32,
+    linked: bool,
+}
+
+/// A link between two nodes, with the SNR it was heard with. When both nodes report the link,
+/// the weaker SNR is used
+#[derive(Debug, PartialEq)]
+struct Link {
+    a: u32,
+    b: u32,
+    snr: f32,
+}
+
+/// The known mesh, laid out to be drawn as a graph
+pub struct Topology {
+    my_node_num: Option<u32>,
+    vertices: Vec<Vertex>,
+    links: Vec<Link>,
+    rings: usize,
+}
+
+impl Topology {
+    pub fn new(
+        my_node_num: Option<u32>,
+        nodes: &HashMap<u32, NodeInfo>,
+        neighbors: &HashMap<u32, Vec<Neighbor>>,
+        config: &Config,
+    ) -> Self {
+        let links = links(neighbors);
+
+        let mut nums: BTreeSet<u32> = nodes.keys().copied().collect();
+        nums.extend(links.iter().flat_map(|link| [link.a, link.b]));
+        nums.extend(my_node_num);
+
+        // Place nodes on rings by how many links they are from us, and those not reachable
+        // from us on the outside ring
+        let depths = my_node_num
+            .map(|my_node_num| depths(my_node_num, &links))
+            .unwrap_or_default();
+        let outside_ring = depths.values().max().map_or(1, |max| max + 1);
+        let mut rings: HashMap<usize, Vec<u32>> = HashMap::new();
+        for num in &nums {
+            let ring = depths.get(num).copied().unwrap_or(outside_ring);
+            rings.entry(ring).or_default().push(*num);
+        }
+
+        let mut vertices = vec![];
+        for (ring, ring_nums) in &rings {
+            for (index, num) in ring_nums.iter().enumerate() {
+                vertices.push(Vertex {
+                    num: *num,
+                    label: node_label(*num, nodes.get(num), config),
+                    ring: *ring,
+                    angle: TAU * index as f32 / ring_nums.len() as f32,
+                    linked: links.iter().any(|link| link.a == *num || link.b == *num),
+                });
+            }
+        }
+
+        Topology {
+            my_node_num,
+            vertices,
+            links,
+            rings: rings.keys().max().copied().unwrap_or(0),
+        }
+    }
+
+    /// Return where to draw a vertex in a canvas of `size`
+    fn vertex_point(&self, vertex: &Vertex, size: Size) -> Point {
+        let ring_spacing =
+            (size.width.min(size.height) / 2.0 - MARGIN).max(10.0) / self.rings.max(1) as f32;
+        let radius = vertex.ring as f32 * ring_spacing;
+        Point::new(
+            size.width / 2.0 + radius * vertex.angle.cos(),
+            size.height / 2.0 + radius * vertex.angle.sin(),
+        )
+    }
+
+    /// Return the node drawn at (or near) a point in the graph, if there is one
+    fn vertex_at(&self, point: Point, size: Size) -> Option<&Vertex> {
+        self.vertices
+            .iter()
+            .map(|vertex| (vertex, self.vertex_point(vertex, size).distance(point)))
+            .filter(|(_, distance)| *distance <= CLICK_DISTANCE)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(vertex, _)| vertex)
+    }
+}
+
+impl canvas::Program<Message> for Topology {
+    type State = ();
+
+    fn update(
+        &self,
+        _state: &mut Self::State,
+        event: &Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> Option<Action<Message>> {
+        let point = cursor.position_in(bounds)?;
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let vertex = self.vertex_at(point, bounds.size())?;
+                Some(Action::publish(DeviceViewEvent(ShowNodeDetail(vertex.num))).and_capture())
+            }
+            _ => None,
+        }
+    }
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &Renderer,
+        theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let size = bounds.size();
+        let points: HashMap<u32, Point> = self
+            .vertices
+            .iter()
+            .map(|vertex| (vertex.num, self.vertex_point(vertex, size)))
+            .collect();
+
+        for link in &self.links {
+            let (Some(a), Some(b)) = (points.get(&link.a), points.get(&link.b)) else {
+                continue;
+            };
+            let line = Path::line(*a, *b);
+            frame.stroke(
+                &line,
+                Stroke::default()
+                    .with_color(snr_color(link.snr))
+                    .with_width(snr_width(link.snr)),
+            );
+            frame.fill_text(Text {
+                content: format!("{:.1}", link.snr),
+                position: Point::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0),
+                color: snr_color(link.snr),
+                size: 11.into(),
+                ..Default::default()
+            });
+        }
+
+        for vertex in &self.vertices {
+            let point = points[&vertex.num];
+            let color = if Some(vertex.num) == self.my_node_num {
+                COLOR_BLUE
+            } else if vertex.linked {
+                theme.palette().text
+            } else {
+                COLOR_GRAY_50
+            };
+            frame.fill(&Path::circle(point, NODE_RADIUS), color);
+            frame.fill_text(Text {
+                content: vertex.label.clone(),
+                position: point + Vector::new(NODE_RADIUS + 4.0, -8.0),
+                color,
+                ..Default::default()
+            });
+        }
+
+        if self.links.is_empty() {
+            frame.fill_text(Text {
+                content: "No neighbor info received yet".into(),
+                position: Point::new(20.0, 20.0),
+                color: theme.palette().text,
+                ..Default::default()
+            });
+        }
+
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(
+        &self,
+        _state: &Self::State,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> mouse::Interaction {
+        match cursor.position_in(bounds) {
+            Some(point) if self.vertex_at(point, bounds.size()).is_some() => {
+                mouse::Interaction::Pointer
+            }
+            _ => mouse::Interaction::default(),
+        }
+    }
+}
+
+/// Return the links between nodes, from the neighbors reported by each node
+fn links(neighbors: &HashMap<u32, Vec<Neighbor>>) -> Vec<Link> {
+    let mut links: HashMap<(u32, u32), f32> = HashMap::new();
+    for (node, node_neighbors) in neighbors {
+        for neighbor in node_neighbors {
+            let key = ((*node).min(neighbor.node_id), (*node).max(neighbor.node_id));
+            links
+                .entry(key)
+                .and_modify(|snr| *snr = snr.min(neighbor.snr))
+                .or_insert(neighbor.snr);
+        }
+    }
+    let mut links: Vec<Link> = links
+        .into_iter()
+        .map(|((a, b), snr)| Link { a, b, snr })
+        .collect();
+    links.sort_by_key(|link| (link.a, link.b));
+    links
+}
+
+/// Return how many links each node reachable from `start` is from it
+fn depths(start: u32, links: &[Link]) -> HashMap<u32, usize> {
+    let mut depths = HashMap::from([(start, 0)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        let depth = depths[&node];
+        for link in links {
+            let other = if link.a == node {
+                link.b
+            } else if link.b == node {
+                link.a
+            } else {
+                continue;
+            };
+            if let Entry::Vacant(entry) = depths.entry(other) {
+                entry.insert(depth + 1);
+                queue.push_back(other);
+            }
+        }
+    }
+    depths
+}
+
+fn snr_color(snr: f32) -> Color {
+    if snr >= GOOD_SNR {
+        COLOR_GREEN
+    } else if snr >= WEAK_SNR {
+        COLOR_ORANGE
+    } else {
+        COLOR_RED
+    }
+}
+
+/// Return the width of the line to draw a link with, wider for better SNR
+fn snr_width(snr: f32) -> f32 {
+    1.0 + ((snr + 20.0) / 30.0).clamp(0.0, 1.0) * 3.0
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::topology::{Link, Topology, depths, links, snr_width};
+    use iced::Size;
+    use meshtastic::protobufs::{Neighbor, NodeInfo};
+    use std::collections::HashMap;
+
+    fn neighbor(node_id: u32, snr: f32) -> Neighbor {
+        Neighbor {
+            node_id,
+            snr,
+            ..Default::default()
+        }
+    }
+
+    fn neighbors() -> HashMap<u32, Vec<Neighbor>> {
+        HashMap::from([
+            (1000, vec![neighbor(1001, 6.0)]),
+            (1001, vec![neighbor(1000, 4.5), neighbor(1002, -10.0)]),
+        ])
+    }
+
+    #[test]
+    fn links_between_nodes() {
+        assert_eq!(
+            links(&neighbors()),
+            vec![
+                Link {
+                    a: 1000,
+                    b: 1001,
+                    snr: 4.5
+                },
+                Link {
+                    a: 1001,
+                    b: 1002,
+                    snr: -10.0
+                },
+            ]
+        );
+        assert!(snr_width(10.0) > snr_width(-10.0));
+    }
+
+    #[test]
+    fn depths_from_me() {
+        let depths = depths(1000, &links(&neighbors()));
+        assert_eq!(depths[&1000], 0);
+        assert_eq!(depths[&1001], 1);
+        assert_eq!(depths[&1002], 2);
+    }
+
+    #[test]
+    fn isolated_nodes_outside() {
+        let nodes = HashMap::from([(1003, NodeInfo::default())]);
+        let topology = Topology::new(Some(1000), &nodes, &neighbors(), &Config::default());
+        assert_eq!(topology.vertices.len(), 4);
+        assert_eq!(topology.rings, 3);
+
+        let isolated = topology.vertices.iter().find(|v| v.num == 1003).unwrap();
+        assert_eq!(isolated.ring, 3);
+        assert!(!isolated.linked);
+
+        let size = Size::new(400.0, 400.0);
+        let me = topology.vertices.iter().find(|v| v.num == 1000).unwrap();
+        let point = topology.vertex_point(me, size);
+        assert_eq!((point.x, point.y), (200.0, 200.0));
+        assert_eq!(
+            topology.vertex_at(point, size).map(|vertex| vertex.num),
+            Some(1000)
+        );
+    }
+}
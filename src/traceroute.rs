@@ -0,0 +1,156 @@
+//! The route taken through the mesh to reach a node, and back, as found by a traceroute
+use crate::config::Config;
+use meshtastic::protobufs::{NodeInfo, RouteDiscovery};
+use std::collections::HashMap;
+
+/// The value the firmware uses for an SNR it does not know
+const UNKNOWN_SNR: i32 = i8::MIN as i32;
+
+/// A node on a route, with the SNR (in dB) that the packet was received with from the node before
+#[derive(Debug, PartialEq)]
+pub struct Hop {
+    pub node: u32,
+    pub snr: Option<f32>,
+}
+
+/// The state of a traceroute sent to a node
+#[derive(Debug, PartialEq)]
+pub enum Traceroute {
+    Pending,
+    /// The hops from us to the node, and back to us if the return route is known.
+    /// Both include us and the node at the ends.
+    Complete {
+        towards: Vec<Hop>,
+        back: Option<Vec<Hop>>,
+    },
+}
+
+impl Traceroute {
+    /// Create a completed [Traceroute] from the [RouteDiscovery] the `target` node replied with
+    pub fn new(my_node_num: u32, target: u32, route_discovery: &RouteDiscovery) -> Self {
+        let towards = hops(
+            my_node_num,
+            &route_discovery.route,
+            target,
+            &route_discovery.snr_towards,
+        );
+        // Older firmware does not report the route back
+        let back = (!route_discovery.snr_back.is_empty()).then(|| {
+            hops(
+                target,
+                &route_discovery.route_back,
+                my_node_num,
+                &route_discovery.snr_back,
+            )
+        });
+        Traceroute::Complete { towards, back }
+    }
+}
+
+/// Return the hops from `from` via `route` to `to`, with the SNRs each was received with
+fn hops(from: u32, route: &[u32], to: u32, snrs: &[i32]) -> Vec<Hop> {
+    let mut hops = vec![Hop {
+        node: from,
+        snr: None,
+    }];
+    for (index, node) in route.iter().chain([&to]).enumerate() {
+        let snr = snrs
+            .get(index)
+            .filter(|snr| **snr != UNKNOWN_SNR)
+            .map(|snr| *snr as f32 / 4.0);
+        hops.push(Hop { node: *node, snr });
+    }
+    hops
+}
+
+/// Return the name to show for a node on a route: its alias, long name or node id
+pub fn hop_name(nodes: &HashMap<u32, NodeInfo>, config: &Config, node: u32) -> String {
+    config
+        .aliases
+        .get(&node)
+        .cloned()
+        .or(nodes
+            .get(&node)
+            .and_then(|node_info| node_info.user.as_ref())
+            .map(|user| user.long_name.clone()))
+        .unwrap_or(format!("!{node:08x}"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+    use crate::traceroute::{Hop, Traceroute, hop_name};
+    use meshtastic::protobufs::{NodeInfo, RouteDiscovery, User};
+    use std::collections::HashMap;
+
+    #[test]
+    fn route_with_return() {
+        let route_discovery = RouteDiscovery {
+            route: vec![2000],
+            snr_towards: vec![21, -128],
+            route_back: vec![],
+            snr_back: vec![-10],
+        };
+        assert_eq!(
+            Traceroute::new(1000, 3000, &route_discovery),
+            Traceroute::Complete {
+                towards: vec![
+                    Hop {
+                        node: 1000,
+                        snr: None
+                    },
+                    Hop {
+                        node: 2000,
+                        snr: Some(5.25)
+                    },
+                    Hop {
+                        node: 3000,
+                        snr: None
+                    },
+                ],
+                back: Some(vec![
+                    Hop {
+                        node: 3000,
+                        snr: None
+                    },
+                    Hop {
+                        node: 1000,
+                        snr: Some(-2.5)
+                    },
+                ]),
+            }
+        );
+    }
+
+    #[test]
+    fn route_without_return() {
+        let Traceroute::Complete { towards, back } =
+            Traceroute::new(1000, 3000, &RouteDiscovery::default())
+        else {
+            panic!("Expected a complete traceroute")
+        };
+        assert_eq!(towards.len(), 2);
+        assert_eq!(back, None);
+    }
+
+    #[test]
+    fn hop_names() {
+        let mut nodes = HashMap::new();
+        nodes.insert(
+            2000,
+            NodeInfo {
+                num: 2000,
+                user: Some(User {
+                    long_name: "Alice".into(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        let mut config = Config::default();
+        config.aliases.insert(3000, "Bob".into());
+        assert_eq!(hop_name(&nodes, &config, 2000), "Alice");
+        assert_eq!(hop_name(&nodes, &config, 3000), "Bob");
+        assert_eq!(hop_name(&nodes, &config, 0xabc), "!00000abc");
+    }
+}
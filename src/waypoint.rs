@@ -0,0 +1,314 @@
+//! Waypoints shared on the mesh: a named location with a description, an icon and an optional
+//! expiry time. Includes the form used to create and edit them.
+use crate::Message;
+use crate::Message::DeviceViewEvent;
+use crate::channel_id::ChannelId;
+use crate::device_view::DeviceViewMessage::{
+    SendWaypointMessage, StopEditingWaypoint, WaypointEditorMsg,
+};
+use crate::styles::{button_chip_style, picker_header_style, text_input_style, tooltip_style};
+use chrono::{DateTime, Local, Utc};
+use iced::font::Weight;
+use iced::widget::{Column, Row, Space, button, container, text, text_input};
+use iced::{Center, Element, Fill, Font};
+use meshtastic::protobufs::Waypoint;
+
+/// The longest name and description the firmware accepts, in characters
+const MAX_NAME: usize = 30;
+const MAX_DESCRIPTION: usize = 100;
+
+/// The most digits that can be typed for the hours until a waypoint expires, over 100 years
+const MAX_EXPIRE_DIGITS: usize = 6;
+
+/// The icon to show for a waypoint that does not have one
+const DEFAULT_ICON: char = '📍';
+
+/// The expiry time used to tell other nodes that a waypoint has been deleted
+pub const DELETED: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub enum WaypointEditorMessage {
+    Name(String),
+    Description(String),
+    Icon(String),
+    Expiry(String),
+}
+
+/// The state of the form used to create or edit a waypoint
+#[derive(Debug, Default)]
+pub struct WaypointEditor {
+    id: u32,
+    latitude_i: i32,
+    longitude_i: i32,
+    locked_to: u32,
+    name: String,
+    description: String,
+    icon: String,
+    expire_hours: String, // Hours from now until it expires, empty if it never expires
+}
+
+impl WaypointEditor {
+    /// Create a form for a new waypoint, at a location
+    pub fn new(id: u32, latitude_i: i32, longitude_i: i32) -> Self {
+        WaypointEditor {
+            id,
+            latitude_i,
+            longitude_i,
+            icon: DEFAULT_ICON.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Create a form to edit an existing waypoint, keeping its id and location
+    pub fn edit(waypoint: &Waypoint, now: i64) -> Self {
+        let expire_hours = if waypoint.expire == 0 {
+            String::new()
+        } else {
+            ((waypoint.expire as i64 - now).max(0) / 3600).to_string()
+        };
+        WaypointEditor {
+            id: waypoint.id,
+            latitude_i: waypoint.latitude_i.unwrap_or_default(),
+            longitude_i: waypoint.longitude_i.unwrap_or_default(),
+            locked_to: waypoint.locked_to,
+            name: waypoint.name.clone(),
+            description: waypoint.description.clone(),
+            icon: icon(waypoint).to_string(),
+            expire_hours,
+        }
+    }
+
+    pub fn update(&mut self, message: WaypointEditorMessage) {
+        match message {
+            WaypointEditorMessage::Name(name) => self.name = truncate(name, MAX_NAME),
+            WaypointEditorMessage::Description(description) => {
+                self.description = truncate(description, MAX_DESCRIPTION)
+            }
+            // Keep only the last character typed, so it can be replaced without deleting first
+            WaypointEditorMessage::Icon(icon) => {
+                self.icon = icon.chars().last().map(String::from).unwrap_or_default()
+            }
+            WaypointEditorMessage::Expiry(hours) => {
+                if hours.len() <= MAX_EXPIRE_DIGITS && hours.chars().all(|c| c.is_ascii_digit()) {
+                    self.expire_hours = hours
+                }
+            }
+        }
+    }
+
+    /// Return the [Waypoint] described by the form, or None if it has no name yet
+    pub fn waypoint(&self, now: i64) -> Option<Waypoint> {
+        if self.name.trim().is_empty() {
+            return None;
+        }
+        // Expiring later than a u32 of seconds can hold is as good as never
+        let expire = match self.expire_hours.parse::<i64>() {
+            Ok(hours) => hours
+                .checked_mul(3600)
+                .and_then(|seconds| now.checked_add(seconds))
+                .and_then(|expire| u32::try_from(expire).ok())
+                .unwrap_or(u32::MAX),
+            Err(_) => 0,
+        };
+        Some(Waypoint {
+            id: self.id,
+            latitude_i: Some(self.latitude_i),
+            longitude_i: Some(self.longitude_i),
+            expire,
+            locked_to: self.locked_to,
+            name: self.name.trim().to_string(),
+            description: self.description.clone(),
+            icon: self.icon.chars().next().unwrap_or(DEFAULT_ICON) as u32,
+        })
+    }
+
+    /// Create the form, to be shown in a modal dialog over `channel_id`
+    pub fn view(&self, channel_id: &ChannelId) -> Element<'_, Message> {
+        let input = |placeholder: &str, value: &str, on_input: fn(String) -> _| {
+            text_input(placeholder, value)
+                .style(text_input_style)
+                .padding([6, 6])
+                .on_input(move |s| DeviceViewEvent(WaypointEditorMsg(on_input(s))))
+        };
+
+        let mut save_button = button("Send").style(button_chip_style);
+        if !self.name.trim().is_empty() {
+            save_button =
+                save_button.on_press(DeviceViewEvent(SendWaypointMessage(channel_id.clone())));
+        }
+
+        let form = Column::new()
+            .spacing(8)
+            .padding(8)
+            .push(text(format!(
+                "Location: {:.5}, {:.5}",
+                self.latitude_i as f64 * 1e-7,
+                self.longitude_i as f64 * 1e-7
+            )))
+            .push(input("Name", &self.name, WaypointEditorMessage::Name))
+            .push(input(
+                "Description",
+                &self.description,
+                WaypointEditorMessage::Description,
+            ))
+            .push(
+                Row::new()
+                    .spacing(8)
+                    .align_y(Center)
+                    .push(text("Icon"))
+                    .push(input("📍", &self.icon, WaypointEditorMessage::Icon).width(60))
+                    .push(text("Expires in"))
+                    .push(
+                        input("never", &self.expire_hours, WaypointEditorMessage::Expiry).width(80),
+                    )
+                    .push(text("hours")),
+            )
+            .push(
+                Row::new()
+                    .push(Space::new().width(Fill))
+                    .push(
+                        button("Cancel")
+                            .style(button_chip_style)
+                            .on_press(DeviceViewEvent(StopEditingWaypoint)),
+                    )
+                    .push(Space::new().width(8))
+                    .push(save_button),
+            );
+
+        container(
+            Column::new()
+                .push(
+                    container(
+                        text("Waypoint")
+                            .size(18)
+                            .width(Fill)
+                            .font(Font {
+                                weight: Weight::Bold,
+                                ..Default::default()
+                            })
+                            .align_x(Center),
+                    )
+                    .style(picker_header_style)
+                    .padding(4),
+                )
+                .push(form),
+        )
+        .style(tooltip_style)
+        .width(400)
+        .into()
+    }
+}
+
+/// Return the icon of a waypoint, or the default one if it has none
+pub fn icon(waypoint: &Waypoint) -> char {
+    char::from_u32(waypoint.icon)
+        .filter(|icon| *icon != '\0')
+        .unwrap_or(DEFAULT_ICON)
+}
+
+/// Return true if the waypoint has expired, or has been deleted, at `now`
+pub fn expired(waypoint: &Waypoint, now: i64) -> bool {
+    waypoint.expire != 0 && (waypoint.expire as i64) <= now
+}
+
+/// Return a one line description of a waypoint, e.g. "📍 Meet here"
+pub fn waypoint_text(waypoint: &Waypoint) -> String {
+    format!("{} {}", icon(waypoint), waypoint.name)
+}
+
+/// Return when a waypoint expires, as text, or None if it never does
+pub fn expiry_text(waypoint: &Waypoint, now: i64) -> Option<String> {
+    match waypoint.expire {
+        0 => None,
+        DELETED => Some("Deleted".into()),
+        _ if expired(waypoint, now) => Some("Expired".into()),
+        expire => {
+            let time = DateTime::<Utc>::from_timestamp_secs(expire as i64)?.with_timezone(&Local);
+            Some(format!("Expires {}", time.format("%Y-%m-%d %H:%M")))
+        }
+    }
+}
+
+/// Truncate a String to at most `max_chars` characters
+fn truncate(mut s: String, max_chars: usize) -> String {
+    if let Some((index, _)) = s.char_indices().nth(max_chars) {
+        s.truncate(index);
+    }
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use crate::waypoint::WaypointEditorMessage::{Description, Expiry, Icon, Name};
+    use crate::waypoint::{DELETED, WaypointEditor, expired, expiry_text, icon, waypoint_text};
+    use meshtastic::protobufs::Waypoint;
+
+    const NOW: i64 = 1_750_000_000;
+
+    #[test]
+    fn new_waypoint() {
+        let mut editor = WaypointEditor::new(42, 515_000_000, -1_000_000);
+        assert!(editor.waypoint(NOW).is_none());
+
+        editor.update(Name("Meet here".into()));
+        editor.update(Description("x".repeat(150)));
+        editor.update(Icon("📍🍺".into()));
+        editor.update(Expiry("2".into()));
+        editor.update(Expiry("2h".into())); // ignored, not a number of hours
+
+        let waypoint = editor.waypoint(NOW).unwrap();
+        assert_eq!(waypoint.id, 42);
+        assert_eq!(waypoint.latitude_i, Some(515_000_000));
+        assert_eq!(waypoint.name, "Meet here");
+        assert_eq!(waypoint.description.len(), 100);
+        assert_eq!(icon(&waypoint), '🍺');
+        assert_eq!(waypoint.expire as i64, NOW + 2 * 3600);
+        assert_eq!(waypoint_text(&waypoint), "🍺 Meet here");
+    }
+
+    #[test]
+    fn edit_waypoint() {
+        let waypoint = Waypoint {
+            id: 7,
+            latitude_i: Some(1),
+            longitude_i: Some(2),
+            name: "Camp".into(),
+            ..Default::default()
+        };
+        let mut editor = WaypointEditor::edit(&waypoint, NOW);
+        editor.update(Name("Base camp".into()));
+        let edited = editor.waypoint(NOW).unwrap();
+        assert_eq!(edited.id, 7);
+        assert_eq!(edited.longitude_i, Some(2));
+        assert_eq!(edited.name, "Base camp");
+        assert_eq!(edited.expire, 0);
+        assert_eq!(icon(&edited), '📍');
+    }
+
+    #[test]
+    fn long_expiry_limited() {
+        let mut editor = WaypointEditor::new(42, 1, 2);
+        editor.update(Name("Far future".into()));
+        editor.update(Expiry("999999".into()));
+        editor.update(Expiry("99999999999999999999".into())); // ignored, too many digits
+        assert_eq!(editor.waypoint(NOW).unwrap().expire, u32::MAX);
+    }
+
+    #[test]
+    fn expiry() {
+        let mut waypoint = Waypoint::default();
+        assert!(!expired(&waypoint, NOW));
+        assert_eq!(expiry_text(&waypoint, NOW), None);
+
+        waypoint.expire = DELETED;
+        assert!(expired(&waypoint, NOW));
+        assert_eq!(expiry_text(&waypoint, NOW), Some("Deleted".into()));
+
+        waypoint.expire = (NOW - 10) as u32;
+        assert_eq!(expiry_text(&waypoint, NOW), Some("Expired".into()));
+
+        waypoint.expire = (NOW + 10) as u32;
+        assert!(!expired(&waypoint, NOW));
+        assert!(expiry_text(&waypoint, NOW).unwrap().starts_with("Expires "));
+    }
+}